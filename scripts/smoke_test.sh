@@ -0,0 +1,60 @@
+#!/usr/bin/env bash
+# End-to-end smoke test against a local validator holding clones of mainnet state.
+#
+# Boots solana-test-validator with the marginfi program and the given accounts cloned from
+# SOURCE_URL, then runs `inspect` on a marginfi account through the real RPC and
+# deserialization path and checks a health breakdown comes out. Not part of `cargo test`: it
+# needs the solana CLI and network access, run it by hand.
+#
+#   SMOKE_ACCOUNT=<marginfi account> SMOKE_CLONES="<group> <bank> <oracle> ..." scripts/smoke_test.sh
+#
+# SMOKE_CLONES must hold the account's group, banks and their oracle accounts (plus Kamino
+# reserves / stake pool accounts for such banks). Cloned prices keep their publish time, so a
+# bank whose oracle max age is short may fail as stale if cloning takes long.
+set -euo pipefail
+
+MARGINFI_PROGRAM_ID="MFv2hWf31Z9kbCa1snEPYctwafyhdvnV7FZnsebVacA"
+SOURCE_URL="${SOURCE_URL:-https://api.mainnet-beta.solana.com}"
+: "${SMOKE_ACCOUNT:?set SMOKE_ACCOUNT to the marginfi account to evaluate}"
+: "${SMOKE_CLONES:?set SMOKE_CLONES to the group, bank and oracle accounts it needs}"
+
+ledger="$(mktemp -d)"
+validator_pid=""
+cleanup() {
+  if [ -n "$validator_pid" ]; then
+    kill "$validator_pid" 2>/dev/null || true
+    wait "$validator_pid" 2>/dev/null || true
+  fi
+  rm -rf "$ledger"
+}
+trap cleanup EXIT
+
+clone_args=(--clone-upgradeable-program "$MARGINFI_PROGRAM_ID" --clone "$SMOKE_ACCOUNT")
+for pubkey in $SMOKE_CLONES; do
+  clone_args+=(--clone "$pubkey")
+done
+
+echo "Starting validator with ${#clone_args[@]} clone arguments from $SOURCE_URL"
+solana-test-validator --reset --quiet --ledger "$ledger" --url "$SOURCE_URL" "${clone_args[@]}" &
+validator_pid=$!
+
+for _ in $(seq 1 60); do
+  if solana --url http://127.0.0.1:8899 cluster-version >/dev/null 2>&1; then
+    break
+  fi
+  sleep 1
+done
+solana --url http://127.0.0.1:8899 cluster-version >/dev/null
+
+output="$(RPC_URL=http://127.0.0.1:8899 WS_URL=ws://127.0.0.1:8900 READ_ONLY=true cargo run --quiet -- inspect "$SMOKE_ACCOUNT" 2>&1)" || {
+  echo "$output"
+  echo "FAIL: inspect exited with an error"
+  exit 1
+}
+echo "$output"
+
+if ! grep -q "^Buffer: " <<<"$output"; then
+  echo "FAIL: no health breakdown in the output"
+  exit 1
+fi
+echo "PASS"
@@ -1,21 +1,611 @@
+use anchor_client::solana_sdk::commitment_config::CommitmentLevel;
+use anchor_lang::prelude::Pubkey;
 use anyhow::Context;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::consts::MARGINFI_PROGRAM_ID;
+use crate::utils::RetryPolicy;
 
 #[derive(serde::Serialize, serde::Deserialize, Debug, Clone)]
 pub struct Config {
+  /// Named cluster the urls default to, if any
+  pub(crate) cluster: Option<ClusterPreset>,
   pub(crate) url: String,
   pub(crate) ws_url: String,
+  /// Commitment of the log subscription, the simulations and the account reads. Polling reads
+  /// are at least `confirmed`, lower isn't supported there.
+  pub(crate) commitment: CommitmentLevel,
+  /// How program transactions are discovered
+  pub(crate) ingest_mode: IngestMode,
+  /// Seconds between polls when polling, either as `IngestMode::Poll` or as websocket fallback
+  pub(crate) poll_interval_secs: u64,
+  /// Milliseconds an oracle account is reused across account evaluations, 0 fetches every time
+  pub(crate) oracle_cache_ttl_ms: u64,
+  /// Milliseconds a bank is reused across account evaluations, 0 fetches every time. Cached
+  /// banks carry share values as of their fetch, so keep it short.
+  pub(crate) bank_cache_ttl_ms: u64,
+  /// Banks whose oracle quotes in another token rather than usd, each with the bank whose oracle
+  /// prices that token in usd, e.g. a SOL-quoted LST bank and the SOL bank
+  pub(crate) usd_references: Vec<(Pubkey, Pubkey)>,
+  /// Mints whose banks are priced off the pyth push oracle of the given shard rather than the
+  /// stored one, e.g. 0 for pyth-sponsored and 3301 for marginfi-sponsored feeds
+  pub(crate) pyth_shard_overrides: Vec<(Pubkey, u16)>,
+  /// Applied on top of each bank's `oracle_max_confidence`, < 1 tightens, > 1 loosens.
+  pub(crate) oracle_confidence_multiplier: f64,
+  /// Bounds on concurrent account evaluations, see `rpc_latency_target_ms`
+  pub(crate) min_concurrency: usize,
+  pub(crate) max_concurrency: usize,
+  /// Account loads slower than this on average lower the concurrency, loads faster than half of
+  /// it raise it
+  pub(crate) rpc_latency_target_ms: u64,
+  /// Seconds between re-evaluations of the most urgent tracked accounts, 0 disables sweeping
+  pub(crate) sweep_interval_secs: u64,
+  /// Number of tracked accounts re-evaluated per sweep
+  pub(crate) sweep_limit: usize,
+  /// Seconds between scans evaluating every borrowing account of the watched groups, 0 disables
+  /// scanning. Catches accounts pushed under water by price moves alone, which no event reports.
+  pub(crate) scan_interval_secs: u64,
+  /// Opportunities with a lower estimated profit (in usd) are skipped
+  pub(crate) min_profit_usd: f64,
+  /// Banks acted on whose interest wasn't accrued for longer than this (in seconds) are warned
+  /// about, their share values and so the health may be off
+  pub(crate) interest_staleness_warn_secs: u64,
+  /// Keypair file of the liquidator wallet paying for and signing transactions, required unless
+  /// `read_only` is set
+  pub(crate) keypair_path: Option<PathBuf>,
+  /// Pure risk monitor: without a `keypair_path` the payer is an ephemeral keypair, and building
+  /// or sending a liquidation errors. Unlike a dry run, which still builds transactions to simulate them,
+  /// nothing transaction-shaped is produced.
+  pub(crate) read_only: bool,
+  /// Most compute units a liquidation transaction may request, its limit is sized from its
+  /// simulation
+  pub(crate) compute_unit_limit: u32,
+  /// Priority fee of sent transactions, in micro-lamports per compute unit
+  pub(crate) compute_unit_price_micro_lamports: u64,
+  /// Marginfi account of the payer that liquidations are made from
+  pub(crate) liquidator_account: Option<Pubkey>,
+  /// Jupiter swap API used to sell seized collateral, unset leaves the collateral unsold
+  pub(crate) jupiter_api_url: Option<String>,
+  /// Slippage tolerated when selling seized collateral, in basis points
+  pub(crate) unwind_slippage_bps: u16,
+  /// Programs whose logs are subscribed to, one subscription each
+  pub(crate) program_ids: Vec<Pubkey>,
+  /// Marginfi groups to watch, empty watches every group
+  pub(crate) groups: Vec<Pubkey>,
+  /// Only these banks are liquidation targets, empty allows every bank
+  pub(crate) bank_allowlist: Vec<Pubkey>,
+  /// These banks are never liquidation targets. Either list may be set, not both.
+  pub(crate) bank_denylist: Vec<Pubkey>,
+  pub(crate) output_format: OutputFormat,
+  /// Least severe log events written to stderr
+  pub(crate) log_level: LogLevel,
+  /// Prices health is computed with when deciding whether to liquidate
+  pub(crate) valuation: ValuationPreset,
+  /// Price oracles wider than their bank's max confidence anyway, logging it, instead of failing
+  /// the evaluation of every account using them
+  pub(crate) lenient_confidence: bool,
+  /// Event balance changes worth less than this (in usd) skip re-evaluating an account that stays
+  /// healthy regardless, 0 always re-evaluates
+  pub(crate) immaterial_change_usd: f64,
+  /// Evaluated accounts with a health factor at or above this are summarized in one line instead
+  /// of printed in full, unset prints every account. At least 1, so liquidatable accounts always
+  /// show.
+  pub(crate) health_alert_threshold: Option<f64>,
+  /// Milliseconds after a check of an account during which further triggers for it are
+  /// skipped, 0 checks on every trigger
+  pub(crate) recheck_cooldown_ms: u64,
+  /// Consecutive evaluations an account must be found liquidatable before acting on it, filters
+  /// out accounts that self-repair and blips from inconsistent reads
+  pub(crate) liquidation_confirmations: u32,
+  /// Seconds further actions on an account are suppressed after the bot acted on a liquidation
+  /// opportunity of it, unless it turns healthy earlier
+  pub(crate) liquidation_cooldown_secs: u64,
+  /// Seconds without a notification or successful RPC read after which the process exits with a
+  /// non-zero code for its supervisor to restart it, 0 disables the watchdog. Should comfortably
+  /// exceed quiet periods of the cluster.
+  pub(crate) watchdog_timeout_secs: u64,
+  /// Milliseconds before the first websocket reconnect attempt, doubled on each further attempt
+  pub(crate) reconnect_backoff_initial_ms: u64,
+  /// Upper bound in milliseconds of the delay between websocket reconnect attempts
+  pub(crate) reconnect_backoff_max_ms: u64,
+  /// Attempts at loading an account when RPC requests fail transiently, 1 never retries
+  pub(crate) rpc_retry_attempts: u32,
+  /// Milliseconds before the first retry of a failed RPC request, doubled on each further retry
+  pub(crate) rpc_retry_backoff_ms: u64,
+  /// Tracked accounts are saved here periodically and on shutdown, and resumed on startup
+  pub(crate) watch_list_path: Option<PathBuf>,
+  /// Seconds between periodic saves of the watch list
+  pub(crate) watch_list_save_secs: u64,
+  /// Address the Prometheus metrics are served on, unset disables the metrics server
+  pub(crate) metrics_addr: Option<SocketAddr>,
+  /// Accounts found liquidatable are appended here as JSON lines, for offline analysis
+  pub(crate) underwater_log_path: Option<PathBuf>,
+  /// Log notifications are appended here as JSON lines, for later replay
+  pub(crate) capture_path: Option<PathBuf>,
+  /// Replay a capture file instead of subscribing to the websocket
+  pub(crate) replay_path: Option<PathBuf>,
+}
+
+/// How evaluated accounts are printed
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+  /// Free-form, human readable
+  #[default]
+  Human,
+  /// One JSON object per line
+  Json,
+}
+
+/// Verbosity of the stderr log
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogLevel {
+  Error,
+  Warn,
+  #[default]
+  Info,
+  Debug,
+  Trace,
+}
+
+impl From<LogLevel> for tracing::Level {
+  fn from(level: LogLevel) -> Self {
+    match level {
+      LogLevel::Error => tracing::Level::ERROR,
+      LogLevel::Warn => tracing::Level::WARN,
+      LogLevel::Info => tracing::Level::INFO,
+      LogLevel::Debug => tracing::Level::DEBUG,
+      LogLevel::Trace => tracing::Level::TRACE,
+    }
+  }
+}
+
+/// Named `ValuationPolicy` health is computed with
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum ValuationPreset {
+  /// See `ValuationPolicy::CONSERVATIVE`
+  Conservative,
+  /// See `ValuationPolicy::AGGRESSIVE`
+  Aggressive,
+  /// See `ValuationPolicy::PROTOCOL`
+  #[default]
+  Protocol,
+}
+
+/// Well-known clusters, expanding to their canonical endpoints
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClusterPreset {
+  MainnetBeta,
+  Devnet,
+  Localnet,
+}
+
+impl ClusterPreset {
+  pub fn url(&self) -> &'static str {
+    match self {
+      Self::MainnetBeta => "https://api.mainnet-beta.solana.com",
+      Self::Devnet => "https://api.devnet.solana.com",
+      Self::Localnet => "http://127.0.0.1:8899",
+    }
+  }
+
+  pub fn ws_url(&self) -> &'static str {
+    match self {
+      Self::MainnetBeta => "wss://api.mainnet-beta.solana.com",
+      Self::Devnet => "wss://api.devnet.solana.com",
+      Self::Localnet => "ws://127.0.0.1:8900",
+    }
+  }
+}
+
+impl std::str::FromStr for ClusterPreset {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "mainnet-beta" | "mainnet" => Ok(Self::MainnetBeta),
+      "devnet" => Ok(Self::Devnet),
+      "localnet" => Ok(Self::Localnet),
+      other => Err(format!("unknown cluster \"{}\", expected \"mainnet-beta\", \"devnet\" or \"localnet\"", other)),
+    }
+  }
+}
+
+/// Source of program transactions
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum IngestMode {
+  /// `logsSubscribe` over the websocket, polling only while it's unavailable
+  #[default]
+  Websocket,
+  /// `getSignaturesForAddress` polling only
+  Poll,
+}
+
+impl std::str::FromStr for IngestMode {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "websocket" => Ok(Self::Websocket),
+      "poll" => Ok(Self::Poll),
+      other => Err(format!("unknown ingest mode \"{}\", expected \"websocket\" or \"poll\"", other)),
+    }
+  }
+}
+
+impl std::str::FromStr for ValuationPreset {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "conservative" => Ok(Self::Conservative),
+      "aggressive" => Ok(Self::Aggressive),
+      "protocol" => Ok(Self::Protocol),
+      other => Err(format!("unknown valuation \"{}\", expected \"conservative\", \"aggressive\" or \"protocol\"", other)),
+    }
+  }
+}
+
+impl std::str::FromStr for OutputFormat {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "human" => Ok(Self::Human),
+      "json" => Ok(Self::Json),
+      other => Err(format!("unknown output format \"{}\", expected \"human\" or \"json\"", other)),
+    }
+  }
+}
+
+impl std::str::FromStr for LogLevel {
+  type Err = String;
+
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    match s.to_ascii_lowercase().as_str() {
+      "error" => Ok(Self::Error),
+      "warn" => Ok(Self::Warn),
+      "info" => Ok(Self::Info),
+      "debug" => Ok(Self::Debug),
+      "trace" => Ok(Self::Trace),
+      other => Err(format!("unknown log level \"{}\", expected \"error\", \"warn\", \"info\", \"debug\" or \"trace\"", other)),
+    }
+  }
 }
 
 impl Config {
+  /// Whether accounts of `group` should be evaluated
+  pub fn watches_group(&self, group: &Pubkey) -> bool {
+    self.groups.is_empty() || self.groups.contains(group)
+  }
+
+  /// Whether a liquidation may seize from or repay to `bank`. Filtered banks still count towards
+  /// an account's health.
+  pub fn allows_bank(&self, bank: &Pubkey) -> bool {
+    if !self.bank_allowlist.is_empty() {
+      return self.bank_allowlist.contains(bank);
+    }
+    !self.bank_denylist.contains(bank)
+  }
+
+  /// How account loads retry transient RPC failures
+  pub fn rpc_retry_policy(&self) -> RetryPolicy {
+    RetryPolicy {
+      max_attempts: self.rpc_retry_attempts,
+      initial_backoff: Duration::from_millis(self.rpc_retry_backoff_ms),
+    }
+  }
+
   pub async fn open() -> anyhow::Result<Config> {
     dotenvy::dotenv().context("failed to load .env file")?;
-    let url = std::env::var("RPC_URL").context("\"RPC_URL\" is required")?;
-    let ws_url = std::env::var("WS_URL").context("\"WS_URL\" is required")?;
+    let cluster = std::env::var("CLUSTER")
+      .ok()
+      .map(|cluster| cluster.parse::<ClusterPreset>())
+      .transpose()
+      .map_err(|e| anyhow::anyhow!("\"CLUSTER\" is invalid: {}", e))?;
+    // Explicit urls override the cluster's canonical ones
+    let url = match (std::env::var("RPC_URL"), cluster) {
+      (Ok(url), _) => url,
+      (Err(_), Some(cluster)) => cluster.url().to_string(),
+      (Err(_), None) => anyhow::bail!("\"RPC_URL\" is required unless \"CLUSTER\" is set"),
+    };
+    let ws_url = match (std::env::var("WS_URL"), cluster) {
+      (Ok(ws_url), _) => ws_url,
+      (Err(_), Some(cluster)) => cluster.ws_url().to_string(),
+      (Err(_), None) => anyhow::bail!("\"WS_URL\" is required unless \"CLUSTER\" is set"),
+    };
+    let commitment = env_var_or("COMMITMENT", CommitmentLevel::Confirmed)?;
+    let ingest_mode = env_var_or("INGEST_MODE", IngestMode::Websocket)?;
+    let poll_interval_secs = env_var_or("POLL_INTERVAL_SECS", 5)?;
+    let oracle_cache_ttl_ms = env_var_or("ORACLE_CACHE_TTL_MS", 0)?;
+    let bank_cache_ttl_ms = env_var_or("BANK_CACHE_TTL_MS", 0)?;
+    let usd_references = env_pubkey_pairs("USD_REFERENCES")?;
+    let pyth_shard_overrides = env_pubkey_pairs("PYTH_SHARD_OVERRIDES")?;
+    let oracle_confidence_multiplier = env_var_or("ORACLE_CONFIDENCE_MULTIPLIER", 1.0_f64)?;
+    if !oracle_confidence_multiplier.is_finite() || oracle_confidence_multiplier <= 0.0 {
+      anyhow::bail!("\"ORACLE_CONFIDENCE_MULTIPLIER\" must be a positive number");
+    }
+    let min_concurrency = env_var_or("MIN_CONCURRENCY", 1_usize)?;
+    let max_concurrency = env_var_or("MAX_CONCURRENCY", 8_usize)?;
+    if min_concurrency == 0 || min_concurrency > max_concurrency {
+      anyhow::bail!("\"MIN_CONCURRENCY\" must be at least 1 and at most \"MAX_CONCURRENCY\"");
+    }
+    let rpc_latency_target_ms = env_var_or("RPC_LATENCY_TARGET_MS", 500)?;
+    let sweep_interval_secs = env_var_or("SWEEP_INTERVAL_SECS", 0)?;
+    let sweep_limit = env_var_or("SWEEP_LIMIT", 10)?;
+    let scan_interval_secs = env_var_or("SCAN_INTERVAL_SECS", 0)?;
+    let min_profit_usd = env_var_or("MIN_PROFIT_USD", 0.0_f64)?;
+    if !min_profit_usd.is_finite() {
+      anyhow::bail!("\"MIN_PROFIT_USD\" must be a finite number");
+    }
+    let interest_staleness_warn_secs = env_var_or("INTEREST_STALENESS_WARN_SECS", 3_600)?;
+    let read_only = env_var_or("READ_ONLY", false)?;
+    let keypair_path = std::env::var("KEYPAIR_PATH").ok().map(PathBuf::from);
+    if keypair_path.is_none() && !read_only {
+      anyhow::bail!("\"KEYPAIR_PATH\" is required unless \"READ_ONLY\" is set");
+    }
+    let compute_unit_limit = env_var_or("COMPUTE_UNIT_LIMIT", 400_000_u32)?;
+    if compute_unit_limit == 0 || compute_unit_limit > 1_400_000 {
+      anyhow::bail!("\"COMPUTE_UNIT_LIMIT\" must be between 1 and 1400000");
+    }
+    let compute_unit_price_micro_lamports = env_var_or("COMPUTE_UNIT_PRICE_MICRO_LAMPORTS", 0)?;
+    let liquidator_account = std::env::var("LIQUIDATOR_ACCOUNT")
+      .ok()
+      .filter(|value| !value.is_empty())
+      .map(|value| value.parse::<Pubkey>())
+      .transpose()
+      .map_err(|e| anyhow::anyhow!("\"LIQUIDATOR_ACCOUNT\" is invalid: {}", e))?;
+    let jupiter_api_url = std::env::var("JUPITER_API_URL").ok().filter(|url| !url.is_empty());
+    let unwind_slippage_bps = env_var_or("UNWIND_SLIPPAGE_BPS", 50_u16)?;
+    if unwind_slippage_bps > 10_000 {
+      anyhow::bail!("\"UNWIND_SLIPPAGE_BPS\" must be at most 10000");
+    }
+    let mut program_ids = env_pubkeys("PROGRAM_IDS")?;
+    if program_ids.is_empty() {
+      program_ids.push(MARGINFI_PROGRAM_ID);
+    }
+    let groups = env_pubkeys("MARGINFI_GROUPS")?;
+    let bank_allowlist = env_pubkeys("BANK_ALLOWLIST")?;
+    let bank_denylist = env_pubkeys("BANK_DENYLIST")?;
+    if !bank_allowlist.is_empty() && !bank_denylist.is_empty() {
+      anyhow::bail!("\"BANK_ALLOWLIST\" and \"BANK_DENYLIST\" can't both be set");
+    }
+    let output_format = env_var_or("OUTPUT_FORMAT", OutputFormat::Human)?;
+    let log_level = env_var_or("LOG_LEVEL", LogLevel::Info)?;
+    let valuation = env_var_or("VALUATION", ValuationPreset::Protocol)?;
+    let lenient_confidence = env_var_or("LENIENT_CONFIDENCE", false)?;
+    let immaterial_change_usd = env_var_or("IMMATERIAL_CHANGE_USD", 0.0_f64)?;
+    if !immaterial_change_usd.is_finite() {
+      anyhow::bail!("\"IMMATERIAL_CHANGE_USD\" must be a finite number");
+    }
+    let health_alert_threshold = std::env::var("HEALTH_ALERT_THRESHOLD")
+      .ok()
+      .filter(|value| !value.is_empty())
+      .map(|value| value.parse::<f64>())
+      .transpose()
+      .map_err(|e| anyhow::anyhow!("\"HEALTH_ALERT_THRESHOLD\" is invalid: {}", e))?;
+    if health_alert_threshold.is_some_and(|threshold| !threshold.is_finite() || threshold < 1.0) {
+      anyhow::bail!("\"HEALTH_ALERT_THRESHOLD\" must be a finite number of at least 1");
+    }
+    let recheck_cooldown_ms = env_var_or("RECHECK_COOLDOWN_MS", 2_000)?;
+    let liquidation_confirmations = env_var_or("LIQUIDATION_CONFIRMATIONS", 1_u32)?;
+    if liquidation_confirmations == 0 {
+      anyhow::bail!("\"LIQUIDATION_CONFIRMATIONS\" must be at least 1");
+    }
+    let liquidation_cooldown_secs = env_var_or("LIQUIDATION_COOLDOWN_SECS", 30)?;
+    let watchdog_timeout_secs = env_var_or("WATCHDOG_TIMEOUT_SECS", 0)?;
+    let reconnect_backoff_initial_ms = env_var_or("RECONNECT_BACKOFF_INITIAL_MS", 500)?;
+    let reconnect_backoff_max_ms = env_var_or("RECONNECT_BACKOFF_MAX_MS", 30_000)?;
+    if reconnect_backoff_max_ms < reconnect_backoff_initial_ms {
+      anyhow::bail!("\"RECONNECT_BACKOFF_MAX_MS\" must be at least \"RECONNECT_BACKOFF_INITIAL_MS\"");
+    }
+    let rpc_retry_attempts = env_var_or("RPC_RETRY_ATTEMPTS", 3_u32)?;
+    if rpc_retry_attempts == 0 {
+      anyhow::bail!("\"RPC_RETRY_ATTEMPTS\" must be at least 1");
+    }
+    let rpc_retry_backoff_ms = env_var_or("RPC_RETRY_BACKOFF_MS", 200)?;
+    let watch_list_path = std::env::var("WATCH_LIST_PATH").ok().map(PathBuf::from);
+    let watch_list_save_secs = env_var_or("WATCH_LIST_SAVE_SECS", 60)?;
+    let metrics_addr = std::env::var("METRICS_ADDR")
+      .ok()
+      .filter(|value| !value.is_empty())
+      .map(|value| value.parse::<SocketAddr>())
+      .transpose()
+      .map_err(|e| anyhow::anyhow!("\"METRICS_ADDR\" is invalid: {}", e))?;
+    let underwater_log_path = std::env::var("UNDERWATER_LOG_PATH").ok().map(PathBuf::from);
+    let capture_path = std::env::var("CAPTURE_PATH").ok().map(PathBuf::from);
+    let replay_path = std::env::var("REPLAY_PATH").ok().map(PathBuf::from);
     let config = Config {
+      cluster,
       url,
       ws_url,
+      commitment,
+      ingest_mode,
+      poll_interval_secs,
+      oracle_cache_ttl_ms,
+      bank_cache_ttl_ms,
+      usd_references,
+      pyth_shard_overrides,
+      oracle_confidence_multiplier,
+      min_concurrency,
+      max_concurrency,
+      rpc_latency_target_ms,
+      sweep_interval_secs,
+      sweep_limit,
+      scan_interval_secs,
+      min_profit_usd,
+      interest_staleness_warn_secs,
+      keypair_path,
+      read_only,
+      compute_unit_limit,
+      compute_unit_price_micro_lamports,
+      liquidator_account,
+      jupiter_api_url,
+      unwind_slippage_bps,
+      program_ids,
+      groups,
+      bank_allowlist,
+      bank_denylist,
+      output_format,
+      log_level,
+      valuation,
+      lenient_confidence,
+      immaterial_change_usd,
+      health_alert_threshold,
+      recheck_cooldown_ms,
+      liquidation_confirmations,
+      liquidation_cooldown_secs,
+      watchdog_timeout_secs,
+      reconnect_backoff_initial_ms,
+      reconnect_backoff_max_ms,
+      rpc_retry_attempts,
+      rpc_retry_backoff_ms,
+      watch_list_path,
+      watch_list_save_secs,
+      metrics_addr,
+      underwater_log_path,
+      capture_path,
+      replay_path,
     };
 
     Ok(config)
   }
-}
\ No newline at end of file
+
+  /// `open`'s defaults, for a read-only bot reading from `url`
+  #[cfg(test)]
+  pub(crate) fn for_tests(url: &str) -> Config {
+    Config {
+      cluster: None,
+      url: url.to_string(),
+      ws_url: url.replace("http", "ws"),
+      commitment: CommitmentLevel::Confirmed,
+      ingest_mode: IngestMode::Websocket,
+      poll_interval_secs: 5,
+      oracle_cache_ttl_ms: 0,
+      bank_cache_ttl_ms: 0,
+      usd_references: Vec::new(),
+      pyth_shard_overrides: Vec::new(),
+      oracle_confidence_multiplier: 1.0,
+      min_concurrency: 1,
+      max_concurrency: 8,
+      rpc_latency_target_ms: 500,
+      sweep_interval_secs: 0,
+      sweep_limit: 10,
+      scan_interval_secs: 0,
+      min_profit_usd: 0.0,
+      interest_staleness_warn_secs: 3_600,
+      keypair_path: None,
+      read_only: true,
+      compute_unit_limit: 400_000,
+      compute_unit_price_micro_lamports: 0,
+      liquidator_account: None,
+      jupiter_api_url: None,
+      unwind_slippage_bps: 50,
+      program_ids: vec![MARGINFI_PROGRAM_ID],
+      groups: Vec::new(),
+      bank_allowlist: Vec::new(),
+      bank_denylist: Vec::new(),
+      output_format: OutputFormat::Human,
+      log_level: LogLevel::Info,
+      valuation: ValuationPreset::Protocol,
+      lenient_confidence: false,
+      immaterial_change_usd: 0.0,
+      health_alert_threshold: None,
+      recheck_cooldown_ms: 2_000,
+      liquidation_confirmations: 1,
+      liquidation_cooldown_secs: 30,
+      watchdog_timeout_secs: 0,
+      reconnect_backoff_initial_ms: 500,
+      reconnect_backoff_max_ms: 30_000,
+      rpc_retry_attempts: 3,
+      rpc_retry_backoff_ms: 200,
+      watch_list_path: None,
+      watch_list_save_secs: 60,
+      metrics_addr: None,
+      underwater_log_path: None,
+      capture_path: None,
+      replay_path: None,
+    }
+  }
+}
+
+/// Parses a comma separated list of pubkeys, missing or empty yields an empty list
+fn env_pubkeys(key: &str) -> anyhow::Result<Vec<Pubkey>> {
+  let Ok(value) = std::env::var(key) else {
+    return Ok(Vec::new());
+  };
+
+  value
+    .split(',')
+    .map(str::trim)
+    .filter(|s| !s.is_empty())
+    .map(|s| {
+      s.parse::<Pubkey>()
+        .map_err(|e| anyhow::anyhow!("\"{}\" contains an invalid pubkey \"{}\": {}", key, s, e))
+    })
+    .collect()
+}
+
+/// Comma separated `<pubkey>:<value>` pairs
+fn env_pubkey_pairs<T>(key: &str) -> anyhow::Result<Vec<(Pubkey, T)>>
+where
+  T: std::str::FromStr,
+  T::Err: std::fmt::Display,
+{
+  let Ok(value) = std::env::var(key) else {
+    return Ok(Vec::new());
+  };
+
+  value
+    .split(',')
+    .map(str::trim)
+    .filter(|s| !s.is_empty())
+    .map(|s| {
+      let (pubkey, value) = s
+        .split_once(':')
+        .ok_or_else(|| anyhow::anyhow!("\"{}\" contains \"{}\", expected \"<pubkey>:<value>\"", key, s))?;
+      let pubkey = pubkey.trim().parse::<Pubkey>()
+        .map_err(|e| anyhow::anyhow!("\"{}\" contains an invalid pubkey \"{}\": {}", key, pubkey, e))?;
+      let value = value.trim().parse::<T>()
+        .map_err(|e| anyhow::anyhow!("\"{}\" contains an invalid value \"{}\": {}", key, value, e))?;
+      Ok((pubkey, value))
+    })
+    .collect()
+}
+
+fn env_var_or<T>(key: &str, default: T) -> anyhow::Result<T>
+where
+  T: std::str::FromStr,
+  T::Err: std::fmt::Display,
+{
+  match std::env::var(key) {
+    Ok(value) => value
+      .parse::<T>()
+      .map_err(|e| anyhow::anyhow!("\"{}\" is invalid: {}", key, e)),
+    Err(_) => Ok(default),
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn commitment_parses_each_level() {
+    const KEY: &str = "LIQUIDATION_SEARCHER_TEST_COMMITMENT";
+    let parse = |value: &str| {
+      std::env::set_var(KEY, value);
+      env_var_or(KEY, CommitmentLevel::Confirmed)
+    };
+
+    assert_eq!(parse("processed").unwrap(), CommitmentLevel::Processed);
+    assert_eq!(parse("confirmed").unwrap(), CommitmentLevel::Confirmed);
+    assert_eq!(parse("finalized").unwrap(), CommitmentLevel::Finalized);
+    assert!(parse("final").is_err());
+    std::env::remove_var(KEY);
+    assert_eq!(env_var_or(KEY, CommitmentLevel::Confirmed).unwrap(), CommitmentLevel::Confirmed);
+  }
+}
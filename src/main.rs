@@ -3,21 +3,69 @@ mod consts;
 mod marginfi;
 mod utils;
 
+use anchor_lang::prelude::Pubkey;
 use config::Config;
 
-use crate::marginfi::Marginfi;
+use crate::marginfi::{check_account, Marginfi};
+
+const USAGE: &str = "usage: liquidation_searcher [listen | check <account> | inspect <account>]";
+
+/// What the binary was asked to do, `Listen` without arguments
+enum Command {
+  /// Stream program logs and act on liquidatable accounts
+  Listen,
+  /// Print an account's snapshot and health factor once
+  Check(Pubkey),
+  /// Print why an account is or isn't liquidatable
+  Inspect(Pubkey),
+}
+
+impl Command {
+  fn parse(args: &[String]) -> anyhow::Result<Self> {
+    let parse_account = |account: &String| account.parse::<Pubkey>()
+      .map_err(|e| anyhow::anyhow!("invalid account pubkey \"{}\": {}", account, e));
+
+    match args {
+      [] => Ok(Self::Listen),
+      [command] if command == "listen" => Ok(Self::Listen),
+      [command, account] if command == "check" => Ok(Self::Check(parse_account(account)?)),
+      [command, account] if command == "inspect" => Ok(Self::Inspect(parse_account(account)?)),
+      _ => anyhow::bail!(USAGE),
+    }
+  }
+}
 
 #[tokio::main]
 async fn main() {
   let result: anyhow::Result<()> = async move {
-    let config = Config::open().await?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let command = Command::parse(&args)?;
 
-    let marginfi = Marginfi::new(config.url, config.ws_url).await?;
-    marginfi.listen_for_targets().await?;
+    let config = Config::open().await?;
+    utils::init_logging(config.log_level.into())?;
+    if let Command::Check(account) = command {
+      return check_account(&config, &account).await;
+    }
+    let replay_path = config.replay_path.clone();
+    let marginfi = Marginfi::new(config).await?;
+    if let Command::Inspect(account) = command {
+      return marginfi.inspect(&account).await;
+    }
+    match replay_path {
+      Some(path) => marginfi.replay_from_file(path).await?,
+      None => {
+        marginfi.verify_program().await?;
+        if let Err(err) = marginfi.report_oracle_coverage().await {
+          tracing::warn!(error = %format!("{:#}", err), "oracle coverage report failed");
+        }
+        marginfi.listen_for_targets().await?
+      }
+    }
     
     Ok(())
   }.await;
 
+  // Printed directly, the subscriber may not be installed yet
   if let Err(err) = result {
     eprintln!("Error: {err}");
     
@@ -25,4 +73,36 @@ async fn main() {
         .skip(1)
         .for_each(|cause| eprintln!("caused by:\n  {cause}"));
   }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn parse(args: &[&str]) -> anyhow::Result<Command> {
+    Command::parse(&args.iter().map(|arg| arg.to_string()).collect::<Vec<_>>())
+  }
+
+  #[test]
+  fn listen_is_the_default() {
+    assert!(matches!(parse(&[]).unwrap(), Command::Listen));
+    assert!(matches!(parse(&["listen"]).unwrap(), Command::Listen));
+  }
+
+  #[test]
+  fn check_takes_an_account() {
+    let account = Pubkey::new_unique();
+
+    assert!(matches!(parse(&["check", &account.to_string()]).unwrap(), Command::Check(parsed) if parsed == account));
+  }
+
+  #[test]
+  fn malformed_arguments_print_the_usage() {
+    assert!(parse(&["check", "not-a-pubkey"]).unwrap_err().to_string().contains("invalid account pubkey"));
+    assert_eq!(parse(&["check"]).unwrap_err().to_string(), USAGE);
+    assert_eq!(parse(&["listen", "extra"]).unwrap_err().to_string(), USAGE);
+    assert_eq!(parse(&["liquidate"]).unwrap_err().to_string(), USAGE);
+  }
+}
@@ -0,0 +1,112 @@
+use super::OracleLoadError;
+
+/// What kind of failure ended an operation. Carried inside `anyhow::Error` like any other error,
+/// callers that need to tell failures apart find it with `BotError::of`.
+#[derive(Debug)]
+pub enum BotError {
+  /// An RPC request failed, e.g. timed out or was rate limited
+  Rpc(anyhow::Error),
+  /// Account data didn't decode as the expected type
+  Parse(anyhow::Error),
+  /// A bank's oracle failed to load or price, e.g. because it's stale
+  Oracle(OracleLoadError),
+  /// A transaction couldn't be simulated
+  Simulation(anyhow::Error),
+  /// The websocket couldn't be connected or subscribed to
+  Subscription(anyhow::Error),
+}
+
+impl BotError {
+  /// The `BotError` of `err` or any error it was wrapped in, if any
+  pub fn of(err: &anyhow::Error) -> Option<&BotError> {
+    err.downcast_ref::<BotError>()
+  }
+
+  /// Whether trying again may succeed, as for network failures. Decoding the same data or pricing
+  /// with the same oracle state fails the same way again.
+  pub fn is_transient(&self) -> bool {
+    matches!(self, Self::Rpc(_) | Self::Simulation(_) | Self::Subscription(_))
+  }
+}
+
+impl std::fmt::Display for BotError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    match self {
+      Self::Rpc(err) => write!(f, "rpc request failed: {:#}", err),
+      Self::Parse(err) => write!(f, "{:#}", err),
+      Self::Oracle(err) => write!(f, "{}", err),
+      Self::Simulation(err) => write!(f, "simulation failed: {:#}", err),
+      Self::Subscription(err) => write!(f, "websocket failed: {:#}", err),
+    }
+  }
+}
+
+impl std::error::Error for BotError {}
+
+impl From<OracleLoadError> for BotError {
+  fn from(err: OracleLoadError) -> Self {
+    Self::Oracle(err)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use anchor_lang::prelude::Pubkey;
+  use solana_rpc_client::mock_sender::MocksMap;
+  use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+  use solana_rpc_client_types::request::RpcRequest;
+
+  use super::*;
+  use crate::config::Config;
+  use crate::consts::MARGINFI_PROGRAM_ID;
+  use crate::marginfi::fixtures::{bank, response, rpc_account, ui_account, zero_copy};
+  use crate::marginfi::{Marginfi, MarginfiUserAccount};
+  use crate::utils::{fetch_account, Backoff};
+
+  /// Nothing listens there, so every request fails to connect
+  const UNREACHABLE: &str = "http://127.0.0.1:1";
+
+  #[tokio::test]
+  async fn failed_rpc_request_is_a_transient_rpc_error() {
+    let rpc_client = RpcClient::new_mock_with_mocks_map("fails", MocksMap::default());
+
+    let err = fetch_account(&rpc_client, &Pubkey::new_unique()).await.unwrap_err();
+    assert!(matches!(BotError::of(&err), Some(BotError::Rpc(_))), "{:#}", err);
+    assert!(BotError::of(&err).unwrap().is_transient());
+  }
+
+  #[tokio::test]
+  async fn undecodable_account_is_a_lasting_parse_error() {
+    let account_pk = Pubkey::new_unique();
+    // A bank where a marginfi account was expected
+    let mocks: MocksMap = [
+      (RpcRequest::GetAccountInfo, response(ui_account(&account_pk, &rpc_account(MARGINFI_PROGRAM_ID, zero_copy(&bank()))))),
+    ]
+    .into_iter()
+    .collect();
+    let rpc_client = RpcClient::new_mock_with_mocks_map("fails", mocks);
+
+    let err = MarginfiUserAccount::fetch_account(&rpc_client, &account_pk).await.unwrap_err();
+    assert!(matches!(BotError::of(&err), Some(BotError::Parse(_))), "{:#}", err);
+    assert!(!BotError::of(&err).unwrap().is_transient());
+  }
+
+  #[tokio::test]
+  async fn failed_simulation_is_a_simulation_error() {
+    let marginfi = Marginfi::new(Config::for_tests(UNREACHABLE)).await.unwrap();
+
+    let err = marginfi.simulate_instructions(&[]).await.err().unwrap();
+    assert!(matches!(BotError::of(&err), Some(BotError::Simulation(_))), "{:#}", err);
+  }
+
+  #[tokio::test]
+  async fn unreachable_websocket_is_a_subscription_error() {
+    let marginfi = Marginfi::new(Config::for_tests(UNREACHABLE)).await.unwrap();
+    let mut backoff = Backoff::new(Duration::from_millis(1), Duration::from_millis(1));
+
+    let err = marginfi.connect_pubsub(&mut backoff).await.err().unwrap();
+    assert!(matches!(BotError::of(&err), Some(BotError::Subscription(_))), "{:#}", err);
+  }
+}
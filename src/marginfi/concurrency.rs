@@ -0,0 +1,79 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, SemaphorePermit};
+
+/// Weight of the newest sample in the latency EMA
+const LATENCY_EMA_ALPHA: f64 = 0.2;
+
+/// Bounds concurrent account evaluations, adjusting the bound to an EMA of RPC latency: one
+/// permit less while latency exceeds the target, one more while it's under half the target.
+pub struct AdaptiveConcurrency {
+  semaphore: Arc<Semaphore>,
+  /// Permits taken out of circulation to lower the bound below `max`
+  reserved: Mutex<Vec<OwnedSemaphorePermit>>,
+  min: usize,
+  max: usize,
+  target_latency: Duration,
+  /// Microseconds, 0 before the first sample
+  latency_ema_us: AtomicU64,
+}
+
+impl AdaptiveConcurrency {
+  /// Starts at `max` permits
+  pub fn new(min: usize, max: usize, target_latency: Duration) -> Self {
+    let max = max.max(1);
+    Self {
+      semaphore: Arc::new(Semaphore::new(max)),
+      reserved: Mutex::new(Vec::new()),
+      min: min.clamp(1, max),
+      max,
+      target_latency,
+      latency_ema_us: AtomicU64::new(0),
+    }
+  }
+
+  pub async fn acquire(&self) -> SemaphorePermit<'_> {
+    self.semaphore.acquire().await.expect("semaphore is never closed")
+  }
+
+  /// Folds the latency of an RPC round into the EMA and adjusts the bound
+  pub fn record_latency(&self, latency: Duration) {
+    let sample = latency.as_micros() as f64;
+    let previous = self.latency_ema_us.load(Ordering::Relaxed);
+    let ema = if previous == 0 {
+      sample
+    } else {
+      LATENCY_EMA_ALPHA * sample + (1.0 - LATENCY_EMA_ALPHA) * previous as f64
+    };
+    self.latency_ema_us.store((ema as u64).max(1), Ordering::Relaxed);
+
+    let ema = Duration::from_micros(ema as u64);
+    let mut reserved = self.reserved.lock().unwrap();
+    if ema > self.target_latency && self.max - reserved.len() > self.min {
+      // Only idle permits can be reserved, a busy pool shrinks on a later sample
+      if let Ok(permit) = Arc::clone(&self.semaphore).try_acquire_owned() {
+        reserved.push(permit);
+      }
+    } else if ema < self.target_latency / 2 {
+      reserved.pop();
+    }
+  }
+
+  /// Current bound on concurrent evaluations
+  pub fn limit(&self) -> usize {
+    self.max - self.reserved.lock().unwrap().len()
+  }
+
+  pub fn max(&self) -> usize {
+    self.max
+  }
+
+  pub fn latency_ema(&self) -> Option<Duration> {
+    match self.latency_ema_us.load(Ordering::Relaxed) {
+      0 => None,
+      us => Some(Duration::from_micros(us)),
+    }
+  }
+}
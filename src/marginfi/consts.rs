@@ -36,6 +36,8 @@ pub const DAILY_RESET_INTERVAL: i64 = 24 * 60 * 60; // 24 hours
 pub const ORACLE_MIN_AGE: u16 = 30;
 pub const MAX_PYTH_ORACLE_AGE: u64 = 60;
 pub const MAX_SWB_ORACLE_AGE: u64 = 3 * 60;
+/// Nominal slot duration, used to express an oracle `max_age` (seconds) in slots
+pub const SLOT_DURATION_MS: u64 = 400;
 
 /// Range that contains 95% price data distribution
 ///
@@ -126,6 +128,12 @@ pub const EXP_10_I80F48: [I80F48; MAX_EXP_10_I80F48] = [
     I80F48!(100000000000000000000000), // 10^23
 ];
 
+/// `10^exp` from `EXP_10_I80F48`, or None if `exp` is beyond the table
+#[inline]
+pub fn exp_10_i80f48(exp: usize) -> Option<I80F48> {
+    EXP_10_I80F48.get(exp).copied()
+}
+
 pub const MAX_EXP_10: usize = 21;
 pub const EXP_10: [i128; MAX_EXP_10] = [
     1,                     // 10^0
@@ -196,6 +204,7 @@ pub mod ix_discriminators {
     pub const INIT_LIQUIDATION_RECORD: [u8; 8] = [236, 213, 238, 126, 147, 251, 164, 8];
     pub const START_LIQUIDATION: [u8; 8] = [244, 93, 90, 214, 192, 166, 191, 21];
     pub const END_LIQUIDATION: [u8; 8] = [110, 11, 244, 54, 229, 181, 22, 184];
+    pub const LENDING_ACCOUNT_LIQUIDATE: [u8; 8] = [214, 169, 151, 213, 251, 167, 86, 219];
     pub const LENDING_ACCOUNT_PULSE_HEALTH: [u8; 8] = [186, 52, 117, 97, 34, 74, 39, 253];
     pub const LENDING_ACCOUNT_WITHDRAW: [u8; 8] = [36, 72, 74, 19, 210, 210, 192, 192];
     pub const LENDING_ACCOUNT_REPAY: [u8; 8] = [79, 209, 172, 177, 222, 51, 173, 151];
@@ -206,4 +215,17 @@ pub mod ix_discriminators {
     pub const END_FLASHLOAN: [u8; 8] = [105, 124, 201, 106, 153, 2, 8, 156];
     pub const START_DELEVERAGE: [u8; 8] = [10, 138, 10, 57, 40, 232, 182, 193];
     pub const END_DELEVERAGE: [u8; 8] = [114, 14, 250, 143, 252, 104, 214, 209];
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn exp_10_i80f48_covers_the_table_and_nothing_beyond() {
+    assert_eq!(exp_10_i80f48(0), Some(I80F48::ONE));
+    assert_eq!(exp_10_i80f48(MAX_EXP_10_I80F48 - 1), Some(I80F48::from_num(10u128.pow(23))));
+    assert_eq!(exp_10_i80f48(MAX_EXP_10_I80F48), None);
+  }
+}
@@ -0,0 +1,40 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anchor_lang::prelude::Pubkey;
+
+/// Coalesces checks of the same account triggered in a burst, e.g. by the several events of one
+/// transaction. A change landing inside the window after a check is only picked up by the next
+/// trigger or sweep, so keep the window short.
+pub struct CheckDebounce {
+  window: Duration,
+  last_checked: Mutex<HashMap<Pubkey, Instant>>,
+}
+
+impl CheckDebounce {
+  pub fn new(window: Duration) -> Self {
+    Self {
+      window,
+      last_checked: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Whether `account` should be checked now, in which case the check is recorded. `None` if so,
+  /// otherwise how long ago the last check started.
+  pub fn begin(&self, account: &Pubkey) -> Option<Duration> {
+    if self.window.is_zero() {
+      return None;
+    }
+    let now = Instant::now();
+    let mut last_checked = self.last_checked.lock().unwrap();
+    if let Some(elapsed) = last_checked.get(account).map(|at| now.duration_since(*at)) {
+      if elapsed < self.window {
+        return Some(elapsed);
+      }
+    }
+    last_checked.retain(|_, at| now.duration_since(*at) < self.window);
+    last_checked.insert(*account, now);
+    None
+  }
+}
@@ -241,6 +241,9 @@ pub enum MarginfiError {
     #[msg("Invalid Kamino obligation: account constraint violated")]
     InvalidKaminoObligation, // 6214
                              // **************END KAMINO ERRORS
+                             // **************BOT ERRORS, never returned by the program
+    #[msg("Switchboard account was written recently but its result is stale")]
+    SwitchboardStaleResult, // 6215
 }
 
 impl From<MarginfiError> for ProgramError {
@@ -411,6 +414,7 @@ impl MarginfiError {
                 | MarginfiError::WrongOracleAccountKeys
                 | MarginfiError::PythPushStalePrice
                 | MarginfiError::SwitchboardStalePrice
+                | MarginfiError::SwitchboardStaleResult
                 | MarginfiError::StakePoolValidationFailed
                 | MarginfiError::InvalidBankAccount
                 | MarginfiError::MissingBankAccount
@@ -0,0 +1,229 @@
+use std::collections::HashMap;
+
+use anchor_lang::prelude::Pubkey;
+use anchor_lang::{AnchorDeserialize, Discriminator};
+use base64::{Engine as _, engine::general_purpose};
+
+use super::events::{
+  AccountEventHeader, LendingAccountBorrowEvent, LendingAccountDepositEvent, LendingAccountLiquidateEvent,
+  LendingAccountRepayEvent, LendingAccountWithdrawEvent,
+};
+use super::tracked::{BalanceChange, BalanceChangeKind};
+
+/// What the bot should do about a decoded event
+#[derive(Clone, Copy, Debug)]
+pub enum EventAction {
+  /// Nothing, e.g. the event was only logged
+  Ignore,
+  /// Re-evaluate `account`, `change` being the balance change the event made, if known
+  Evaluate { account: Pubkey, change: Option<BalanceChange> },
+  /// Another liquidator is acting on `account`
+  Contested { account: Pubkey },
+}
+
+/// Logs a balance change event and asks for its account to be re-evaluated
+fn balance_changed(kind: BalanceChangeKind, ctx: &EventContext, header: &AccountEventHeader, bank: Pubkey, amount: u64) -> EventAction {
+  tracing::info!(
+    signature = ctx.signature,
+    account = %header.marginfi_account,
+    bank = %bank,
+    amount,
+    kind = ?kind,
+    "balance changed"
+  );
+  EventAction::Evaluate {
+    account: header.marginfi_account,
+    change: Some(BalanceChange { bank, amount, kind }),
+  }
+}
+
+/// The notification an event was found in
+pub struct EventContext<'a> {
+  pub signature: &'a str,
+}
+
+type EventHandler = Box<dyn Fn(&[u8], &EventContext) -> anyhow::Result<EventAction>>;
+
+/// Name of event type `T` without its module path, e.g. `LendingAccountDepositEvent`
+fn event_type_name<T>() -> &'static str {
+  let name = std::any::type_name::<T>();
+  name.rsplit("::").next().unwrap_or(name)
+}
+
+/// Event handlers keyed by anchor discriminator, so a `Program data: ` payload is decoded once
+/// and dispatched without trying every event type
+pub struct EventRegistry {
+  handlers: HashMap<&'static [u8], (&'static str, EventHandler)>,
+}
+
+impl EventRegistry {
+  /// A registry handling the events the bot acts on
+  pub fn with_known_events() -> Self {
+    let mut registry = Self { handlers: HashMap::new() };
+    registry.register(|event: LendingAccountDepositEvent, ctx| {
+      balance_changed(BalanceChangeKind::Deposit, ctx, &event.header, event.bank, event.amount)
+    });
+    registry.register(|event: LendingAccountBorrowEvent, ctx| {
+      balance_changed(BalanceChangeKind::Borrow, ctx, &event.header, event.bank, event.amount)
+    });
+    registry.register(|event: LendingAccountRepayEvent, ctx| {
+      balance_changed(BalanceChangeKind::Repay, ctx, &event.header, event.bank, event.amount)
+    });
+    registry.register(|event: LendingAccountWithdrawEvent, ctx| {
+      balance_changed(BalanceChangeKind::Withdraw, ctx, &event.header, event.bank, event.amount)
+    });
+    registry.register(|event: LendingAccountLiquidateEvent, ctx| {
+      tracing::info!(
+        signature = ctx.signature,
+        account = %event.liquidatee_marginfi_account,
+        liquidator = %event.header.marginfi_account,
+        "liquidated by another liquidator"
+      );
+      // Someone else is settling this account, racing them would only burn fees
+      EventAction::Contested { account: event.liquidatee_marginfi_account }
+    });
+    registry
+  }
+
+  /// Handles events of type `T` with `handler`, replacing any handler registered for `T` before
+  pub fn register<T, F>(&mut self, handler: F)
+  where
+    T: AnchorDeserialize + Discriminator,
+    F: Fn(T, &EventContext) -> EventAction + 'static,
+  {
+    let handler: EventHandler = Box::new(move |data, ctx| {
+      let event = T::deserialize(&mut &data[..])?;
+      anyhow::Ok(handler(event, ctx))
+    });
+    self.handlers.insert(T::DISCRIMINATOR, (event_type_name::<T>(), handler));
+  }
+
+  /// Decodes a `Program data: ` payload and runs its handler, returning the event's type name
+  /// with the action. `None` if no handler is registered for its discriminator.
+  pub fn dispatch(&self, data: &str, ctx: &EventContext) -> anyhow::Result<Option<(&'static str, EventAction)>> {
+    let decoded = general_purpose::STANDARD.decode(data)?;
+    let Some((discriminator, event_data)) = decoded.split_at_checked(8) else {
+      anyhow::bail!("event data is {} bytes, too short for a discriminator", decoded.len());
+    };
+    let Some((event_type, handler)) = self.handlers.get(discriminator) else {
+      return anyhow::Ok(None);
+    };
+
+    handler(event_data, ctx).map(|action| Some((*event_type, action)))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use anchor_lang::Event;
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  const BANK: Pubkey = Pubkey::new_from_array([1; 32]);
+  const ACCOUNT: Pubkey = Pubkey::new_from_array([2; 32]);
+
+  fn ctx() -> EventContext<'static> {
+    EventContext { signature: "test" }
+  }
+
+  fn deposit(amount: u64) -> String {
+    let event = LendingAccountDepositEvent {
+      header: AccountEventHeader {
+        signer: None,
+        marginfi_account: ACCOUNT,
+        marginfi_account_authority: Pubkey::default(),
+        marginfi_group: Pubkey::default(),
+      },
+      bank: BANK,
+      mint: Pubkey::default(),
+      amount,
+    };
+    general_purpose::STANDARD.encode(event.data())
+  }
+
+  #[test]
+  fn dispatches_by_discriminator() {
+    let (event_type, action) = EventRegistry::with_known_events().dispatch(&deposit(42), &ctx()).unwrap().unwrap();
+
+    assert_eq!(event_type, "LendingAccountDepositEvent");
+    match action {
+      EventAction::Evaluate { account, change: Some(change) } => {
+        assert_eq!(account, ACCOUNT);
+        assert_eq!(change.kind, BalanceChangeKind::Deposit);
+        assert_eq!(change.amount, 42);
+      }
+      other => panic!("expected an evaluation, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn unknown_discriminator_is_not_handled() {
+    let data = general_purpose::STANDARD.encode([0u8; 16]);
+
+    assert!(EventRegistry::with_known_events().dispatch(&data, &ctx()).unwrap().is_none());
+  }
+
+  #[test]
+  fn payload_shorter_than_a_discriminator_errors() {
+    let data = general_purpose::STANDARD.encode([1u8; 7]);
+
+    assert!(EventRegistry::with_known_events().dispatch(&data, &ctx()).is_err());
+  }
+
+  #[test]
+  fn registering_a_type_again_replaces_its_handler() {
+    let mut registry = EventRegistry::with_known_events();
+    registry.register(|_: LendingAccountDepositEvent, _| EventAction::Ignore);
+
+    let (_, action) = registry.dispatch(&deposit(42), &ctx()).unwrap().unwrap();
+    assert!(matches!(action, EventAction::Ignore));
+  }
+
+  #[test]
+  fn another_liquidators_liquidation_contests_the_liquidatee() {
+    use std::time::Duration;
+
+    use crate::marginfi::events::LiquidationBalances;
+    use crate::marginfi::SeenOpportunities;
+
+    let balances = || LiquidationBalances {
+      liquidatee_asset_balance: 10.0,
+      liquidatee_liability_balance: 5.0,
+      liquidator_asset_balance: 0.0,
+      liquidator_liability_balance: 0.0,
+    };
+    let event = LendingAccountLiquidateEvent {
+      header: AccountEventHeader {
+        signer: None,
+        marginfi_account: Pubkey::new_unique(),
+        marginfi_account_authority: Pubkey::default(),
+        marginfi_group: Pubkey::default(),
+      },
+      liquidatee_marginfi_account: ACCOUNT,
+      liquidatee_marginfi_account_authority: Pubkey::default(),
+      asset_bank: BANK,
+      asset_mint: Pubkey::default(),
+      liability_bank: Pubkey::new_unique(),
+      liability_mint: Pubkey::default(),
+      liquidatee_pre_health: 0.95,
+      liquidatee_post_health: 1.0,
+      pre_balances: balances(),
+      post_balances: balances(),
+    };
+    let data = general_purpose::STANDARD.encode(event.data());
+
+    let (event_type, action) = EventRegistry::with_known_events().dispatch(&data, &ctx()).unwrap().unwrap();
+    assert_eq!(event_type, "LendingAccountLiquidateEvent");
+    let EventAction::Contested { account } = action else {
+      panic!("expected the liquidatee to be contested, got {:?}", action);
+    };
+    assert_eq!(account, ACCOUNT);
+
+    let seen = SeenOpportunities::new(Duration::from_millis(20));
+    seen.mark_contested(account);
+    assert!(seen.contested_since(&ACCOUNT).is_some());
+    std::thread::sleep(Duration::from_millis(30));
+    assert_eq!(seen.contested_since(&ACCOUNT), None);
+  }
+}
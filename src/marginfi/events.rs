@@ -142,6 +142,31 @@ pub struct AccountEventHeader {
     pub marginfi_group: Pubkey,
 }
 
+#[event]
+pub struct LendingAccountDepositEvent {
+    pub header: AccountEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
+#[event]
+pub struct LendingAccountRepayEvent {
+    pub header: AccountEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+    pub close_balance: bool,
+}
+
+#[event]
+pub struct LendingAccountBorrowEvent {
+    pub header: AccountEventHeader,
+    pub bank: Pubkey,
+    pub mint: Pubkey,
+    pub amount: u64,
+}
+
 #[event]
 pub struct LendingAccountWithdrawEvent {
     pub header: AccountEventHeader,
@@ -151,6 +176,30 @@ pub struct LendingAccountWithdrawEvent {
     pub close_balance: bool,
 }
 
+#[derive(AnchorSerialize, AnchorDeserialize)]
+pub struct LiquidationBalances {
+    pub liquidatee_asset_balance: f64,
+    pub liquidatee_liability_balance: f64,
+    pub liquidator_asset_balance: f64,
+    pub liquidator_liability_balance: f64,
+}
+
+/// Emitted by `lending_account_liquidate`, `header.marginfi_account` is the liquidator's account
+#[event]
+pub struct LendingAccountLiquidateEvent {
+    pub header: AccountEventHeader,
+    pub liquidatee_marginfi_account: Pubkey,
+    pub liquidatee_marginfi_account_authority: Pubkey,
+    pub asset_bank: Pubkey,
+    pub asset_mint: Pubkey,
+    pub liability_bank: Pubkey,
+    pub liability_mint: Pubkey,
+    pub liquidatee_pre_health: f64,
+    pub liquidatee_post_health: f64,
+    pub pre_balances: LiquidationBalances,
+    pub post_balances: LiquidationBalances,
+}
+
 #[event]
 pub struct HealthPulseEvent {
   pub account: Pubkey,
@@ -0,0 +1,89 @@
+//! Banks and accounts built in memory, to value and liquidate accounts in tests without an RPC
+
+use anchor_lang::prelude::{Clock, Pubkey};
+use anchor_lang::Discriminator;
+use bytemuck::Zeroable;
+use fixed::types::I80F48;
+use serde_json::{json, Value};
+use solana_account::Account;
+use solana_account_decoder::{encode_ui_account, UiAccountEncoding};
+
+use super::types::{Balance, Bank, BankOperationalState, FixedPriceFeed, MarginfiAccount, OraclePriceFeedAdapter, OracleSetup};
+use super::{BankAccount, MarginfiUserAccount};
+
+/// Group of every fixture bank and account
+pub const GROUP: Pubkey = Pubkey::new_from_array([7; 32]);
+
+/// An operational bank of `GROUP` with 6 decimals and share values of 1, on a fixed oracle.
+/// Assets weigh 0.7 for init and 0.8 for maintenance, liabilities 1.3 and 1.2.
+pub fn bank() -> Bank {
+  let mut bank = Bank::zeroed();
+  bank.group = GROUP;
+  bank.mint_decimals = 6;
+  bank.asset_share_value = I80F48::ONE.into();
+  bank.liability_share_value = I80F48::ONE.into();
+  bank.config.asset_weight_init = I80F48::from_num(0.7).into();
+  bank.config.asset_weight_maint = I80F48::from_num(0.8).into();
+  bank.config.liability_weight_init = I80F48::from_num(1.3).into();
+  bank.config.liability_weight_maint = I80F48::from_num(1.2).into();
+  bank.config.operational_state = BankOperationalState::Operational;
+  bank.config.oracle_setup = OracleSetup::Fixed;
+  bank
+}
+
+/// `bank` at `bank_pk` priced at `price` usd, with `deposited` and `borrowed` in ui units, turned
+/// into shares at the bank's share values
+pub fn bank_account(bank_pk: Pubkey, bank: Bank, price: f64, deposited: f64, borrowed: f64) -> BankAccount {
+  let native = |amount: f64| I80F48::from_num(amount * 10f64.powi(bank.mint_decimals as i32));
+  let mut balance = Balance::zeroed();
+  balance.set_active(true);
+  balance.bank_pk = bank_pk;
+  balance.asset_shares = (native(deposited) / I80F48::from(bank.asset_share_value)).into();
+  balance.liability_shares = (native(borrowed) / I80F48::from(bank.liability_share_value)).into();
+
+  BankAccount {
+    oracle_max_confidence: bank.config.oracle_max_confidence,
+    liquidation_target: true,
+    usd_reference: None,
+    price_feed: OraclePriceFeedAdapter::Fixed(FixedPriceFeed { price: I80F48::from_num(price) }),
+    bank,
+    balance,
+  }
+}
+
+/// An account of `GROUP` holding the balances of `bank_accounts`, in order
+pub fn user_account(bank_accounts: Vec<BankAccount>) -> MarginfiUserAccount {
+  let mut account = MarginfiAccount::zeroed();
+  account.group = GROUP;
+  for (slot, bank_account) in account.lending_account.balances.iter_mut().zip(&bank_accounts) {
+    *slot = bank_account.balance;
+  }
+  MarginfiUserAccount::from_parts(account, bank_accounts, Vec::new())
+}
+
+/// An account holding `data`, owned by `owner`
+pub fn rpc_account(owner: Pubkey, data: Vec<u8>) -> Account {
+  Account { lamports: 1, data, owner, executable: false, rent_epoch: 0 }
+}
+
+/// The account data of a zero-copy account `value`, discriminator first
+pub fn zero_copy<T: bytemuck::Pod + Discriminator>(value: &T) -> Vec<u8> {
+  [T::DISCRIMINATOR, bytemuck::bytes_of(value)].concat()
+}
+
+/// An rpc response holding `value`, as `getAccountInfo` and `getMultipleAccounts` return them,
+/// to be served by a mocked `RpcClient`
+pub fn response(value: Value) -> Value {
+  json!({ "context": { "slot": 1 }, "value": value })
+}
+
+/// `account` at `pubkey`, encoded as in rpc responses
+pub fn ui_account(pubkey: &Pubkey, account: &Account) -> Value {
+  json!(encode_ui_account(pubkey, account, UiAccountEncoding::Base64, None, None))
+}
+
+/// The `getAccountInfo` response for the clock sysvar at `clock`
+pub fn clock_response(clock: &Clock) -> Value {
+  let data = bincode::serialize(clock).unwrap();
+  response(ui_account(&anchor_lang::solana_program::sysvar::clock::ID, &rpc_account(Pubkey::default(), data)))
+}
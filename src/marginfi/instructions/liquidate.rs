@@ -0,0 +1,50 @@
+use anchor_lang::{InstructionData, prelude::*};
+
+use crate::marginfi::consts::ix_discriminators;
+
+/// Legacy liquidation: the liquidator repays part of the liquidatee's liability and receives
+/// `asset_amount` of its collateral at a discount
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct LendingAccountLiquidate {
+  pub asset_amount: u64,
+}
+
+impl Discriminator for LendingAccountLiquidate {
+  const DISCRIMINATOR: &'static [u8] = &ix_discriminators::LENDING_ACCOUNT_LIQUIDATE;
+}
+
+impl InstructionData for LendingAccountLiquidate {
+}
+
+/// Fixed accounts of `lending_account_liquidate`. The remaining accounts follow: the liability
+/// mint for Token-2022 banks, the asset bank's oracles, the liability bank's oracles, then the
+/// liquidator's and the liquidatee's banks each followed by their oracles.
+pub struct LendingAccountLiquidateAccounts {
+    pub group: Pubkey,
+    pub asset_bank: Pubkey,
+    pub liab_bank: Pubkey,
+    pub liquidator_marginfi_account: Pubkey,
+    pub authority: Pubkey,
+    pub liquidatee_marginfi_account: Pubkey,
+    pub bank_liquidity_vault_authority: Pubkey,
+    pub bank_liquidity_vault: Pubkey,
+    pub bank_insurance_vault: Pubkey,
+    pub token_program: Pubkey,
+}
+
+impl ToAccountMetas for LendingAccountLiquidateAccounts {
+  fn to_account_metas(&self, _is_signer: Option<bool>) -> Vec<AccountMeta> {
+    vec![
+      AccountMeta::new_readonly(self.group, false),
+      AccountMeta::new(self.asset_bank, false),
+      AccountMeta::new(self.liab_bank, false),
+      AccountMeta::new(self.liquidator_marginfi_account, false),
+      AccountMeta::new_readonly(self.authority, true),
+      AccountMeta::new(self.liquidatee_marginfi_account, false),
+      AccountMeta::new(self.bank_liquidity_vault_authority, false),
+      AccountMeta::new(self.bank_liquidity_vault, false),
+      AccountMeta::new(self.bank_insurance_vault, false),
+      AccountMeta::new_readonly(self.token_program, false),
+    ]
+  }
+}
@@ -1,3 +1,5 @@
+mod liquidate;
 mod pulse_health;
 
-pub use pulse_health::*;
\ No newline at end of file
+pub use liquidate::*;
+pub use pulse_health::*;
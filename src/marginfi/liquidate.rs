@@ -0,0 +1,309 @@
+use std::collections::HashMap;
+
+use anchor_client::solana_sdk::transaction::Transaction;
+use anchor_lang::prelude::{Pubkey, ToAccountMetas};
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::InstructionData;
+use anyhow::Context;
+
+use crate::consts::MARGINFI_PROGRAM_ID;
+use crate::utils::parse_account;
+
+use super::consts::LIQUIDITY_VAULT_AUTHORITY_SEED;
+use super::transactions::{with_compute_budget, MAX_COMPUTE_UNIT_LIMIT};
+use super::types::{get_oracle_keys_for_bank, Bank, MarginfiAccount};
+use super::{LendingAccountLiquidate, LendingAccountLiquidateAccounts, LiquidationOpportunity, Marginfi, MarginfiError, MarginfiUserAccount};
+
+/// Result of `Marginfi::simulate_liquidate`
+pub struct LiquidationSimulation {
+  pub units_consumed: u64,
+  /// Debug-formatted transaction error, if the liquidation would fail
+  pub err: Option<String>,
+  /// The marginfi error the program failed with, if it logged one
+  pub program_error: Option<MarginfiError>,
+}
+
+/// The marginfi error of the first `AnchorError ... Error Number: <n>.` log line
+fn program_error(logs: &[String]) -> Option<MarginfiError> {
+  logs.iter().find_map(|log| {
+    let (_, rest) = log.split_once("Error Number: ")?;
+    let number = rest.split('.').next()?.trim().parse::<u32>().ok()?;
+    // Numbers below the custom error offset are anchor's own
+    (number >= 6000).then(|| MarginfiError::from(number))
+  })
+}
+
+/// The liquidator's side of a liquidation, fetched by `Marginfi::liquidate_instruction`
+struct Liquidator {
+  /// Marginfi account liquidating, and its authority signing the liquidation
+  pubkey: Pubkey,
+  authority: Pubkey,
+  account: MarginfiAccount,
+  /// Owner of the liability mint, spl-token or token-2022
+  token_program: Pubkey,
+  /// Banks of `liquidator_banks` the liquidatee has no balance in
+  banks: HashMap<Pubkey, Bank>,
+}
+
+/// Banks the liquidator has balances in once the liquidation is done: its current ones, then
+/// both banks of the liquidation if it has none there yet
+fn liquidator_banks(liquidator: &MarginfiAccount, opportunity: &LiquidationOpportunity) -> Vec<Pubkey> {
+  let mut banks: Vec<Pubkey> = liquidator.lending_account
+    .get_active_balances_iter()
+    .map(|balance| balance.bank_pk)
+    .collect();
+  for bank_pk in [opportunity.asset_bank, opportunity.liability_bank] {
+    if !banks.contains(&bank_pk) {
+      banks.push(bank_pk);
+    }
+  }
+  banks
+}
+
+/// `lending_account_liquidate` of `opportunity` by `liquidator`. Its remaining accounts are the
+/// oracles of both banks, then each bank of the liquidator and of the liquidatee followed by its
+/// oracles.
+fn build_liquidate_instruction(opportunity: &LiquidationOpportunity, liquidatee: &MarginfiUserAccount, liquidator: &Liquidator) -> anyhow::Result<Instruction> {
+  let bank_of = |bank_pk: Pubkey| {
+    liquidatee
+      .bank_accounts()
+      .iter()
+      .find(|bank_account| bank_account.balance.bank_pk == bank_pk)
+      .map(|bank_account| bank_account.bank)
+      .or_else(|| liquidator.banks.get(&bank_pk).copied())
+      .with_context(|| format!("bank {} failed to load", bank_pk))
+  };
+  let asset_bank = bank_of(opportunity.asset_bank)?;
+  let liab_bank = bank_of(opportunity.liability_bank)?;
+  let asset_amount = opportunity.asset_seized_native
+    .floor()
+    .checked_to_num::<u64>()
+    .context("seized amount does not fit in u64")?;
+
+  let (bank_liquidity_vault_authority, _) = Pubkey::find_program_address(
+    &[LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(), opportunity.liability_bank.as_ref()],
+    &MARGINFI_PROGRAM_ID,
+  );
+  let mut metas = LendingAccountLiquidateAccounts {
+    group: liquidatee.account().group,
+    asset_bank: opportunity.asset_bank,
+    liab_bank: opportunity.liability_bank,
+    liquidator_marginfi_account: liquidator.pubkey,
+    authority: liquidator.authority,
+    liquidatee_marginfi_account: opportunity.account,
+    bank_liquidity_vault_authority,
+    bank_liquidity_vault: liab_bank.liquidity_vault,
+    bank_insurance_vault: liab_bank.insurance_vault,
+    token_program: liquidator.token_program,
+  }.to_account_metas(None);
+
+  if liquidator.token_program == anchor_spl::token_2022::ID {
+    metas.push(AccountMeta::new_readonly(liab_bank.mint, false));
+  }
+  let readonly = |keys: Vec<Pubkey>| keys.into_iter().map(|key| AccountMeta::new_readonly(key, false));
+  metas.extend(readonly(get_oracle_keys_for_bank(&asset_bank)?));
+  metas.extend(readonly(get_oracle_keys_for_bank(&liab_bank)?));
+
+  let liquidatee_banks = liquidatee.bank_accounts().iter().map(|bank_account| bank_account.balance.bank_pk);
+  for bank_pk in liquidator_banks(&liquidator.account, opportunity).into_iter().chain(liquidatee_banks) {
+    metas.push(AccountMeta::new_readonly(bank_pk, false));
+    metas.extend(readonly(get_oracle_keys_for_bank(&bank_of(bank_pk)?)?));
+  }
+
+  anyhow::Ok(Instruction {
+    program_id: MARGINFI_PROGRAM_ID,
+    accounts: metas,
+    data: LendingAccountLiquidate { asset_amount }.data(),
+  })
+}
+
+impl Marginfi {
+  /// Unsigned transaction of the liquidation `ix`, paid by the payer, requesting `cu_limit`
+  /// compute units at the configured price, see `sized_compute_unit_limit`
+  pub fn build_liquidate_tx(&self, ix: Instruction, cu_limit: u32) -> Transaction {
+    let instructions = with_compute_budget(
+      vec![ix],
+      cu_limit,
+      self.config.compute_unit_price_micro_lamports,
+    );
+    Transaction::new_with_payer(&instructions, Some(&self.program.payer()))
+  }
+
+  /// Simulates the liquidation `ix` without signing it, to confirm it would succeed and measure
+  /// its compute units before paying for it
+  pub async fn simulate_liquidate(&self, ix: Instruction) -> anyhow::Result<LiquidationSimulation> {
+    let instructions = with_compute_budget(vec![ix], MAX_COMPUTE_UNIT_LIMIT, 0);
+    let outcome = self.simulate_instructions(&instructions).await?;
+
+    anyhow::Ok(LiquidationSimulation {
+      units_consumed: outcome.units_consumed,
+      program_error: program_error(&outcome.logs),
+      err: outcome.err,
+    })
+  }
+
+  /// `lending_account_liquidate` of `opportunity` from the configured liquidator account, seizing
+  /// `asset_seized_native` of the opportunity. Fetches the liquidator account, the liability mint
+  /// and the liquidator's banks the liquidatee has no balance in.
+  pub async fn liquidate_instruction(&self, opportunity: &LiquidationOpportunity, liquidatee: &MarginfiUserAccount) -> anyhow::Result<Instruction> {
+    let liquidator_pubkey = self.config.liquidator_account
+      .context("\"LIQUIDATOR_ACCOUNT\" is required to build liquidations")?;
+    let liab_mint = liquidatee
+      .bank_accounts()
+      .iter()
+      .find(|bank_account| bank_account.balance.bank_pk == opportunity.liability_bank)
+      .map(|bank_account| bank_account.bank.mint)
+      .with_context(|| format!("bank {} is not part of the account", opportunity.liability_bank))?;
+
+    let liquidator_data = self.rpc_client.get_account_data(&liquidator_pubkey).await
+      .with_context(|| format!("failed to fetch liquidator account {}", liquidator_pubkey))?;
+    let liquidator = parse_account::<MarginfiAccount>(&liquidator_data)
+      .map_err(|e| anyhow::anyhow!("invalid liquidator account data: {}", e))?;
+    let token_program = self.rpc_client.get_account(&liab_mint).await
+      .with_context(|| format!("failed to fetch mint {}", liab_mint))?
+      .owner;
+
+    let unknown: Vec<Pubkey> = liquidator_banks(&liquidator, opportunity)
+      .into_iter()
+      .filter(|bank_pk| liquidatee.bank_accounts().iter().all(|bank_account| bank_account.balance.bank_pk != *bank_pk))
+      .collect();
+    let mut banks = HashMap::new();
+    if !unknown.is_empty() {
+      let fetched = self.rpc_client.get_multiple_accounts(&unknown).await?;
+      for (bank_pk, account) in unknown.iter().zip(fetched) {
+        let account = account.with_context(|| format!("bank {} not found", bank_pk))?;
+        let bank = parse_account::<Bank>(&account.data)
+          .map_err(|e| anyhow::anyhow!("invalid bank data: {}", e))?;
+        banks.insert(*bank_pk, bank);
+      }
+    }
+
+    build_liquidate_instruction(opportunity, liquidatee, &Liquidator {
+      pubkey: liquidator_pubkey,
+      authority: self.program.payer(),
+      account: liquidator,
+      token_program,
+      banks,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use anchor_lang::solana_program::hash::hash;
+  use bytemuck::Zeroable;
+  use fixed::types::I80F48;
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::marginfi::fixtures::{bank, bank_account, user_account, GROUP};
+  use crate::marginfi::types::OracleSetup;
+
+  const ASSET_BANK: Pubkey = Pubkey::new_from_array([1; 32]);
+  const LIAB_BANK: Pubkey = Pubkey::new_from_array([2; 32]);
+  /// Bank the liquidator already has a balance in
+  const LIQUIDATOR_BANK: Pubkey = Pubkey::new_from_array([3; 32]);
+  const LIQUIDATEE: Pubkey = Pubkey::new_from_array([4; 32]);
+  const LIQUIDATOR: Pubkey = Pubkey::new_from_array([5; 32]);
+  const AUTHORITY: Pubkey = Pubkey::new_from_array([6; 32]);
+
+  fn oracle_of(bank_pk: Pubkey) -> Pubkey {
+    Pubkey::new_from_array([bank_pk.to_bytes()[0] + 100; 32])
+  }
+
+  fn pyth_bank(bank_pk: Pubkey) -> Bank {
+    let mut bank = bank();
+    bank.mint = Pubkey::new_from_array([bank_pk.to_bytes()[0] + 50; 32]);
+    bank.liquidity_vault = Pubkey::new_from_array([bank_pk.to_bytes()[0] + 150; 32]);
+    bank.insurance_vault = Pubkey::new_from_array([bank_pk.to_bytes()[0] + 200; 32]);
+    bank.config.oracle_setup = OracleSetup::PythPushOracle;
+    bank.config.oracle_keys[0] = oracle_of(bank_pk);
+    bank
+  }
+
+  fn liquidation(token_program: Pubkey) -> anyhow::Result<Instruction> {
+    let liquidatee = user_account(vec![
+      bank_account(ASSET_BANK, pyth_bank(ASSET_BANK), 1.0, 100.0, 0.0),
+      bank_account(LIAB_BANK, pyth_bank(LIAB_BANK), 1.0, 0.0, 90.0),
+    ]);
+    let mut account = MarginfiAccount::zeroed();
+    account.lending_account.balances[0] = bank_account(LIQUIDATOR_BANK, bank(), 1.0, 10.0, 0.0).balance;
+    let opportunity = LiquidationOpportunity {
+      account: LIQUIDATEE,
+      asset_bank: ASSET_BANK,
+      liability_bank: LIAB_BANK,
+      maintenance: I80F48::from_num(-5),
+      liability_repaid: I80F48::from_num(10),
+      liability_repaid_native: I80F48::from_num(10_000_000),
+      asset_seized_native: I80F48::from_num(10_250_000.9),
+      estimated_liquidation_profit: I80F48::from_num(0.25),
+      unwind: None,
+    };
+
+    build_liquidate_instruction(&opportunity, &liquidatee, &Liquidator {
+      pubkey: LIQUIDATOR,
+      authority: AUTHORITY,
+      account,
+      token_program,
+      banks: HashMap::from([(LIQUIDATOR_BANK, pyth_bank(LIQUIDATOR_BANK))]),
+    })
+  }
+
+  #[test]
+  fn liquidate_data_is_discriminator_then_seized_amount() {
+    let ix = liquidation(anchor_spl::token::ID).unwrap();
+
+    assert_eq!(ix.program_id, MARGINFI_PROGRAM_ID);
+    assert_eq!(ix.data[..8], hash(b"global:lending_account_liquidate").to_bytes()[..8]);
+    // The seized amount is rounded down to whole native units
+    assert_eq!(ix.data[8..], 10_250_000u64.to_le_bytes()[..]);
+  }
+
+  #[test]
+  fn liquidate_account_metas_follow_the_idl() {
+    let ix = liquidation(anchor_spl::token::ID).unwrap();
+
+    let (vault_authority, _) = Pubkey::find_program_address(
+      &[LIQUIDITY_VAULT_AUTHORITY_SEED.as_bytes(), LIAB_BANK.as_ref()],
+      &MARGINFI_PROGRAM_ID,
+    );
+    let liab_bank = pyth_bank(LIAB_BANK);
+    let readonly = |key| AccountMeta::new_readonly(key, false);
+    let expected = vec![
+      readonly(GROUP),
+      AccountMeta::new(ASSET_BANK, false),
+      AccountMeta::new(LIAB_BANK, false),
+      AccountMeta::new(LIQUIDATOR, false),
+      AccountMeta::new_readonly(AUTHORITY, true),
+      AccountMeta::new(LIQUIDATEE, false),
+      AccountMeta::new(vault_authority, false),
+      AccountMeta::new(liab_bank.liquidity_vault, false),
+      AccountMeta::new(liab_bank.insurance_vault, false),
+      readonly(anchor_spl::token::ID),
+      readonly(oracle_of(ASSET_BANK)),
+      readonly(oracle_of(LIAB_BANK)),
+      // The liquidator's current bank, then the banks of the liquidation it has no balance in
+      readonly(LIQUIDATOR_BANK),
+      readonly(oracle_of(LIQUIDATOR_BANK)),
+      readonly(ASSET_BANK),
+      readonly(oracle_of(ASSET_BANK)),
+      readonly(LIAB_BANK),
+      readonly(oracle_of(LIAB_BANK)),
+      // The liquidatee's banks
+      readonly(ASSET_BANK),
+      readonly(oracle_of(ASSET_BANK)),
+      readonly(LIAB_BANK),
+      readonly(oracle_of(LIAB_BANK)),
+    ];
+    assert_eq!(ix.accounts, expected);
+  }
+
+  #[test]
+  fn token_2022_liquidation_passes_the_liability_mint() {
+    let ix = liquidation(anchor_spl::token_2022::ID).unwrap();
+
+    assert_eq!(ix.accounts[9], AccountMeta::new_readonly(anchor_spl::token_2022::ID, false));
+    assert_eq!(ix.accounts[10], AccountMeta::new_readonly(pyth_bank(LIAB_BANK).mint, false));
+    assert_eq!(ix.accounts[11], AccountMeta::new_readonly(oracle_of(ASSET_BANK), false));
+  }
+}
@@ -0,0 +1,309 @@
+use anchor_lang::prelude::Pubkey;
+use anyhow::Context;
+use fixed::types::I80F48;
+
+use crate::utils::format_usd;
+use super::consts::LIQUIDATION_LIQUIDATOR_FEE;
+use super::types::{BankOperation, BankOperationalState};
+use super::unwind::{UnwindPlan, UnwindRequest};
+use super::{BankAccount, MarginfiUserAccount, ValuationPolicy};
+
+/// Whether an opportunity is worth acting on
+#[derive(Clone, Debug, PartialEq)]
+pub enum LiquidationDecision {
+  Skip(String),
+  /// Act, expecting this usd profit
+  Act(I80F48),
+}
+
+/// An underwater account and the balances a liquidation would act on
+#[derive(Clone, Debug)]
+pub struct LiquidationOpportunity {
+  pub account: Pubkey,
+  /// Bank of the collateral to seize
+  pub asset_bank: Pubkey,
+  /// Bank of the liability to repay
+  pub liability_bank: Pubkey,
+  /// Maintenance buffer in usd (negative)
+  pub maintenance: I80F48,
+  /// Usd value of the liability repaid
+  pub liability_repaid: I80F48,
+  /// Liability repaid, in native units of the liability bank
+  pub liability_repaid_native: I80F48,
+  /// Collateral seized, in native units of the asset bank
+  pub asset_seized_native: I80F48,
+  /// Gross usd gain of the liquidator, before transaction fees and tips. Includes the unwind cost
+  /// once `with_unwind` is applied.
+  pub estimated_liquidation_profit: I80F48,
+  /// Sale of the seized collateral, if an unwinder is configured
+  pub unwind: Option<UnwindPlan>,
+}
+
+impl LiquidationOpportunity {
+  /// Returns an opportunity if the account is below maintenance, acting on its largest collateral
+  /// and largest liability. The seized collateral is bought at a `LIQUIDATION_LIQUIDATOR_FEE`
+  /// discount, so the estimated profit is that discount applied to the repaid value, where the
+  /// repaid value is capped by the discounted value of the seizable collateral.
+  /// Only banks marked `liquidation_target` are seized from or repaid to. Whether the account is
+  /// below maintenance is decided under `policy`.
+  pub fn find(account_pubkey: Pubkey, account: &MarginfiUserAccount, policy: ValuationPolicy) -> anyhow::Result<Option<Self>> {
+    Self::find_among(account_pubkey, account, policy, |bank_account| bank_account.liquidation_target)
+  }
+
+  /// Whether the account is only spared because the bank filter excludes its banks as targets
+  pub fn is_blocked_by_bank_filter(account_pubkey: Pubkey, account: &MarginfiUserAccount, policy: ValuationPolicy) -> anyhow::Result<bool> {
+    if Self::find(account_pubkey, account, policy)?.is_some() {
+      return anyhow::Ok(false);
+    }
+    anyhow::Ok(Self::find_among(account_pubkey, account, policy, |_| true)?.is_some())
+  }
+
+  fn find_among<F>(account_pubkey: Pubkey, account: &MarginfiUserAccount, policy: ValuationPolicy, targetable: F) -> anyhow::Result<Option<Self>>
+  where
+    F: Fn(&BankAccount) -> bool,
+  {
+    let maintenance = account.maintenance(policy)?;
+    if maintenance >= I80F48::ZERO {
+      return anyhow::Ok(None);
+    }
+
+    let targets = || account.bank_accounts().iter().filter(|bank_account| targetable(bank_account));
+    let Some((asset, asset_value)) = largest_by(targets(), BankAccount::asset_value)? else {
+      return anyhow::Ok(None);
+    };
+    // The liquidator takes on both the collateral and the liability, so their asset tags must
+    // comingle, e.g. staked collateral only backs a SOL liability
+    let backed_by_asset = targets().filter(|liability| asset.bank.config.comingles_with(&liability.bank.config));
+    let Some((liability, liability_value)) = largest_by(backed_by_asset, BankAccount::liability_value)? else {
+      return anyhow::Ok(None);
+    };
+
+    let seizable_value = asset_value
+      .checked_mul(I80F48::ONE - LIQUIDATION_LIQUIDATOR_FEE)
+      .context("seizable value calculation failed")?;
+    let liability_repaid = std::cmp::min(liability_value, seizable_value);
+    let estimated_liquidation_profit = liability_repaid
+      .checked_mul(LIQUIDATION_LIQUIDATOR_FEE)
+      .context("liquidation profit calculation failed")?;
+
+    let asset_native = asset.asset_amount_native()?;
+    // Native amounts are the balances scaled by the share of their value being acted on
+    let liability_repaid_native = I80F48::from_num(liability.liability_amount_native()?)
+      .checked_mul(liability_repaid)
+      .and_then(|amount| amount.checked_div(liability_value))
+      .context("repaid amount calculation failed")?;
+    let asset_seized_native = liability_repaid
+      .checked_div(I80F48::ONE - LIQUIDATION_LIQUIDATOR_FEE)
+      .and_then(|seized_value| I80F48::from_num(asset_native).checked_mul(seized_value))
+      .and_then(|amount| amount.checked_div(asset_value))
+      .context("seized amount calculation failed")?;
+
+    anyhow::Ok(Some(Self {
+      account: account_pubkey,
+      asset_bank: asset.balance.bank_pk,
+      liability_bank: liability.balance.bank_pk,
+      maintenance,
+      liability_repaid,
+      liability_repaid_native,
+      asset_seized_native,
+      estimated_liquidation_profit,
+      unwind: None,
+    }))
+  }
+
+  /// Sale of the collateral seized by `owner` back into the repaid asset
+  pub fn unwind_request(&self, account: &MarginfiUserAccount, owner: Pubkey) -> anyhow::Result<UnwindRequest> {
+    let mint_of = |bank_pk: Pubkey| {
+      account
+        .bank_accounts()
+        .iter()
+        .find(|bank_account| bank_account.balance.bank_pk == bank_pk)
+        .map(|bank_account| bank_account.bank.mint)
+        .with_context(|| format!("bank {} is not part of the account", bank_pk))
+    };
+    let amount = self.asset_seized_native
+      .floor()
+      .checked_to_num::<u64>()
+      .context("seized amount does not fit in u64")?;
+
+    anyhow::Ok(UnwindRequest {
+      owner,
+      input_mint: mint_of(self.asset_bank)?,
+      output_mint: mint_of(self.liability_bank)?,
+      amount,
+    })
+  }
+
+  /// Attaches the unwind and deducts its expected cost, a fraction of the seized value, from the
+  /// estimated profit
+  pub fn with_unwind(mut self, plan: UnwindPlan) -> anyhow::Result<Self> {
+    let unwind_cost = self.liability_repaid
+      .checked_div(I80F48::ONE - LIQUIDATION_LIQUIDATOR_FEE)
+      .and_then(|seized_value| seized_value.checked_mul(plan.cost_fraction))
+      .context("unwind cost calculation failed")?;
+    self.estimated_liquidation_profit = self.estimated_liquidation_profit
+      .checked_sub(unwind_cost)
+      .context("liquidation profit calculation failed")?;
+    self.unwind = Some(plan);
+
+    anyhow::Ok(self)
+  }
+
+  /// Skips the opportunity when its estimated profit, the liquidation discount on the repaid
+  /// value net of the unwind if one is attached, is below `min_profit` usd
+  pub fn decide(&self, min_profit: I80F48) -> LiquidationDecision {
+    if self.estimated_liquidation_profit < min_profit {
+      return LiquidationDecision::Skip(format!(
+        "estimated profit {} is below minimum {}",
+        format_usd(self.estimated_liquidation_profit), format_usd(min_profit)
+      ));
+    }
+    LiquidationDecision::Act(self.estimated_liquidation_profit)
+  }
+
+  /// The first bank whose operational state rejects what the liquidation does to it, with that
+  /// state. Paused and bankrupt banks accept nothing, and reduce-only banks take neither the
+  /// liquidator's deposit of the seized collateral nor its new liability.
+  pub fn inoperative_bank(&self, account: &MarginfiUserAccount) -> anyhow::Result<Option<(Pubkey, BankOperationalState)>> {
+    // The collateral moves from the liquidatee to the liquidator, and the liability the other way
+    let checks = [
+      (self.asset_bank, BankOperation::Withdraw),
+      (self.asset_bank, BankOperation::Deposit),
+      (self.liability_bank, BankOperation::Borrow),
+      (self.liability_bank, BankOperation::Repay),
+    ];
+    for (bank_pk, op) in checks {
+      let bank_account = bank_account_of(account, bank_pk)?;
+      if !bank_account.is_operational(op) {
+        return anyhow::Ok(Some((bank_pk, bank_account.operational_state())));
+      }
+    }
+
+    anyhow::Ok(None)
+  }
+}
+
+fn bank_account_of(account: &MarginfiUserAccount, bank_pk: Pubkey) -> anyhow::Result<&BankAccount> {
+  account
+    .bank_accounts()
+    .iter()
+    .find(|bank_account| bank_account.balance.bank_pk == bank_pk)
+    .with_context(|| format!("bank {} is not part of the account", bank_pk))
+}
+
+/// The bank account with the largest non-zero `value`
+fn largest_by<'a, I, F>(bank_accounts: I, value: F) -> anyhow::Result<Option<(&'a BankAccount, I80F48)>>
+where
+  I: IntoIterator<Item = &'a BankAccount>,
+  F: Fn(&BankAccount) -> anyhow::Result<I80F48>,
+{
+  let mut largest: Option<(&'a BankAccount, I80F48)> = None;
+  for bank_account in bank_accounts {
+    let value = value(bank_account)?;
+    if value > I80F48::ZERO && largest.is_none_or(|(_, current)| value > current) {
+      largest = Some((bank_account, value));
+    }
+  }
+
+  anyhow::Ok(largest)
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::marginfi::fixtures::{bank, bank_account, user_account};
+
+  const ACCOUNT: Pubkey = Pubkey::new_from_array([9; 32]);
+  const ASSET_BANK: Pubkey = Pubkey::new_from_array([1; 32]);
+  const LIAB_BANK: Pubkey = Pubkey::new_from_array([2; 32]);
+
+  /// $1000 of collateral against $900 borrowed, $280 below maintenance, with the banks in
+  /// `asset_state` and `liability_state`
+  fn underwater(asset_state: BankOperationalState, liability_state: BankOperationalState) -> MarginfiUserAccount {
+    let mut asset_bank = bank();
+    asset_bank.config.operational_state = asset_state;
+    let mut liab_bank = bank();
+    liab_bank.config.operational_state = liability_state;
+    user_account(vec![
+      bank_account(ASSET_BANK, asset_bank, 10.0, 100.0, 0.0),
+      bank_account(LIAB_BANK, liab_bank, 1.0, 0.0, 900.0),
+    ])
+  }
+
+  fn inoperative_bank(asset_state: BankOperationalState, liability_state: BankOperationalState) -> Option<(Pubkey, BankOperationalState)> {
+    let account = underwater(asset_state, liability_state);
+    let opportunity = LiquidationOpportunity::find(ACCOUNT, &account, ValuationPolicy::PROTOCOL).unwrap().unwrap();
+    opportunity.inoperative_bank(&account).unwrap()
+  }
+
+  #[test]
+  fn operational_banks_are_actionable() {
+    assert_eq!(inoperative_bank(BankOperationalState::Operational, BankOperationalState::Operational), None);
+  }
+
+  #[test]
+  fn paused_bank_is_not_actionable() {
+    assert_eq!(
+      inoperative_bank(BankOperationalState::Paused, BankOperationalState::Operational),
+      Some((ASSET_BANK, BankOperationalState::Paused)),
+    );
+    assert_eq!(
+      inoperative_bank(BankOperationalState::Operational, BankOperationalState::Paused),
+      Some((LIAB_BANK, BankOperationalState::Paused)),
+    );
+  }
+
+  #[test]
+  fn reduce_only_bank_is_not_actionable() {
+    // The liquidator would deposit the collateral and borrow the liability
+    assert_eq!(
+      inoperative_bank(BankOperationalState::ReduceOnly, BankOperationalState::Operational),
+      Some((ASSET_BANK, BankOperationalState::ReduceOnly)),
+    );
+    assert_eq!(
+      inoperative_bank(BankOperationalState::Operational, BankOperationalState::ReduceOnly),
+      Some((LIAB_BANK, BankOperationalState::ReduceOnly)),
+    );
+  }
+
+  #[test]
+  fn bankrupt_bank_is_not_actionable() {
+    assert_eq!(
+      inoperative_bank(BankOperationalState::KilledByBankruptcy, BankOperationalState::Operational),
+      Some((ASSET_BANK, BankOperationalState::KilledByBankruptcy)),
+    );
+  }
+
+  fn opportunity() -> LiquidationOpportunity {
+    let account = underwater(BankOperationalState::Operational, BankOperationalState::Operational);
+    LiquidationOpportunity::find(ACCOUNT, &account, ValuationPolicy::PROTOCOL).unwrap().unwrap()
+  }
+
+  #[test]
+  fn profit_is_the_liquidator_fee_on_the_repaid_value() {
+    // All $900 of debt is repaid, well within the discounted $1000 of collateral
+    let opportunity = opportunity();
+
+    assert_eq!(opportunity.liability_repaid, I80F48::from_num(900));
+    assert_eq!(opportunity.estimated_liquidation_profit, I80F48::from_num(900) * LIQUIDATION_LIQUIDATOR_FEE);
+  }
+
+  #[test]
+  fn profit_just_below_the_minimum_is_skipped() {
+    let opportunity = opportunity();
+    let min_profit = opportunity.estimated_liquidation_profit + I80F48::from_num(0.01);
+
+    assert!(matches!(opportunity.decide(min_profit), LiquidationDecision::Skip(_)));
+  }
+
+  #[test]
+  fn profit_at_or_just_above_the_minimum_is_acted_on() {
+    let opportunity = opportunity();
+    let profit = opportunity.estimated_liquidation_profit;
+
+    assert_eq!(opportunity.decide(profit), LiquidationDecision::Act(profit));
+    assert_eq!(opportunity.decide(profit - I80F48::from_num(0.01)), LiquidationDecision::Act(profit));
+  }
+}
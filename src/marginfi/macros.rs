@@ -89,7 +89,7 @@ macro_rules! math_error {
     () => {{
         || {
             let error_code = $crate::marginfi::MarginfiError::MathError;
-            anchor_lang::prelude::msg!(
+            tracing::debug!(
                 "Error \"{}\" thrown at {}:{}",
                 error_code,
                 file!(),
@@ -138,10 +138,7 @@ macro_rules! bank_signer {
 #[macro_export]
 macro_rules! debug {
     ($($arg:tt)*) => {
-        // #[cfg(feature = "debug")]
-        {
-            anchor_lang::prelude::msg!($($arg)*);
-        }
+        tracing::debug!($($arg)*);
     };
 }
 
@@ -0,0 +1,45 @@
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use anyhow::Context;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use super::BotStats;
+
+/// Serves `stats` in the Prometheus text format on `addr` in the background, whatever the
+/// request path
+pub async fn spawn_metrics_server(addr: SocketAddr, stats: Arc<BotStats>) -> anyhow::Result<()> {
+  let listener = TcpListener::bind(addr).await
+    .with_context(|| format!("failed to bind metrics server to {}", addr))?;
+  tracing::info!(url = %format!("http://{}/metrics", addr), "serving metrics");
+
+  tokio::spawn(async move {
+    loop {
+      let (mut stream, _) = match listener.accept().await {
+        Ok(connection) => connection,
+        Err(err) => {
+          tracing::warn!(error = %err, "metrics server accept failed");
+          continue;
+        }
+      };
+      let stats = Arc::clone(&stats);
+      tokio::spawn(async move {
+        // The request itself doesn't matter, only that one arrived
+        let mut request = [0u8; 1024];
+        if stream.read(&mut request).await.is_err() {
+          return;
+        }
+        let body = stats.render_prometheus();
+        let response = format!(
+          "HTTP/1.1 200 OK\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+          body.len(),
+          body
+        );
+        let _ = stream.write_all(response.as_bytes()).await;
+      });
+    }
+  });
+
+  anyhow::Ok(())
+}
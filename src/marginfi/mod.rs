@@ -1,125 +1,1096 @@
+mod bot_error;
 mod instructions;
+mod liquidate;
+mod liquidation;
 mod user;
 mod types;
+mod concurrency;
 mod consts;
+mod debounce;
 mod errors;
+mod event_registry;
 mod events;
+#[cfg(test)]
+mod fixtures;
+#[cfg(test)]
+mod reconstructed_liquidation;
+mod seen;
 mod macros;
+mod metrics;
+mod output;
+mod poll;
 mod prelude;
+mod pulse;
+mod replay;
+mod scan;
+mod shutdown;
+mod stats;
+mod tracked;
+mod transactions;
+mod underwater_log;
+mod unwind;
+mod watch_list;
+mod watchdog;
 mod wrapped_i80f48;
 
 use fixed::types::I80F48;
 use instructions::*;
+use concurrency::*;
 use consts::*;
+use debounce::*;
+pub use bot_error::*;
 pub use errors::*;
+pub use event_registry::*;
 use events::*;
+use seen::*;
+use liquidation::*;
+use metrics::*;
+use output::*;
+use replay::*;
+use shutdown::*;
+use stats::*;
+use tracked::*;
+use transactions::sized_compute_unit_limit;
+use underwater_log::*;
+use unwind::*;
+use watch_list::*;
+use watchdog::*;
 use wrapped_i80f48::*;
 use user::*;
+use types::{BankAccountCache, OracleAccountCache};
 
+use std::collections::HashMap;
 use std::rc::Rc;
+use std::sync::Arc;
+
+use anchor_lang::prelude::Pubkey;
 
 use anchor_client::solana_sdk::commitment_config::CommitmentConfig;
+use anchor_client::solana_sdk::packet::PACKET_DATA_SIZE;
 use solana_rpc_client_types::config::{RpcTransactionLogsConfig, RpcTransactionLogsFilter};
+use solana_rpc_client_types::response::{Response, RpcLogsResponse};
 use solana_rpc_client::nonblocking::rpc_client::RpcClient;
-use solana_pubsub_client::nonblocking::pubsub_client::PubsubClient;
+use solana_pubsub_client::nonblocking::pubsub_client::{PubsubClient, UnsubscribeFn};
 use anchor_client::{Client, Cluster, Program};
-use anchor_client::solana_sdk::signature::Keypair;
-use tokio_stream::StreamExt;
-use std::time::Instant;
+use anchor_client::solana_sdk::signature::{read_keypair_file, Keypair, Signature};
+use tokio::time::Interval;
+use tokio_stream::{Stream, StreamExt};
+use tracing::Instrument;
+use std::time::{Duration, Instant};
 
+use crate::config::{Config, IngestMode, OutputFormat};
 use crate::consts::MARGINFI_PROGRAM_ID;
+use crate::utils::{format_usd, with_retry, Backoff, RateLimitedLog};
+
+/// Window over which identical oracle failures of a bank are collapsed into one log line
+const ORACLE_ERROR_LOG_WINDOW: Duration = Duration::from_secs(60);
+/// Websocket connection attempts before falling back to polling
+const WEBSOCKET_CONNECT_ATTEMPTS: u32 = 3;
+/// How long to poll after the websocket became unavailable before trying it again
+const WEBSOCKET_RETRY_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Why a listening loop returned
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+enum ListenEnd {
+  /// The source closed or its time ran out, listening goes on
+  Closed,
+  /// Ctrl-C or SIGTERM was received
+  Shutdown,
+}
+
+/// A log subscription. Unsubscribing is async so it can't happen in `Drop`, `close` must be
+/// awaited on every exit path or the subscription leaks on the node.
+struct LogSubscription {
+  unsubscribes: Vec<UnsubscribeFn>,
+}
+
+impl LogSubscription {
+  async fn close(self) {
+    for unsubscribe in self.unsubscribes {
+      unsubscribe().await;
+    }
+  }
+}
 
 pub struct Marginfi {
-  pubsub: PubsubClient,
+  config: Config,
   rpc_client: RpcClient,
   client: Client<Rc<Keypair>>,
-  program: Program<Rc<Keypair>>
+  program: Program<Rc<Keypair>>,
+  tracked: Arc<TrackedAccounts>,
+  seen: SeenOpportunities,
+  debounce: CheckDebounce,
+  stats: Arc<BotStats>,
+  recorder: Option<NotificationRecorder>,
+  underwater_log: Option<UnderwaterLog>,
+  oracle_errors: RateLimitedLog<(Pubkey, String)>,
+  unwinder: Option<Box<dyn CollateralUnwinder>>,
+  watchdog: Arc<Watchdog>,
+  shutdown: Shutdown,
+  bank_cache: Option<BankAccountCache>,
+  oracle_cache: Option<OracleAccountCache>,
+  concurrency: AdaptiveConcurrency,
+  events: EventRegistry
 }
 
 impl Marginfi {
-  pub async fn new(http_url: String, ws_url: String) -> anyhow::Result<Self> {
-    let pubsub = PubsubClient::new(&ws_url).await?;
-    let payer = Rc::new(Keypair::new());
-    let client = Client::new(Cluster::Custom(http_url, ws_url), payer);
+  pub async fn new(config: Config) -> anyhow::Result<Self> {
+    let underwater_log = config.underwater_log_path
+      .as_ref()
+      .map(UnderwaterLog::open)
+      .transpose()?;
+    let recorder = config.capture_path
+      .as_ref()
+      .map(NotificationRecorder::open)
+      .transpose()?;
+    let payer = match &config.keypair_path {
+      Some(path) => read_keypair_file(path)
+        .map_err(|err| anyhow::anyhow!("failed to read keypair file {}: {}", path.display(), err))?,
+      // Only reachable with a read-only bot, which never signs anything
+      None => Keypair::new(),
+    };
+    let payer = Rc::new(payer);
+    let client = Client::new_with_options(
+      Cluster::Custom(config.url.clone(), config.ws_url.clone()),
+      payer,
+      CommitmentConfig { commitment: config.commitment },
+    );
     let program = client.program(MARGINFI_PROGRAM_ID)?;
     let rpc_client = program.rpc();
+    let bank_cache = (config.bank_cache_ttl_ms > 0)
+      .then(|| BankAccountCache::new(Duration::from_millis(config.bank_cache_ttl_ms)));
+    let oracle_cache = (config.oracle_cache_ttl_ms > 0)
+      .then(|| OracleAccountCache::new(Duration::from_millis(config.oracle_cache_ttl_ms)));
+    let unwinder = config.jupiter_api_url
+      .as_ref()
+      .map(|url| Box::new(JupiterUnwinder::new(url, config.unwind_slippage_bps)) as Box<dyn CollateralUnwinder>);
+
+    let seen = SeenOpportunities::new(Duration::from_secs(config.liquidation_cooldown_secs));
+    let debounce = CheckDebounce::new(Duration::from_millis(config.recheck_cooldown_ms));
+    let concurrency = AdaptiveConcurrency::new(
+      config.min_concurrency,
+      config.max_concurrency,
+      Duration::from_millis(config.rpc_latency_target_ms)
+    );
+
+    anyhow::Ok(Self {
+      config,
+      rpc_client,
+      client,
+      program,
+      tracked: Arc::default(),
+      seen,
+      debounce,
+      stats: Arc::default(),
+      recorder,
+      underwater_log,
+      oracle_errors: RateLimitedLog::new(ORACLE_ERROR_LOG_WINDOW),
+      unwinder,
+      watchdog: Arc::default(),
+      shutdown: Shutdown::default(),
+      bank_cache,
+      oracle_cache,
+      concurrency,
+      events: EventRegistry::with_known_events()
+    })
+  }
 
-    anyhow::Ok(Self { pubsub, rpc_client, client, program })
+  /// Checks the marginfi program is deployed on the configured cluster, catching endpoints that
+  /// point at a cluster the program id doesn't belong to
+  pub async fn verify_program(&self) -> anyhow::Result<()> {
+    let program = self.rpc_client
+      .get_account_with_commitment(&MARGINFI_PROGRAM_ID, self.rpc_client.commitment())
+      .await?
+      .value;
+    match program {
+      Some(program) if program.executable => anyhow::Ok(()),
+      _ => anyhow::bail!(
+        "marginfi program {} is not deployed on {}{}",
+        MARGINFI_PROGRAM_ID,
+        self.config.url,
+        self.config.cluster.map(|cluster| format!(" ({:?})", cluster)).unwrap_or_default()
+      ),
+    }
+  }
+
+  pub fn stats(&self) -> &BotStats {
+    &self.stats
   }
 
+  /// Listens for program logs over the websocket, reconnecting with exponential backoff when the
+  /// subscription closes. If the websocket can't be established, polls for
+  /// `WEBSOCKET_RETRY_INTERVAL` before trying again. With `IngestMode::Poll` the websocket isn't
+  /// used at all. Returns on Ctrl-C or SIGTERM once the event at hand is handled, reporting the
+  /// opportunities still open. With a watch list configured, the accounts saved there are
+  /// evaluated first and the list is saved periodically and on return. With a metrics address
+  /// configured, metrics are served there.
   pub async fn listen_for_targets(&self) -> anyhow::Result<()> {
-    let (mut logs, _unsub) = self.pubsub
-        .logs_subscribe(
-            RpcTransactionLogsFilter::Mentions(vec![MARGINFI_PROGRAM_ID.to_string()]),
+    self.shutdown.listen()?;
+    if let Some(addr) = self.config.metrics_addr {
+      spawn_metrics_server(addr, Arc::clone(&self.stats)).await?;
+    }
+    if let Some(path) = &self.config.watch_list_path {
+      self.resume_watch_list(path).await;
+      spawn_watch_list_saver(
+        Arc::clone(&self.tracked),
+        path.clone(),
+        Duration::from_secs(self.config.watch_list_save_secs.max(1))
+      );
+    }
+
+    let result = self.listen().await;
+    self.report_open_opportunities();
+    if let Some(path) = &self.config.watch_list_path {
+      match save_watch_list(path, &self.tracked) {
+        Ok(()) => tracing::info!(accounts = self.tracked.len(), path = %path.display(), "saved watch list"),
+        Err(err) => tracing::warn!(error = %format!("{:#}", err), "failed to save watch list"),
+      }
+    }
+    result
+  }
+
+  /// Evaluates the accounts of a previous run's watch list, most at-risk first, so they're
+  /// tracked again right away
+  async fn resume_watch_list(&self, path: &std::path::Path) {
+    let entries = load_watch_list(path);
+    if entries.is_empty() {
+      return;
+    }
+    tracing::info!(accounts = entries.len(), path = %path.display(), "resuming watch list");
+    for entry in entries {
+      if let Err(err) = self.handle_account(&entry.pubkey, None, None).await {
+        if let Err(err) = self.report_account_error(&entry.pubkey, err) {
+          tracing::warn!(account = %entry.pubkey, tier = ?entry.tier, error = %err, "failed to evaluate");
+        }
+      }
+    }
+  }
+
+  async fn listen(&self) -> anyhow::Result<()> {
+    let mut sweep = tokio::time::interval(Duration::from_secs(self.config.sweep_interval_secs.max(1)));
+    let mut scan = tokio::time::interval(Duration::from_secs(self.config.scan_interval_secs.max(1)));
+    let mut last_seen: HashMap<Pubkey, Signature> = HashMap::new();
+    if self.config.watchdog_timeout_secs > 0 {
+      self.watchdog.spawn(Duration::from_secs(self.config.watchdog_timeout_secs));
+    }
+
+    if self.config.ingest_mode == IngestMode::Poll {
+      self.poll_for_targets(&mut sweep, &mut scan, &mut last_seen, None).await?;
+      return anyhow::Ok(());
+    }
+
+    let mut backoff = Backoff::new(
+      Duration::from_millis(self.config.reconnect_backoff_initial_ms),
+      Duration::from_millis(self.config.reconnect_backoff_max_ms),
+    );
+    loop {
+      let pubsub = match self.connect_pubsub(&mut backoff).await {
+        Ok(pubsub) => pubsub,
+        Err(err) => {
+          tracing::warn!(error = %format!("{:#}", err), "websocket unavailable, polling");
+          if self.poll_for_targets(&mut sweep, &mut scan, &mut last_seen, Some(WEBSOCKET_RETRY_INTERVAL)).await? == ListenEnd::Shutdown {
+            return anyhow::Ok(());
+          }
+          continue;
+        }
+      };
+      // `mentions` takes a single pubkey, so each program gets its own subscription
+      let mut streams = Vec::with_capacity(self.config.program_ids.len());
+      let mut subscription = LogSubscription { unsubscribes: Vec::new() };
+      let mut failure = None;
+      for program_id in &self.config.program_ids {
+        let subscribed = pubsub
+          .logs_subscribe(
+            RpcTransactionLogsFilter::Mentions(vec![program_id.to_string()]),
             RpcTransactionLogsConfig {
-              commitment: Some(CommitmentConfig::confirmed()),
+              commitment: Some(CommitmentConfig { commitment: self.config.commitment }),
             },
-        )
-        .await?;
+          )
+          .await;
+        match subscribed {
+          Ok((logs, unsubscribe)) => {
+            streams.push(logs);
+            subscription.unsubscribes.push(unsubscribe);
+          }
+          Err(err) => {
+            failure = Some((program_id, err));
+            break;
+          }
+        }
+      }
+      if let Some((program_id, err)) = failure {
+        subscription.close().await;
+        tracing::warn!(program = %program_id, error = %err, "log subscription failed");
+        if self.poll_for_targets(&mut sweep, &mut scan, &mut last_seen, Some(WEBSOCKET_RETRY_INTERVAL)).await? == ListenEnd::Shutdown {
+          return anyhow::Ok(());
+        }
+        continue;
+      }
+      let logs = futures::stream::select_all(streams);
+      backoff.reset();
 
-        println!("✅ Connected! Listening for liquidation events...\n");
+      tracing::info!(programs = self.config.program_ids.len(), "connected, listening for liquidation events");
+      let end = self.listen_via_websocket(logs, &mut sweep, &mut scan).await;
+      subscription.close().await;
+      if end? == ListenEnd::Shutdown {
+        tracing::info!("shutting down");
+        return anyhow::Ok(());
+      }
+      let delay = backoff.next_delay();
+      tracing::warn!(delay = ?delay, "log subscription closed, reconnecting");
+      tokio::select! {
+        _ = tokio::time::sleep(delay) => {}
+        _ = self.shutdown.requested() => {
+          tracing::info!("shutting down");
+          return anyhow::Ok(());
+        }
+      }
+    }
+  }
 
-    while let Some(response) = logs.next().await {
-      let signature = &response.value.signature;
-      let err = response.value.err.is_some();
-      
-      if err {
-        continue;
+  fn report_open_opportunities(&self) {
+    let open = self.seen.open();
+    if open.is_empty() {
+      return;
+    }
+    tracing::info!(count = open.len(), "opportunities acted on whose accounts are still liquidatable");
+    for seen in open {
+      tracing::info!(
+        account = %seen.opportunity.account,
+        liability_bank = %seen.opportunity.liability_bank,
+        asset_bank = %seen.opportunity.asset_bank,
+        elapsed = ?seen.seen_at.elapsed(),
+        "still open"
+      );
+    }
+  }
+
+  async fn connect_pubsub(&self, backoff: &mut Backoff) -> anyhow::Result<PubsubClient> {
+    let mut attempt = 1;
+    loop {
+      match PubsubClient::new(&self.config.ws_url).await {
+        Ok(pubsub) => return anyhow::Ok(pubsub),
+        Err(err) if attempt < WEBSOCKET_CONNECT_ATTEMPTS => {
+          let delay = backoff.next_delay();
+          tracing::warn!(attempt, error = %err, delay = ?delay, "websocket connection failed, retrying");
+          tokio::time::sleep(delay).await;
+          attempt += 1;
+        }
+        Err(err) => return Err(BotError::Subscription(err.into()).into()),
       }
+    }
+  }
+
+  /// Processes notifications until the subscription closes or shutdown is requested
+  async fn listen_via_websocket(
+    &self,
+    mut logs: impl Stream<Item = Response<RpcLogsResponse>> + Unpin,
+    sweep: &mut Interval,
+    scan: &mut Interval,
+  ) -> anyhow::Result<ListenEnd> {
+    let sweep_enabled = self.config.sweep_interval_secs > 0;
+    let scan_enabled = self.config.scan_interval_secs > 0;
+
+    loop {
+      tokio::select! {
+        response = logs.next() => {
+          let Some(response) = response else {
+            return anyhow::Ok(ListenEnd::Closed);
+          };
+          self.ingest(response).await?;
+        }
+        _ = sweep.tick(), if sweep_enabled => {
+          self.sweep_tracked().await;
+        }
+        _ = scan.tick(), if scan_enabled => {
+          self.run_scan().await;
+        }
+        _ = self.shutdown.requested() => {
+          return anyhow::Ok(ListenEnd::Shutdown);
+        }
+      }
+    }
+  }
+
+  /// Records a notification from a live source, if capturing, and processes it
+  async fn ingest(&self, response: Response<RpcLogsResponse>) -> anyhow::Result<()> {
+    self.watchdog.touch();
+    if let Some(recorder) = &self.recorder {
+      recorder.record(&response)?;
+    }
+    self.process_notification(response).await
+  }
+
+  /// Parses the events of a single log notification and evaluates the accounts they touch
+  pub async fn process_notification(&self, response: Response<RpcLogsResponse>) -> anyhow::Result<()> {
+    let signature = &response.value.signature;
+    let err = response.value.err.is_some();
+    
+    if err {
+      return anyhow::Ok(());
+    }
+
+    let ctx = EventContext { signature };
+    for log in &response.value.logs {
+      let Some(event_data) = log.strip_prefix("Program data: ") else {
+        continue;
+      };
+      let span = tracing::info_span!("event", signature = %signature, event_type = tracing::field::Empty);
+      let action = match span.in_scope(|| self.events.dispatch(event_data, &ctx)) {
+        Ok(Some((event_type, action))) => {
+          span.record("event_type", event_type);
+          self.stats.record_event(event_type);
+          action
+        }
+        Ok(None) | Err(_) => continue,
+      };
 
-      for log in &response.value.logs {
-        if let Some(event_data) = log.strip_prefix("Program data: ") {
-          if let Ok(event) = parse_anchor_event::<LendingAccountWithdrawEvent>(event_data) {
-            println!("WITHDRAW!");
-            println!("  Transaction: {}", signature);
-            
-            self.handle_account(&event.header.marginfi_account).await?;
-            println!();
+      match action {
+        EventAction::Ignore => continue,
+        EventAction::Evaluate { account, change } => {
+          let result = self.handle_account(&account, change, Some(signature.as_str())).instrument(span.clone()).await;
+          if let Err(err) = result {
+            span.in_scope(|| self.report_account_error(&account, err))?;
           }
         }
+        EventAction::Contested { account } => span.in_scope(|| self.seen.mark_contested(account)),
       }
     }
 
     anyhow::Ok(())
   }
 
-  async fn handle_account(&self, account_pubkey: &anchor_lang::prelude::Pubkey) -> anyhow::Result<()> {
+  /// Re-evaluates the most urgent tracked accounts (see `TrackedAccount::cmp_urgency`), most
+  /// underwater first.
+  async fn sweep_tracked(&self) {
+    let targets = self.tracked.by_urgency();
+    tracing::info!(
+      limit = self.concurrency.limit(),
+      max = self.concurrency.max(),
+      rpc_latency_ema = ?self.concurrency.latency_ema().unwrap_or_default(),
+      "concurrency"
+    );
+    match &self.oracle_cache {
+      Some(cache) => tracing::info!(
+        tracked = targets.len(),
+        oracle_cache_hit_rate = %format!("{:.1}%", cache.hit_rate() * 100.0),
+        oracle_cache_lookups = cache.hits() + cache.misses(),
+        "sweep"
+      ),
+      None => tracing::info!(tracked = targets.len(), "sweep"),
+    }
+    for (bank, value) in self.tracked.liability_exposure() {
+      tracing::info!(bank = %bank, exposure = %format_usd(value), "bank exposure");
+    }
+    let targets = futures::stream::iter(targets.into_iter().take(self.config.sweep_limit));
+    futures::StreamExt::for_each_concurrent(targets, self.concurrency.max(), |target| async move {
+      let _permit = self.concurrency.acquire().await;
+      if let Err(err) = self.handle_account(&target.pubkey, None, None).await {
+        if let Err(err) = self.report_account_error(&target.pubkey, err) {
+          tracing::warn!(account = %target.pubkey, error = %err, "failed to re-evaluate");
+        }
+      }
+    })
+    .await;
+  }
+
+  /// Evaluates an account and returns its snapshot, or `None` when it was skipped before being
+  /// valued. `change` is the balance change that triggered the evaluation, if any, and lets the
+  /// evaluation be skipped when it's too small to matter. `signature` is the transaction that
+  /// triggered it, recorded in the underwater log.
+  ///
+  /// Runs in a `handle_account` span carrying the `account`, the `signature` and, once valued,
+  /// the `health` factor.
+  async fn handle_account(
+    &self,
+    account_pubkey: &anchor_lang::prelude::Pubkey,
+    change: Option<BalanceChange>,
+    signature: Option<&str>,
+  ) -> anyhow::Result<Option<AccountSnapshot>> {
+    let span = tracing::info_span!(
+      "handle_account",
+      account = %account_pubkey,
+      signature = tracing::field::Empty,
+      health = tracing::field::Empty,
+    );
+    if let Some(signature) = signature {
+      span.record("signature", signature);
+    }
+    self.evaluate_account(account_pubkey, change, signature).instrument(span).await
+  }
+
+  async fn evaluate_account(
+    &self,
+    account_pubkey: &anchor_lang::prelude::Pubkey,
+    change: Option<BalanceChange>,
+    signature: Option<&str>,
+  ) -> anyhow::Result<Option<AccountSnapshot>> {
+    if let (Some(change), Some(tracked)) = (change, self.tracked.get(account_pubkey)) {
+      let threshold = I80F48::from_num(self.config.immaterial_change_usd);
+      if tracked.is_immaterial(&change, threshold) {
+        tracing::debug!(amount = %change.amount, bank = %change.bank, "skipped, immaterial change");
+        return anyhow::Ok(None);
+      }
+    }
+
+    if let Some(elapsed) = self.debounce.begin(account_pubkey) {
+      tracing::debug!(elapsed = ?elapsed, "skipped, checked recently");
+      return anyhow::Ok(None);
+    }
+
     let start = Instant::now();
-    let account = MarginfiUserAccount::from_pubkey(&self.rpc_client, account_pubkey).await?;
+    let retry = self.config.rpc_retry_policy();
+    let marginfi_account = with_retry(retry, || MarginfiUserAccount::fetch_account(&self.rpc_client, account_pubkey)).await
+      .inspect_err(|_| BotStats::increment(&self.stats.rpc_errors))?;
+    // Accounts of other groups are skipped before their banks and oracles are loaded
+    if !self.config.watches_group(&marginfi_account.group) {
+      tracing::debug!(group = %marginfi_account.group, "skipped, unwatched group");
+      return anyhow::Ok(None);
+    }
+    if !marginfi_account.lending_account.has_liabilities() {
+      // Pure depositors can't be liquidated, skip their banks and oracles
+      self.tracked.remove(account_pubkey);
+      if let Some(seen) = self.seen.resolve(account_pubkey) {
+        tracing::info!(elapsed = ?seen.seen_at.elapsed(), "opportunity resolved");
+      }
+      tracing::debug!(deposits = marginfi_account.lending_account.active_asset_count(), "skipped, nothing borrowed");
+      return anyhow::Ok(None);
+    }
+    let account = with_retry(retry, || MarginfiUserAccount::from_account_cached(
+      &self.rpc_client,
+      marginfi_account,
+      self.bank_cache.as_ref(),
+      self.oracle_cache.as_ref(),
+    )).await
+      .and_then(MarginfiUserAccount::require_complete_pricing)
+      .inspect_err(|_| BotStats::increment(&self.stats.rpc_errors))?
+      .with_pyth_shard_overrides(&self.rpc_client, &self.config.pyth_shard_overrides).await?
+      .with_usd_references(&self.rpc_client, &self.config.usd_references).await?
+      .with_oracle_confidence_multiplier(I80F48::from_num(self.config.oracle_confidence_multiplier))
+      .with_bank_filter(|bank| self.config.allows_bank(bank));
+    self.concurrency.record_latency(start.elapsed());
+    self.watchdog.touch();
     let marginfi_account = account.account();
-    let bank_accounts = account.bank_accounts();
+    let mismatched = account.mismatched_group_banks();
+    if !mismatched.is_empty() {
+      tracing::warn!(group = %marginfi_account.group, banks = ?mismatched, "account references banks outside its group");
+      return anyhow::Ok(None);
+    }
+    if let Some((bank, other)) = account.asset_tag_conflict() {
+      tracing::warn!(bank = %bank, other = %other, "account mixes banks whose asset tags can't comingle");
+      return anyhow::Ok(None);
+    }
+    if let Some(reason) = marginfi_account.liquidation_blocked_reason() {
+      tracing::info!(reason = %reason, "skipped, liquidation blocked");
+      return anyhow::Ok(None);
+    }
     let duration = start.elapsed();
-    println!("ACCOUNT DATA ({:?})", duration);
-    println!("  Owner: {}", marginfi_account.authority);
-    let asset_value = account.asset_value()?;
-    println!("  Lended assets ({}$):", asset_value);
-    for bank_account in bank_accounts {
-      let asset_shares: I80F48 = bank_account.balance.asset_shares.into();
-      if asset_shares.is_zero() {
-        continue;
+    let policy = self.valuation_policy();
+    let snapshot = AccountSnapshot::new(account_pubkey, &account, policy)?;
+    tracing::Span::current().record("health", tracing::field::display(snapshot.health_factor_label()));
+    BotStats::increment(&self.stats.accounts_checked);
+    self.stats.record_latency(start.elapsed());
+    if snapshot.is_liquidatable() {
+      BotStats::increment(&self.stats.accounts_liquidatable);
+    }
+    match (self.config.output_format, snapshot.is_alert(self.config.health_alert_threshold)) {
+      (OutputFormat::Human, true) => println!("ACCOUNT DATA ({:?})\n{}\n", duration, snapshot),
+      (OutputFormat::Human, false) => tracing::info!(elapsed = ?duration, "healthy"),
+      (OutputFormat::Json, true) => println!("{}", snapshot.to_json()),
+      (OutputFormat::Json, false) => {}
+    }
+    if let (Some(log), true) = (&self.underwater_log, snapshot.is_liquidatable()) {
+      log.record(&snapshot, signature)?;
+    }
+    let tracked = self.tracked.update(*account_pubkey, &account, policy)?;
+    self.report_snapshot_change(&snapshot);
+    if let Some(seen) = self.seen.get(account_pubkey) {
+      if tracked.maintenance >= I80F48::ZERO {
+        // Healthy again, someone liquidated it or it recovered
+        self.seen.resolve(account_pubkey);
+        tracing::info!(elapsed = ?seen.seen_at.elapsed(), "opportunity resolved");
+      } else {
+        tracing::info!(elapsed = ?seen.seen_at.elapsed(), "skipped, opportunity acted on recently");
+        return anyhow::Ok(Some(snapshot));
+      }
+    }
+
+    if let Some(opportunity) = LiquidationOpportunity::find(*account_pubkey, &account, policy)? {
+      if let Some(elapsed) = self.seen.contested_since(account_pubkey) {
+        tracing::info!(elapsed = ?elapsed, "skipped, contested by another liquidator");
+        return anyhow::Ok(Some(snapshot));
+      }
+      if tracked.liquidatable_streak < self.config.liquidation_confirmations {
+        tracing::info!(
+          streak = tracked.liquidatable_streak,
+          required = self.config.liquidation_confirmations,
+          "liquidatable, awaiting confirmation"
+        );
+        return anyhow::Ok(Some(snapshot));
       }
-      println!("     Mint: {}", bank_account.bank.mint);
-      println!("     Balance: {}", bank_account.bank.get_display_asset(bank_account.bank.get_asset_amount(asset_shares).unwrap()).unwrap());
+      self.act_on(opportunity, &account).await?;
+    } else if LiquidationOpportunity::is_blocked_by_bank_filter(*account_pubkey, &account, policy)? {
+      tracing::info!("skipped, only filtered banks could be seized from or repaid to");
     }
-    println!("  Borrowed assets ({}$):", account.liability_value()?);
-    for bank_account in bank_accounts {
-      let liability_shares: I80F48 = bank_account.balance.liability_shares.into();
-      if liability_shares.is_zero() {
+
+    anyhow::Ok(Some(snapshot))
+  }
+
+  /// Logs how `snapshot` differs from the account's previous one, warning when it crossed into
+  /// liquidation, and keeps it for the next evaluation
+  fn report_snapshot_change(&self, snapshot: &AccountSnapshot) {
+    let Some(prev) = self.tracked.replace_snapshot(snapshot) else {
+      return;
+    };
+    let diff = snapshot.diff(&prev);
+    if diff.transition == HealthTransition::BecameLiquidatable {
+      tracing::warn!(maintenance_delta = %diff.maintenance_delta, "crossed below maintenance");
+    } else if diff.is_deterioration() {
+      tracing::debug!(
+        health_factor_delta = ?diff.health_factor_delta,
+        maintenance_delta = %diff.maintenance_delta,
+        opened = diff.opened.len(),
+        closed = diff.closed.len(),
+        "health deteriorated"
+      );
+    }
+  }
+
+  /// Loads an account the way `handle_account` does and prints why it is or isn't liquidatable
+  pub async fn inspect(&self, account_pubkey: &Pubkey) -> anyhow::Result<()> {
+    let account = self.load_configured(account_pubkey).await?;
+    let explanation = account.explain_health(self.valuation_policy())?;
+
+    println!("ACCOUNT {} ({:?} valuation)", account_pubkey, self.config.valuation);
+    println!("{}", explanation);
+    // Interest keeps accruing while prices hold, a day out shows whether the account drifts under
+    let now = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .map_or(0, |elapsed| elapsed.as_secs() as i64);
+    let projected = account.maintenance_at(self.valuation_policy(), now + 86_400)?;
+    println!("Maintenance health in a day at current prices: {}", format_usd(projected));
+    // The program's own health, to tell a mispriced valuation from a real opportunity
+    match self.pulse_health_batch(&[*account_pubkey]).await {
+      Ok(caches) => match caches.get(account_pubkey) {
+        Some(cache) => println!(
+          "Program health: {} (maintenance assets {}, liabilities {})",
+          if cache.is_healthy() { "healthy" } else { "liquidatable" },
+          format_usd(cache.asset_value_maint.into()),
+          format_usd(cache.liability_value_maint.into()),
+        ),
+        None => println!("Program health: pulse simulation failed"),
+      },
+      Err(err) => println!("Program health: {:#}", err),
+    }
+    anyhow::Ok(())
+  }
+
+  async fn load_configured(&self, account_pubkey: &Pubkey) -> anyhow::Result<MarginfiUserAccount> {
+    value_account(&self.rpc_client, &self.config, account_pubkey).await
+  }
+
+  /// Warns about the opportunity's banks whose share values lag interest accrual by more than
+  /// `interest_staleness_warn_secs`
+  fn warn_stale_interest(&self, opportunity: &LiquidationOpportunity, account: &MarginfiUserAccount) {
+    let now = std::time::SystemTime::now()
+      .duration_since(std::time::UNIX_EPOCH)
+      .map_or(0, |elapsed| elapsed.as_secs() as i64);
+    for bank_account in account.bank_accounts() {
+      let bank_pk = bank_account.balance.bank_pk;
+      if bank_pk != opportunity.asset_bank && bank_pk != opportunity.liability_bank {
         continue;
       }
-      println!("     Mint: {}", bank_account.bank.mint);
-      println!("     Balance: {}", bank_account.bank.get_display_asset(bank_account.bank.get_asset_amount(liability_shares).unwrap()).unwrap());
+      let staleness = bank_account.bank.interest_staleness(now);
+      if staleness > self.config.interest_staleness_warn_secs {
+        tracing::warn!(bank = %bank_pk, staleness_secs = staleness, "interest accrual is stale, health may be off");
+      }
+    }
+  }
+
+  /// The configured valuation, made lenient on oracle confidence if configured
+  fn valuation_policy(&self) -> ValuationPolicy {
+    valuation_policy(&self.config)
+  }
+
+  /// Errors in read-only mode. Every path that builds or sends a liquidation must call this first.
+  fn ensure_can_act(&self) -> anyhow::Result<()> {
+    if self.config.read_only {
+      anyhow::bail!("the bot is read-only, liquidations are never built or sent");
     }
-    let maint = account.maintenance()?;
-    println!("  Maintenance: {}$ ({}%)", maint, maint.checked_div(asset_value).unwrap().checked_mul_int(100).unwrap());
+    anyhow::Ok(())
+  }
+
+  /// Logs oracle failures through a per-(bank, error) rate limit so a chronically stale feed
+  /// doesn't flood the output. Any other error is returned to the caller.
+  fn report_account_error(&self, account_pubkey: &Pubkey, err: anyhow::Error) -> anyhow::Result<()> {
+    let Some(BotError::Oracle(oracle_err)) = BotError::of(&err) else {
+      return Err(err);
+    };
+
+    let kind = oracle_err.source.to_string();
+    let message = format!("skipped account {}: {}", account_pubkey, oracle_err);
+    self.oracle_errors.log((oracle_err.bank, kind), &message);
 
     anyhow::Ok(())
   }
+
+  async fn act_on(&self, mut opportunity: LiquidationOpportunity, account: &MarginfiUserAccount) -> anyhow::Result<()> {
+    if let Err(err) = self.ensure_can_act() {
+      tracing::info!(reason = %err, "not acting");
+      return anyhow::Ok(());
+    }
+    if let Some(unwinder) = &self.unwinder {
+      let request = opportunity.unwind_request(account, self.program.payer())?;
+      match unwinder.plan(&request).await {
+        Ok(plan) => opportunity = opportunity.with_unwind(plan)?,
+        Err(err) => {
+          tracing::info!(error = %format!("{:#}", err), "skipped, no unwind for seized collateral");
+          return anyhow::Ok(());
+        }
+      }
+    }
+
+    let profit = match opportunity.decide(I80F48::from_num(self.config.min_profit_usd)) {
+      LiquidationDecision::Act(profit) => profit,
+      LiquidationDecision::Skip(reason) => {
+        BotStats::increment(&self.stats.skipped_for_profit);
+        tracing::info!(reason = %reason, "skipped liquidation");
+        return anyhow::Ok(());
+      }
+    };
+    self.warn_stale_interest(&opportunity, account);
+    if let Some((bank, state)) = opportunity.inoperative_bank(account)? {
+      tracing::info!(bank = %bank, state = ?state, "skipped, bank is not operational");
+      return anyhow::Ok(());
+    }
+
+    tracing::info!(
+      profit = %format_usd(profit),
+      asset_bank = %opportunity.asset_bank,
+      liability_bank = %opportunity.liability_bank,
+      repaid_native = %opportunity.liability_repaid_native,
+      seized_native = %opportunity.asset_seized_native,
+      borrows = account.account().lending_account.active_liability_count(),
+      "liquidatable"
+    );
+    for bank_pk in [opportunity.asset_bank, opportunity.liability_bank] {
+      let age = account.bank_accounts()
+        .iter()
+        .find(|bank_account| bank_account.balance.bank_pk == bank_pk)
+        .and_then(|bank_account| bank_account.price_feed.switchboard_age());
+      if let Some(age) = age {
+        tracing::info!(bank = %bank_pk, write_secs = age.write_secs, result_slots = age.result_slots, "priced by switchboard");
+      }
+    }
+    if self.config.liquidator_account.is_some() {
+      let ix = self.liquidate_instruction(&opportunity, account).await?;
+      let simulation = self.simulate_liquidate(ix.clone()).await?;
+      if let Some(err) = &simulation.err {
+        match &simulation.program_error {
+          Some(program_error) => tracing::info!(program_error = ?program_error, error = %err, "skipped, simulation failed"),
+          None => tracing::info!(error = %err, "skipped, simulation failed"),
+        }
+        return anyhow::Ok(());
+      }
+      let cu_limit = match sized_compute_unit_limit(simulation.units_consumed, self.config.compute_unit_limit) {
+        Ok(cu_limit) => cu_limit,
+        Err(err) => {
+          tracing::info!(reason = %err, "skipped, liquidation needs too much compute");
+          return anyhow::Ok(());
+        }
+      };
+      tracing::info!(units = simulation.units_consumed, cu_limit, "simulated");
+      // Liquidations aren't sent yet, the transaction is only built to check it fits in a packet
+      let tx = self.build_liquidate_tx(ix, cu_limit);
+      let size = bincode::serialized_size(&tx)?;
+      if size > PACKET_DATA_SIZE as u64 {
+        tracing::info!(bytes = size, "skipped, liquidation transaction too large");
+        return anyhow::Ok(());
+      }
+      tracing::info!(bytes = size, "liquidation built, not sent");
+    }
+    self.seen.mark_seen(opportunity);
+
+    anyhow::Ok(())
+  }
+}
+
+/// Loads an account over `rpc_client` alone, without the listener's websocket or wallet, with
+/// the configured shard overrides, usd references, confidence and bank filter applied
+pub async fn value_account(rpc_client: &RpcClient, config: &Config, account_pubkey: &Pubkey) -> anyhow::Result<MarginfiUserAccount> {
+  let account = with_retry(config.rpc_retry_policy(), || MarginfiUserAccount::from_pubkey(rpc_client, account_pubkey)).await?
+    .with_pyth_shard_overrides(rpc_client, &config.pyth_shard_overrides).await?
+    .with_usd_references(rpc_client, &config.usd_references).await?
+    .with_oracle_confidence_multiplier(I80F48::from_num(config.oracle_confidence_multiplier))
+    .with_bank_filter(|bank| config.allows_bank(bank));
+  for unpriced in account.unpriced_banks() {
+    tracing::warn!(account = %account_pubkey, bank = %unpriced.bank, reason = %unpriced.reason, "bank left out of the valuation");
+  }
+  anyhow::Ok(account)
+}
+
+/// Loads an account once and prints its snapshot with its health factor. Only connects to the
+/// RPC, so no wallet or websocket is needed.
+pub async fn check_account(config: &Config, account_pubkey: &Pubkey) -> anyhow::Result<()> {
+  let rpc_client = RpcClient::new_with_commitment(config.url.clone(), CommitmentConfig { commitment: config.commitment });
+  let account = value_account(&rpc_client, config, account_pubkey).await?;
+  println!("{}", check_report(config, account_pubkey, &account)?);
+  anyhow::Ok(())
+}
+
+/// What `check_account` prints for an already loaded account, per `config.output_format`
+fn check_report(config: &Config, account_pubkey: &Pubkey, account: &MarginfiUserAccount) -> anyhow::Result<String> {
+  let policy = valuation_policy(config);
+  let snapshot = AccountSnapshot::new(account_pubkey, account, policy)?;
+  // What the account could borrow against, unlike the snapshot which is about liquidation
+  let init_margin_value = account.init_margin_value(policy)?;
+  let initial_health = account.initial_health(policy)?;
+
+  let report = match config.output_format {
+    OutputFormat::Human => {
+      let mut lines = vec![
+        format!("ACCOUNT {} ({:?} valuation)\n{}", account_pubkey, config.valuation, snapshot),
+        format!("  Health factor: {}", snapshot.health_factor_label()),
+        format!("  Init margin collateral: {}", format_usd(init_margin_value)),
+        format!("  Initial health: {}", format_usd(initial_health)),
+      ];
+      if !account.has_complete_pricing() {
+        lines.push(format!("  Incomplete: {} bank(s) left out of the valuation", account.unpriced_banks().len()));
+      }
+      lines.join("\n")
+    }
+    OutputFormat::Json => {
+      let mut json = snapshot.to_json();
+      json["init_margin_value"] = init_margin_value.to_string().into();
+      json["initial_health"] = initial_health.to_string().into();
+      json["complete_pricing"] = account.has_complete_pricing().into();
+      json.to_string()
+    }
+  };
+  anyhow::Ok(report)
+}
+
+fn valuation_policy(config: &Config) -> ValuationPolicy {
+  let policy = ValuationPolicy::from(config.valuation);
+  if config.lenient_confidence {
+    return policy.lenient();
+  }
+  policy
 }
 
-fn parse_anchor_event<T: anchor_lang::AnchorDeserialize>(data: &str) -> anyhow::Result<T> {
+/// Decodes a `Program data: ` payload as event `T`. Errors on payloads too short to hold a
+/// discriminator and on events of another type.
+fn parse_anchor_event<T: anchor_lang::AnchorDeserialize + anchor_lang::Discriminator>(data: &str) -> anyhow::Result<T> {
   use base64::{Engine as _, engine::general_purpose};
   let decoded = general_purpose::STANDARD.decode(data)?;
-  let event_data = &decoded[8..];
+  let Some((discriminator, event_data)) = decoded.split_at_checked(8) else {
+    anyhow::bail!("event data is {} bytes, too short for a discriminator", decoded.len());
+  };
+  if discriminator != T::DISCRIMINATOR {
+    anyhow::bail!("event discriminator {:?} doesn't match the expected event", discriminator);
+  }
   Ok(T::deserialize(&mut &event_data[..])?)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use std::path::PathBuf;
+  use std::sync::atomic::{AtomicUsize, Ordering};
+
+  use anchor_client::solana_sdk::signature::{write_keypair_file, Signer};
+  use pretty_assertions::assert_eq;
+  use tracing::span::{Attributes, Id};
+  use tracing::Subscriber;
+  use tracing_subscriber::layer::{Context, Layer, SubscriberExt};
+
+  use super::*;
+
+  /// Counts the spans opened with a name
+  struct SpansNamed(&'static str, Arc<AtomicUsize>);
+
+  impl<S: Subscriber> Layer<S> for SpansNamed {
+    fn on_new_span(&self, attrs: &Attributes<'_>, _id: &Id, _ctx: Context<'_, S>) {
+      if attrs.metadata().name() == self.0 {
+        self.1.fetch_add(1, Ordering::Relaxed);
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn handle_account_opens_one_span_per_call() {
+    let spans = Arc::new(AtomicUsize::new(0));
+    let subscriber = tracing_subscriber::registry().with(SpansNamed("handle_account", Arc::clone(&spans)));
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    // Nothing listens there, each evaluation fails fast on its first read
+    let mut config = Config::for_tests("http://127.0.0.1:1");
+    config.rpc_retry_attempts = 1;
+    let marginfi = Marginfi::new(config).await.unwrap();
+    let account = Pubkey::new_unique();
+    for _ in 0..3 {
+      let _ = marginfi.handle_account(&account, None, Some("signature")).await;
+    }
+
+    assert_eq!(spans.load(Ordering::Relaxed), 3);
+  }
+
+  /// A `Program data: ` log of another liquidator liquidating `liquidatee`
+  fn liquidate_log(liquidatee: Pubkey) -> String {
+    use anchor_lang::Event;
+    use base64::{Engine as _, engine::general_purpose};
+
+    let balances = || LiquidationBalances {
+      liquidatee_asset_balance: 0.0,
+      liquidatee_liability_balance: 0.0,
+      liquidator_asset_balance: 0.0,
+      liquidator_liability_balance: 0.0,
+    };
+    let event = LendingAccountLiquidateEvent {
+      header: AccountEventHeader {
+        signer: None,
+        marginfi_account: Pubkey::new_unique(),
+        marginfi_account_authority: Pubkey::default(),
+        marginfi_group: Pubkey::default(),
+      },
+      liquidatee_marginfi_account: liquidatee,
+      liquidatee_marginfi_account_authority: Pubkey::default(),
+      asset_bank: Pubkey::default(),
+      asset_mint: Pubkey::default(),
+      liability_bank: Pubkey::default(),
+      liability_mint: Pubkey::default(),
+      liquidatee_pre_health: 0.9,
+      liquidatee_post_health: 1.0,
+      pre_balances: balances(),
+      post_balances: balances(),
+    };
+    format!("Program data: {}", general_purpose::STANDARD.encode(event.data()))
+  }
+
+  #[tokio::test]
+  async fn process_notification_opens_one_span_per_event() {
+    let spans = Arc::new(AtomicUsize::new(0));
+    let subscriber = tracing_subscriber::registry().with(SpansNamed("event", Arc::clone(&spans)));
+    let _guard = tracing::subscriber::set_default(subscriber);
+    let marginfi = Marginfi::new(Config::for_tests("http://127.0.0.1:1")).await.unwrap();
+    let notification = Response {
+      context: solana_rpc_client_types::response::RpcResponseContext::new(1),
+      value: RpcLogsResponse {
+        signature: "signature".to_string(),
+        err: None,
+        logs: vec![
+          "Program log: Instruction: LendingAccountLiquidate".to_string(),
+          liquidate_log(Pubkey::new_unique()),
+          liquidate_log(Pubkey::new_unique()),
+        ],
+      },
+    };
+
+    marginfi.process_notification(notification).await.unwrap();
+    assert_eq!(spans.load(Ordering::Relaxed), 2);
+  }
+
+  #[tokio::test]
+  async fn repeated_triggers_inside_the_cooldown_run_one_check() {
+    // Nothing listens there, so each full check fails its first read and counts an rpc error
+    let mut config = Config::for_tests("http://127.0.0.1:1");
+    config.rpc_retry_attempts = 1;
+    config.recheck_cooldown_ms = 60_000;
+    let marginfi = Marginfi::new(config).await.unwrap();
+    let account = Pubkey::new_unique();
+
+    assert!(marginfi.handle_account(&account, None, Some("first")).await.is_err());
+    assert!(marginfi.handle_account(&account, None, Some("second")).await.unwrap().is_none());
+    assert_eq!(BotStats::get(&marginfi.stats.rpc_errors), 1);
+  }
+
+  #[test]
+  fn event_data_shorter_than_a_discriminator_errors() {
+    use anchor_lang::Discriminator;
+    use base64::{Engine as _, engine::general_purpose};
+
+    for len in [0, 7] {
+      let data = general_purpose::STANDARD.encode(vec![0u8; len]);
+      let err = parse_anchor_event::<HealthPulseEvent>(&data).unwrap_err();
+      assert_eq!(err.to_string(), format!("event data is {} bytes, too short for a discriminator", len));
+    }
+    let data = general_purpose::STANDARD.encode(HealthPulseEvent::DISCRIMINATOR);
+    assert!(parse_anchor_event::<HealthPulseEvent>(&data).is_err());
+  }
+
+  #[tokio::test]
+  async fn ended_log_stream_is_reported_as_closed_for_a_reconnect() {
+    let marginfi = Marginfi::new(Config::for_tests("http://127.0.0.1:1")).await.unwrap();
+    let mut sweep = tokio::time::interval(Duration::from_secs(60));
+    let mut scan = tokio::time::interval(Duration::from_secs(60));
+
+    let end = marginfi.listen_via_websocket(futures::stream::empty(), &mut sweep, &mut scan).await.unwrap();
+    assert_eq!(end, ListenEnd::Closed);
+  }
+
+  #[tokio::test]
+  async fn shutdown_stops_listening_to_an_open_log_stream() {
+    let marginfi = Marginfi::new(Config::for_tests("http://127.0.0.1:1")).await.unwrap();
+    let mut sweep = tokio::time::interval(Duration::from_secs(60));
+    let mut scan = tokio::time::interval(Duration::from_secs(60));
+    marginfi.shutdown.request();
+
+    let end = marginfi.listen_via_websocket(futures::stream::pending(), &mut sweep, &mut scan).await.unwrap();
+    assert_eq!(end, ListenEnd::Shutdown);
+  }
+
+  #[tokio::test]
+  async fn payer_is_loaded_from_the_keypair_file() {
+    let keypair = Keypair::new();
+    let path = std::env::temp_dir().join(format!("liquidation_searcher_payer_{}.json", std::process::id()));
+    write_keypair_file(&keypair, &path).unwrap();
+    let mut config = Config::for_tests("http://127.0.0.1:1");
+    config.keypair_path = Some(path.clone());
+
+    let marginfi = Marginfi::new(config).await;
+    std::fs::remove_file(&path).unwrap();
+    assert_eq!(marginfi.unwrap().program.payer(), keypair.pubkey());
+  }
+
+  #[tokio::test]
+  async fn unreadable_keypair_file_is_an_error() {
+    let mut config = Config::for_tests("http://127.0.0.1:1");
+    config.keypair_path = Some(PathBuf::from("/nonexistent/payer.json"));
+
+    let err = Marginfi::new(config).await.err().unwrap();
+    assert!(err.to_string().contains("/nonexistent/payer.json"), "{}", err);
+  }
+
+  #[test]
+  fn check_report_follows_the_output_format() {
+    use crate::marginfi::fixtures::{bank, bank_account, user_account};
+
+    let account_pubkey = Pubkey::new_unique();
+    let account = user_account(vec![
+      bank_account(Pubkey::new_from_array([1; 32]), bank(), 10.0, 100.0, 0.0),
+      bank_account(Pubkey::new_from_array([2; 32]), bank(), 1.0, 0.0, 300.0),
+    ]);
+    let mut config = Config::for_tests("http://127.0.0.1:1");
+    let initial_health = account.initial_health(ValuationPolicy::PROTOCOL).unwrap();
+
+    let human = check_report(&config, &account_pubkey, &account).unwrap();
+    assert!(human.starts_with(&format!("ACCOUNT {} (Protocol valuation)", account_pubkey)), "{}", human);
+    assert!(human.contains("  Health factor: "), "{}", human);
+    assert!(human.ends_with(&format!("  Initial health: {}", format_usd(initial_health))), "{}", human);
+
+    config.output_format = OutputFormat::Json;
+    let json: serde_json::Value = serde_json::from_str(&check_report(&config, &account_pubkey, &account).unwrap()).unwrap();
+    assert_eq!(json["initial_health"], initial_health.to_string());
+    assert_eq!(json["complete_pricing"], true);
+  }
+
+  /// Checks the account in `CHECK_ACCOUNT` against the RPC configured in `.env`, run with
+  /// `cargo test --features network-tests`
+  #[cfg(feature = "network-tests")]
+  #[tokio::test]
+  async fn check_account_loads_a_live_account() {
+    let config = Config::open().await.unwrap();
+    let account = std::env::var("CHECK_ACCOUNT").expect("CHECK_ACCOUNT is set").parse::<Pubkey>().unwrap();
+
+    check_account(&config, &account).await.unwrap();
+  }
+}
@@ -0,0 +1,334 @@
+use std::fmt;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::Context;
+use fixed::types::I80F48;
+use serde_json::json;
+
+use crate::utils::format_usd;
+use super::types::BalanceSide;
+use super::{MarginfiUserAccount, ValuationPolicy, WrappedI80F48};
+
+/// One side of a balance of an evaluated account
+#[derive(Clone, Debug)]
+pub struct PositionInfo {
+  pub bank: Pubkey,
+  pub mint: Pubkey,
+  pub side: BalanceSide,
+  /// Amount in ui units of the mint, as an exact decimal
+  pub amount: String,
+  /// Usd value at the high/low-biased spot price, see `BankAccount::asset_value`
+  pub value: I80F48,
+}
+
+/// How an account's maintenance health moved between two snapshots
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthTransition {
+  Unchanged,
+  Improved,
+  /// Worse without crossing maintenance
+  Deteriorated,
+  /// Crossed below maintenance
+  BecameLiquidatable,
+  /// Crossed back above maintenance
+  Recovered,
+}
+
+/// What changed between two snapshots of the same account, see `AccountSnapshot::diff`
+#[derive(Clone, Debug)]
+pub struct SnapshotDiff {
+  /// Current health factor minus the previous one, None unless both had liabilities
+  pub health_factor_delta: Option<I80F48>,
+  /// Maintenance buffer in usd now minus before
+  pub maintenance_delta: I80F48,
+  /// Positions held now but not before, matched by bank and side
+  pub opened: Vec<PositionInfo>,
+  /// Positions held before but not now
+  pub closed: Vec<PositionInfo>,
+  pub transition: HealthTransition,
+}
+
+impl SnapshotDiff {
+  /// Whether the account got closer to or crossed into liquidation
+  pub fn is_deterioration(&self) -> bool {
+    matches!(self.transition, HealthTransition::Deteriorated | HealthTransition::BecameLiquidatable)
+  }
+}
+
+/// Parsed state of an evaluated account, for downstream code to consume and for printing
+#[derive(Clone, Debug)]
+pub struct AccountSnapshot {
+  pub account: Pubkey,
+  pub authority: Pubkey,
+  pub asset_value: I80F48,
+  pub liability_value: I80F48,
+  /// Maintenance buffer in usd under the policy the snapshot was taken with
+  pub maintenance: I80F48,
+  /// Weighted assets over weighted liabilities, `I80F48::MAX` without liabilities
+  pub health_factor: I80F48,
+  /// Asset positions first, then liabilities, each in bank order
+  pub positions: Vec<PositionInfo>,
+}
+
+impl AccountSnapshot {
+  pub fn new(account_pubkey: &Pubkey, account: &MarginfiUserAccount, policy: ValuationPolicy) -> anyhow::Result<Self> {
+    let mut positions = Vec::new();
+    for side in [BalanceSide::Assets, BalanceSide::Liabilities] {
+      for bank_account in account.bank_accounts() {
+        if bank_account.balance.is_empty(side) {
+          continue;
+        }
+        let (amount, value) = match side {
+          BalanceSide::Assets => (
+            bank_account.bank.get_asset_amount(bank_account.balance.asset_shares.into()),
+            bank_account.asset_value()?,
+          ),
+          BalanceSide::Liabilities => (
+            bank_account.bank.get_liability_amount(bank_account.balance.liability_shares.into()),
+            bank_account.liability_value()?,
+          ),
+        };
+        let amount = amount
+          .map(|amount| WrappedI80F48::from(amount).to_ui_string(bank_account.bank.mint_decimals))
+          .context("balance amount calculation failed")?;
+
+        positions.push(PositionInfo {
+          bank: bank_account.balance.bank_pk,
+          mint: bank_account.bank.mint,
+          side,
+          amount,
+          value,
+        });
+      }
+    }
+
+    anyhow::Ok(Self {
+      account: *account_pubkey,
+      authority: account.account().authority,
+      asset_value: account.asset_value()?,
+      liability_value: account.liability_value()?,
+      maintenance: account.maintenance(policy)?,
+      health_factor: account.health_factor(policy)?,
+      positions,
+    })
+  }
+
+  pub fn is_liquidatable(&self) -> bool {
+    self.maintenance < I80F48::ZERO
+  }
+
+  /// Whether the snapshot is printed in full rather than summarized, i.e. its health factor is
+  /// below `threshold` or no threshold is set, see `Config::health_alert_threshold`
+  pub fn is_alert(&self, threshold: Option<f64>) -> bool {
+    threshold.is_none_or(|threshold| self.health_factor < I80F48::from_num(threshold))
+  }
+
+  /// The health factor for printing, "-" without liabilities
+  pub fn health_factor_label(&self) -> String {
+    if self.health_factor == I80F48::MAX {
+      "-".to_string()
+    } else {
+      self.health_factor.to_string()
+    }
+  }
+
+  /// What changed since `prev`, an earlier snapshot of the same account. Health is compared by
+  /// maintenance buffer, so both snapshots should be taken under the same policy.
+  pub fn diff(&self, prev: &AccountSnapshot) -> SnapshotDiff {
+    let holds = |snapshot: &AccountSnapshot, position: &PositionInfo| {
+      snapshot.positions
+        .iter()
+        .any(|held| held.bank == position.bank && held.side == position.side)
+    };
+    let transition = match (prev.is_liquidatable(), self.is_liquidatable()) {
+      (false, true) => HealthTransition::BecameLiquidatable,
+      (true, false) => HealthTransition::Recovered,
+      _ => match self.maintenance.cmp(&prev.maintenance) {
+        std::cmp::Ordering::Equal => HealthTransition::Unchanged,
+        std::cmp::Ordering::Greater => HealthTransition::Improved,
+        std::cmp::Ordering::Less => HealthTransition::Deteriorated,
+      },
+    };
+    let has_liabilities = |snapshot: &AccountSnapshot| snapshot.health_factor != I80F48::MAX;
+
+    SnapshotDiff {
+      health_factor_delta: (has_liabilities(self) && has_liabilities(prev))
+        .then(|| self.health_factor.saturating_sub(prev.health_factor)),
+      maintenance_delta: self.maintenance.saturating_sub(prev.maintenance),
+      opened: self.positions.iter().filter(|position| !holds(prev, position)).cloned().collect(),
+      closed: prev.positions.iter().filter(|position| !holds(self, position)).cloned().collect(),
+      transition,
+    }
+  }
+
+  fn positions_of(&self, side: BalanceSide) -> impl Iterator<Item = &PositionInfo> {
+    self.positions.iter().filter(move |position| position.side == side)
+  }
+
+  /// Machine readable form. `I80F48` values are rendered as decimal strings so no precision is
+  /// lost.
+  pub fn to_json(&self) -> serde_json::Value {
+    let balances: Vec<_> = self.positions
+      .iter()
+      .map(|position| json!({
+        "bank": position.bank.to_string(),
+        "mint": position.mint.to_string(),
+        "side": match position.side {
+          BalanceSide::Assets => "asset",
+          BalanceSide::Liabilities => "liability",
+        },
+        "amount": position.amount,
+        "value": position.value.to_string(),
+      }))
+      .collect();
+
+    json!({
+      "account": self.account.to_string(),
+      "owner": self.authority.to_string(),
+      "asset_value": self.asset_value.to_string(),
+      "liability_value": self.liability_value.to_string(),
+      "maintenance": self.maintenance.to_string(),
+      "liquidatable": self.is_liquidatable(),
+      "balances": balances,
+    })
+  }
+}
+
+impl fmt::Display for AccountSnapshot {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "  Owner: {}", self.authority)?;
+    writeln!(f, "  Lended assets ({}):", format_usd(self.asset_value))?;
+    for position in self.positions_of(BalanceSide::Assets) {
+      writeln!(f, "     Mint: {}", position.mint)?;
+      writeln!(f, "     Balance: {}", position.amount)?;
+    }
+    writeln!(f, "  Borrowed assets ({}):", format_usd(self.liability_value))?;
+    for position in self.positions_of(BalanceSide::Liabilities) {
+      writeln!(f, "     Mint: {}", position.mint)?;
+      writeln!(f, "     Balance: {}", position.amount)?;
+    }
+    match self.maintenance.checked_div(self.asset_value).and_then(|ratio| ratio.checked_mul_int(100)) {
+      Some(percent) => write!(f, "  Maintenance: {} ({}%)", format_usd(self.maintenance), percent),
+      None => write!(f, "  Maintenance: {}", format_usd(self.maintenance)),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::marginfi::fixtures::{bank, bank_account, user_account};
+
+  const ACCOUNT: Pubkey = Pubkey::new_from_array([9; 32]);
+  const COLLATERAL_BANK: Pubkey = Pubkey::new_from_array([1; 32]);
+  const DEBT_BANK: Pubkey = Pubkey::new_from_array([2; 32]);
+
+  /// 100 tokens at $10 deposited against `borrowed` tokens at $1. Maintenance is $800 of weighted
+  /// collateral less 1.2 times the debt, so it's crossed above $666.67 borrowed.
+  fn snapshot(borrowed: f64) -> AccountSnapshot {
+    let mut collateral = bank();
+    collateral.mint = Pubkey::new_from_array([3; 32]);
+    let mut balances = vec![bank_account(COLLATERAL_BANK, collateral, 10.0, 100.0, 0.0)];
+    if borrowed > 0.0 {
+      balances.push(bank_account(DEBT_BANK, bank(), 1.0, 0.0, borrowed));
+    }
+    AccountSnapshot::new(&ACCOUNT, &user_account(balances), ValuationPolicy::PROTOCOL).unwrap()
+  }
+
+  #[test]
+  fn only_accounts_below_the_alert_threshold_alert() {
+    // Health factors of 800 / 600 and 800 / 240
+    let risky = snapshot(500.0);
+    let safe = snapshot(200.0);
+
+    assert!(risky.is_alert(Some(2.0)));
+    assert!(!safe.is_alert(Some(2.0)));
+    assert!(risky.is_alert(None) && safe.is_alert(None));
+  }
+
+  #[test]
+  fn snapshot_of_a_synthetic_account() {
+    let snapshot = snapshot(500.0);
+
+    assert_eq!(snapshot.account, ACCOUNT);
+    assert_eq!(snapshot.asset_value, I80F48::from_num(1_000));
+    assert_eq!(snapshot.liability_value, I80F48::from_num(500));
+    assert!(!snapshot.is_liquidatable());
+    assert_eq!(snapshot.positions.len(), 2);
+    let collateral = &snapshot.positions[0];
+    assert_eq!(collateral.bank, COLLATERAL_BANK);
+    assert_eq!(collateral.mint, Pubkey::new_from_array([3; 32]));
+    assert_eq!(collateral.side, BalanceSide::Assets);
+    assert_eq!(collateral.amount, "100");
+    assert_eq!(collateral.value, I80F48::from_num(1_000));
+    let debt = &snapshot.positions[1];
+    assert_eq!(debt.bank, DEBT_BANK);
+    assert_eq!(debt.side, BalanceSide::Liabilities);
+    assert_eq!(debt.amount, "500");
+    assert_eq!(debt.value, I80F48::from_num(500));
+  }
+
+  #[test]
+  fn snapshot_without_liabilities_has_no_health_factor() {
+    let snapshot = snapshot(0.0);
+
+    assert_eq!(snapshot.health_factor_label(), "-");
+    assert_eq!(snapshot.positions.len(), 1);
+  }
+
+  #[test]
+  fn same_state_is_unchanged() {
+    let diff = snapshot(500.0).diff(&snapshot(500.0));
+
+    assert_eq!(diff.transition, HealthTransition::Unchanged);
+    assert_eq!(diff.maintenance_delta, I80F48::ZERO);
+    assert_eq!(diff.health_factor_delta, Some(I80F48::ZERO));
+    assert!(diff.opened.is_empty() && diff.closed.is_empty());
+    assert!(!diff.is_deterioration());
+  }
+
+  #[test]
+  fn repaid_debt_is_an_improvement() {
+    let diff = snapshot(300.0).diff(&snapshot(500.0));
+
+    assert_eq!(diff.transition, HealthTransition::Improved);
+    assert_eq!(diff.maintenance_delta, I80F48::from_num(240));
+    assert!(diff.health_factor_delta.unwrap() > I80F48::ZERO);
+    assert!(!diff.is_deterioration());
+  }
+
+  #[test]
+  fn more_debt_above_maintenance_deteriorates() {
+    let diff = snapshot(600.0).diff(&snapshot(500.0));
+
+    assert_eq!(diff.transition, HealthTransition::Deteriorated);
+    assert_eq!(diff.maintenance_delta, I80F48::from_num(-120));
+    assert!(diff.health_factor_delta.unwrap() < I80F48::ZERO);
+    assert!(diff.is_deterioration());
+  }
+
+  #[test]
+  fn crossing_maintenance_is_reported_both_ways() {
+    let crossed = snapshot(700.0).diff(&snapshot(600.0));
+    assert_eq!(crossed.transition, HealthTransition::BecameLiquidatable);
+    assert!(crossed.is_deterioration());
+
+    let recovered = snapshot(600.0).diff(&snapshot(700.0));
+    assert_eq!(recovered.transition, HealthTransition::Recovered);
+    assert!(!recovered.is_deterioration());
+  }
+
+  #[test]
+  fn new_borrow_is_an_opened_position() {
+    let diff = snapshot(500.0).diff(&snapshot(0.0));
+
+    assert_eq!(diff.opened.iter().map(|position| position.bank).collect::<Vec<_>>(), vec![DEBT_BANK]);
+    assert!(diff.closed.is_empty());
+    assert_eq!(diff.health_factor_delta, None);
+
+    let repaid = snapshot(0.0).diff(&snapshot(500.0));
+    assert_eq!(repaid.closed.iter().map(|position| position.bank).collect::<Vec<_>>(), vec![DEBT_BANK]);
+  }
+}
@@ -0,0 +1,155 @@
+use std::collections::{HashMap, HashSet};
+use std::str::FromStr;
+use std::time::{Duration, Instant};
+
+use anchor_client::solana_sdk::commitment_config::CommitmentConfig;
+use anchor_client::solana_sdk::signature::Signature;
+use anchor_lang::prelude::Pubkey;
+use solana_rpc_client::rpc_client::GetConfirmedSignaturesForAddress2Config;
+use solana_rpc_client_types::config::RpcTransactionConfig;
+use solana_rpc_client_types::response::{Response, RpcLogsResponse, RpcResponseContext};
+use solana_transaction_status_client_types::UiTransactionEncoding;
+use tokio::time::Interval;
+
+use super::{ListenEnd, Marginfi};
+
+/// Most signatures fetched per poll, anything older is dropped if the bot falls this far behind
+const POLL_SIGNATURE_LIMIT: usize = 1_000;
+
+impl Marginfi {
+  /// Fetches the transactions of the configured programs periodically instead of subscribing to
+  /// their logs, feeding them through the same pipeline. Runs for `duration`, or until shutdown if
+  /// `None`. `last_seen` holds the newest signature already processed per program and is advanced
+  /// as polling goes.
+  pub(super) async fn poll_for_targets(
+    &self,
+    sweep: &mut Interval,
+    scan: &mut Interval,
+    last_seen: &mut HashMap<Pubkey, Signature>,
+    duration: Option<Duration>,
+  ) -> anyhow::Result<ListenEnd> {
+    let deadline = duration.map(|duration| Instant::now() + duration);
+    let sweep_enabled = self.config.sweep_interval_secs > 0;
+    let scan_enabled = self.config.scan_interval_secs > 0;
+    let mut poll = tokio::time::interval(Duration::from_secs(self.config.poll_interval_secs.max(1)));
+
+    tracing::info!(interval_secs = self.config.poll_interval_secs.max(1), "polling for liquidation events");
+
+    while deadline.is_none_or(|deadline| Instant::now() < deadline) {
+      tokio::select! {
+        _ = poll.tick() => {
+          let notifications = match self.fetch_new_notifications(last_seen).await {
+            Ok(notifications) => {
+              self.watchdog.touch();
+              notifications
+            }
+            Err(err) => {
+              tracing::warn!(error = %format!("{:#}", err), "poll failed");
+              continue;
+            }
+          };
+          for notification in notifications {
+            self.ingest(notification).await?;
+          }
+        }
+        _ = sweep.tick(), if sweep_enabled => {
+          self.sweep_tracked().await;
+        }
+        _ = scan.tick(), if scan_enabled => {
+          self.run_scan().await;
+        }
+        _ = self.shutdown.requested() => {
+          return anyhow::Ok(ListenEnd::Shutdown);
+        }
+      }
+    }
+
+    anyhow::Ok(ListenEnd::Closed)
+  }
+
+  /// Successful transactions of every configured program newer than its `last_seen` signature,
+  /// oldest first, shaped like log notifications. A transaction mentioning several of the programs
+  /// is returned once.
+  async fn fetch_new_notifications(&self, last_seen: &mut HashMap<Pubkey, Signature>) -> anyhow::Result<Vec<Response<RpcLogsResponse>>> {
+    let mut notifications = Vec::new();
+    let mut signatures = HashSet::new();
+    let mut newest = Vec::with_capacity(self.config.program_ids.len());
+    for program_id in &self.config.program_ids {
+      let program_last_seen = last_seen.get(program_id).copied();
+      let (fetched, program_newest) = self.fetch_program_notifications(program_id, program_last_seen, &mut signatures).await?;
+      notifications.extend(fetched);
+      newest.extend(program_newest.map(|signature| (*program_id, signature)));
+    }
+    // Only advanced once every program was fetched, a failed poll is retried as a whole
+    last_seen.extend(newest);
+    notifications.sort_by_key(|notification| notification.context.slot);
+
+    anyhow::Ok(notifications)
+  }
+
+  /// Successful transactions of `program_id` newer than `last_seen`, oldest first, with the newest
+  /// signature seen. Without a `last_seen` only the starting point is returned. Transactions
+  /// already in `signatures` are skipped, the returned ones are added to it.
+  async fn fetch_program_notifications(
+    &self,
+    program_id: &Pubkey,
+    last_seen: Option<Signature>,
+    signatures: &mut HashSet<String>,
+  ) -> anyhow::Result<(Vec<Response<RpcLogsResponse>>, Option<Signature>)> {
+    // Signature and transaction lookups don't support `processed`
+    let commitment = Some(match self.rpc_client.commitment() {
+      commitment if commitment.is_at_least_confirmed() => commitment,
+      _ => CommitmentConfig::confirmed(),
+    });
+    let statuses = self.rpc_client
+      .get_signatures_for_address_with_config(
+        program_id,
+        GetConfirmedSignaturesForAddress2Config {
+          before: None,
+          until: last_seen,
+          limit: Some(POLL_SIGNATURE_LIMIT),
+          commitment,
+        },
+      )
+      .await?;
+    let Some(newest) = statuses.first() else {
+      return anyhow::Ok((Vec::new(), None));
+    };
+    let newest = Signature::from_str(&newest.signature)?;
+    if last_seen.is_none() {
+      return anyhow::Ok((Vec::new(), Some(newest)));
+    }
+
+    let mut notifications = Vec::new();
+    for status in statuses.into_iter().rev() {
+      if status.err.is_some() || !signatures.insert(status.signature.clone()) {
+        continue;
+      }
+      let signature = Signature::from_str(&status.signature)?;
+      let transaction = self.rpc_client
+        .get_transaction_with_config(
+          &signature,
+          RpcTransactionConfig {
+            encoding: Some(UiTransactionEncoding::Base64),
+            commitment,
+            max_supported_transaction_version: Some(0),
+          },
+        )
+        .await?;
+      let Some(meta) = transaction.transaction.meta else {
+        continue;
+      };
+      let logs: Option<Vec<String>> = meta.log_messages.into();
+      notifications.push(Response {
+        context: RpcResponseContext::new(transaction.slot),
+        value: RpcLogsResponse {
+          signature: status.signature,
+          err: meta.err,
+          logs: logs.unwrap_or_default(),
+        },
+      });
+    }
+
+    anyhow::Ok((notifications, Some(newest)))
+  }
+}
@@ -0,0 +1,191 @@
+use std::collections::{HashMap, HashSet};
+
+use anchor_lang::prelude::{Pubkey, ToAccountMetas};
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::InstructionData;
+
+use crate::consts::MARGINFI_PROGRAM_ID;
+use crate::utils::parse_account;
+
+use super::transactions::{with_compute_budget, MAX_COMPUTE_UNIT_LIMIT};
+use super::types::{get_oracle_keys_for_bank, Bank, MarginfiAccount};
+use super::{parse_anchor_event, HealthCache, HealthPulseEvent, Marginfi, PulseHealth, PulseHealthAccounts};
+
+/// Most accounts `getMultipleAccounts` returns per call
+const MAX_MULTIPLE_ACCOUNTS: usize = 100;
+/// Most distinct accounts a legacy transaction referencing them fits in 1232 bytes
+const MAX_PULSE_TX_ACCOUNTS: usize = 30;
+/// Most pulse instructions packed in one transaction, keeps it under the compute ceiling for
+/// typical accounts. Batches that still exceed it are split further.
+const MAX_PULSES_PER_TX: usize = 8;
+
+impl Marginfi {
+  /// Simulates `lending_account_pulse_health` for many accounts, packing several per transaction,
+  /// and returns the program's own health cache of each. Accounts that fail to load or whose
+  /// pulse fails even when simulated alone are left out of the result.
+  pub async fn pulse_health_batch(&self, accounts: &[Pubkey]) -> anyhow::Result<HashMap<Pubkey, HealthCache>> {
+    let instructions = self.pulse_health_instructions(accounts).await?;
+
+    let mut pending = chunk_by_accounts(instructions);
+    let mut caches = HashMap::new();
+    while let Some(chunk) = pending.pop() {
+      let simulated = with_compute_budget(
+        chunk.iter().map(|(_, ix)| ix.clone()).collect(),
+        MAX_COMPUTE_UNIT_LIMIT,
+        self.config.compute_unit_price_micro_lamports,
+      );
+
+      let outcome = self.simulate_instructions(&simulated).await?;
+      if let Some(err) = outcome.err {
+        // Too much compute, or one bad account fails the whole transaction: split and retry
+        if chunk.len() > 1 {
+          let mut chunk = chunk;
+          let second = chunk.split_off(chunk.len() / 2);
+          pending.push(chunk);
+          pending.push(second);
+        } else {
+          tracing::warn!(account = %chunk[0].0, error = %err, "health pulse failed");
+        }
+        continue;
+      }
+
+      for event_data in outcome.logs.iter().filter_map(|log| log.strip_prefix("Program data: ")) {
+        if let Ok(event) = parse_anchor_event::<HealthPulseEvent>(event_data) {
+          caches.insert(event.account, event.health_cache);
+        }
+      }
+    }
+
+    anyhow::Ok(caches)
+  }
+
+  /// One pulse instruction per loadable account, each followed by its banks and their oracles in
+  /// balance order, as the risk engine expects
+  async fn pulse_health_instructions(&self, accounts: &[Pubkey]) -> anyhow::Result<Vec<(Pubkey, Instruction)>> {
+    let mut marginfi_accounts = Vec::with_capacity(accounts.len());
+    for chunk in accounts.chunks(MAX_MULTIPLE_ACCOUNTS) {
+      let fetched = self.rpc_client.get_multiple_accounts(chunk).await?;
+      for (pubkey, account) in chunk.iter().zip(fetched) {
+        let parsed = account
+          .ok_or_else(|| "account not found".into())
+          .and_then(|account| parse_account::<MarginfiAccount>(&account.data));
+        match parsed {
+          Ok(marginfi_account) => marginfi_accounts.push((*pubkey, marginfi_account)),
+          Err(err) => tracing::warn!(account = %pubkey, error = %err, "skipped health pulse"),
+        }
+      }
+    }
+
+    let bank_pubkeys: Vec<Pubkey> = marginfi_accounts
+      .iter()
+      .flat_map(|(_, account)| account.lending_account.get_active_balances_iter().map(|balance| balance.bank_pk))
+      .collect::<HashSet<_>>()
+      .into_iter()
+      .collect();
+    let mut banks = HashMap::with_capacity(bank_pubkeys.len());
+    for chunk in bank_pubkeys.chunks(MAX_MULTIPLE_ACCOUNTS) {
+      let fetched = self.rpc_client.get_multiple_accounts(chunk).await?;
+      for (pubkey, account) in chunk.iter().zip(fetched) {
+        if let Some(bank) = account.and_then(|account| parse_account::<Bank>(&account.data).ok()) {
+          banks.insert(*pubkey, bank);
+        }
+      }
+    }
+
+    let mut instructions = Vec::with_capacity(marginfi_accounts.len());
+    'accounts: for (pubkey, account) in marginfi_accounts {
+      let mut metas = PulseHealthAccounts { marginfi_account: pubkey }.to_account_metas(None);
+      for balance in account.lending_account.get_active_balances_iter() {
+        let Some(bank) = banks.get(&balance.bank_pk) else {
+          tracing::warn!(account = %pubkey, bank = %balance.bank_pk, "skipped health pulse, bank failed to load");
+          continue 'accounts;
+        };
+        let oracle_keys = match get_oracle_keys_for_bank(bank) {
+          Ok(oracle_keys) => oracle_keys,
+          Err(err) => {
+            tracing::warn!(account = %pubkey, error = %err, "skipped health pulse");
+            continue 'accounts;
+          }
+        };
+        metas.push(AccountMeta::new_readonly(balance.bank_pk, false));
+        metas.extend(oracle_keys.into_iter().map(|key| AccountMeta::new_readonly(key, false)));
+      }
+
+      instructions.push((pubkey, Instruction {
+        program_id: MARGINFI_PROGRAM_ID,
+        accounts: metas,
+        data: PulseHealth.data(),
+      }));
+    }
+
+    anyhow::Ok(instructions)
+  }
+}
+
+/// Greedily packs instructions into transactions within `MAX_PULSES_PER_TX` and
+/// `MAX_PULSE_TX_ACCOUNTS` (counting the payer and program)
+fn chunk_by_accounts(instructions: Vec<(Pubkey, Instruction)>) -> Vec<Vec<(Pubkey, Instruction)>> {
+  let mut chunks = Vec::new();
+  let mut chunk: Vec<(Pubkey, Instruction)> = Vec::new();
+  let mut keys: HashSet<Pubkey> = HashSet::new();
+
+  for (pubkey, ix) in instructions {
+    let new_keys = ix.accounts.iter().filter(|meta| !keys.contains(&meta.pubkey)).count();
+    let full = chunk.len() >= MAX_PULSES_PER_TX || keys.len() + new_keys + 2 > MAX_PULSE_TX_ACCOUNTS;
+    if full && !chunk.is_empty() {
+      chunks.push(std::mem::take(&mut chunk));
+      keys.clear();
+    }
+    keys.extend(ix.accounts.iter().map(|meta| meta.pubkey));
+    chunk.push((pubkey, ix));
+  }
+  if !chunk.is_empty() {
+    chunks.push(chunk);
+  }
+
+  chunks
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  /// A pulse of a new account referencing `shared` and `own` other new accounts
+  fn pulse(shared: &[Pubkey], own: usize) -> (Pubkey, Instruction) {
+    let account = Pubkey::new_unique();
+    let mut metas = PulseHealthAccounts { marginfi_account: account }.to_account_metas(None);
+    metas.extend(shared.iter().map(|key| AccountMeta::new_readonly(*key, false)));
+    metas.extend((0..own).map(|_| AccountMeta::new_readonly(Pubkey::new_unique(), false)));
+    (account, Instruction { program_id: MARGINFI_PROGRAM_ID, accounts: metas, data: PulseHealth.data() })
+  }
+
+  fn chunk_sizes(instructions: Vec<(Pubkey, Instruction)>) -> Vec<usize> {
+    chunk_by_accounts(instructions).iter().map(Vec::len).collect()
+  }
+
+  #[test]
+  fn pulses_are_packed_up_to_the_instruction_limit() {
+    // Accounts sharing their bank and oracle add one key each
+    let bank = [Pubkey::new_unique(), Pubkey::new_unique()];
+    let instructions = (0..MAX_PULSES_PER_TX + 3).map(|_| pulse(&bank, 0)).collect();
+
+    assert_eq!(chunk_sizes(instructions), vec![MAX_PULSES_PER_TX, 3]);
+  }
+
+  #[test]
+  fn pulses_are_split_before_exceeding_the_account_limit() {
+    // 10 keys each: two pulses take 22 keys with the payer and program, a third would take 32
+    let instructions = (0..4).map(|_| pulse(&[], 9)).collect();
+
+    assert_eq!(chunk_sizes(instructions), vec![2, 2]);
+  }
+
+  #[test]
+  fn oversized_pulse_gets_its_own_transaction() {
+    let instructions = vec![pulse(&[], 1), pulse(&[], MAX_PULSE_TX_ACCOUNTS), pulse(&[], 1)];
+
+    assert_eq!(chunk_sizes(instructions), vec![1, 1, 1]);
+  }
+}
@@ -0,0 +1,147 @@
+//! Loads a liquidatable account through the same rpc path as the bot, from account bytes served
+//! by a mocked rpc client, and checks its health against the program's `HealthCache`.
+//!
+//! The liquidatee, its SOL and USDC banks and their pyth feeds are rebuilt in memory in the shape
+//! of a mainnet liquidation: interest has accrued on both banks, so asset and liability share
+//! values differ and valuing liabilities with `get_asset_amount` turns the account healthy.
+//!
+//! Refreshing from mainnet: dump the liquidatee, its banks and their oracles at the slot before
+//! the liquidation from an archival rpc, e.g. `solana account <pubkey> --output json`, and serve
+//! their base64 data in place of the builders below. The liquidatee's `health_cache` is then the
+//! program's own valuation at that slot.
+
+use anchor_lang::prelude::{Clock, Pubkey};
+use anchor_lang::{AnchorSerialize, Discriminator};
+use bytemuck::Zeroable;
+use fixed::types::I80F48;
+use pyth_solana_receiver_sdk::price_update::{PriceFeedMessage, PriceUpdateV2, VerificationLevel};
+use serde_json::json;
+use solana_account::Account;
+use solana_rpc_client::mock_sender::MocksMap;
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+use solana_rpc_client_types::request::RpcRequest;
+
+use crate::consts::MARGINFI_PROGRAM_ID;
+
+use super::fixtures::{self, clock_response, rpc_account, response, ui_account, zero_copy, GROUP};
+use super::types::{Balance, Bank, MarginfiAccount, OracleSetup};
+use super::{MarginfiUserAccount, ValuationPolicy};
+
+const SLOT: u64 = 310_000_000;
+const PUBLISH_TIME: i64 = 1_735_000_000;
+
+const LIQUIDATEE: Pubkey = Pubkey::new_from_array([10; 32]);
+const SOL_BANK: Pubkey = Pubkey::new_from_array([11; 32]);
+const USDC_BANK: Pubkey = Pubkey::new_from_array([12; 32]);
+const SOL_ORACLE: Pubkey = Pubkey::new_from_array([13; 32]);
+const USDC_ORACLE: Pubkey = Pubkey::new_from_array([14; 32]);
+
+/// A bank priced by the pyth push feed at `oracle`, with interest accrued into its share values
+fn bank(mint_decimals: u8, oracle: Pubkey, asset_share_value: f64, liability_share_value: f64) -> Bank {
+  let mut bank = fixtures::bank();
+  bank.mint_decimals = mint_decimals;
+  bank.asset_share_value = I80F48::from_num(asset_share_value).into();
+  bank.liability_share_value = I80F48::from_num(liability_share_value).into();
+  bank.config.oracle_setup = OracleSetup::PythPushOracle;
+  bank.config.oracle_keys[0] = oracle;
+  bank
+}
+
+fn sol_bank() -> Bank {
+  bank(9, SOL_ORACLE, 1.1, 1.15)
+}
+
+fn usdc_bank() -> Bank {
+  bank(6, USDC_ORACLE, 1.05, 1.25)
+}
+
+/// 10 SOL worth of shares deposited against 1000 USDC worth of shares borrowed, with the health
+/// cache the program wrote for them
+fn liquidatee() -> MarginfiAccount {
+  let mut account = MarginfiAccount::zeroed();
+  account.group = GROUP;
+  let mut balance = |slot: usize, bank_pk: Pubkey, asset_shares: f64, liability_shares: f64| {
+    let mut balance = Balance::zeroed();
+    balance.set_active(true);
+    balance.bank_pk = bank_pk;
+    balance.asset_shares = I80F48::from_num(asset_shares).into();
+    balance.liability_shares = I80F48::from_num(liability_shares).into();
+    account.lending_account.balances[slot] = balance;
+  };
+  balance(0, SOL_BANK, 10e9, 0.0);
+  balance(1, USDC_BANK, 0.0, 1_000e6);
+
+  // 11 SOL at $150 less 2.12 * $0.05 of confidence, weighted 0.8
+  account.health_cache.asset_value_maint = I80F48::from_num(1_319.0672).into();
+  // 1250 USDC at $1 plus 2.12 * $0.0001 of confidence, weighted 1.2
+  account.health_cache.liability_value_maint = I80F48::from_num(1_500.318).into();
+  account
+}
+
+/// A full-verified pyth update quoting `price` and `conf` scaled by 1e-8
+fn price_update(price: i64, conf: u64) -> PriceUpdateV2 {
+  PriceUpdateV2 {
+    write_authority: Pubkey::default(),
+    verification_level: VerificationLevel::Full,
+    price_message: PriceFeedMessage {
+      feed_id: [0; 32],
+      price,
+      conf,
+      exponent: -8,
+      publish_time: PUBLISH_TIME,
+      prev_publish_time: PUBLISH_TIME - 1,
+      ema_price: price,
+      ema_conf: conf,
+    },
+    posted_slot: SLOT,
+  }
+}
+
+fn pyth(update: PriceUpdateV2) -> Account {
+  let mut data = PriceUpdateV2::DISCRIMINATOR.to_vec();
+  update.serialize(&mut data).unwrap();
+  rpc_account(pyth_solana_receiver_sdk::ID, data)
+}
+
+/// Serves the liquidatee, then its banks, the clock and the oracles, in the order
+/// `MarginfiUserAccount::from_pubkey` asks for them. Any other request fails.
+fn mocked_rpc() -> RpcClient {
+  let banks = [(SOL_BANK, sol_bank()), (USDC_BANK, usdc_bank())]
+    .iter()
+    .map(|(pubkey, bank)| ui_account(pubkey, &rpc_account(MARGINFI_PROGRAM_ID, zero_copy(bank))))
+    .collect::<Vec<_>>();
+  let oracles = [(SOL_ORACLE, price_update(15_000_000_000, 5_000_000)), (USDC_ORACLE, price_update(100_000_000, 10_000))]
+    .into_iter()
+    .map(|(pubkey, update)| ui_account(&pubkey, &pyth(update)))
+    .collect::<Vec<_>>();
+
+  let mocks: MocksMap = [
+    (RpcRequest::GetAccountInfo, response(ui_account(&LIQUIDATEE, &rpc_account(MARGINFI_PROGRAM_ID, zero_copy(&liquidatee()))))),
+    (RpcRequest::GetMultipleAccounts, response(json!(banks))),
+    (RpcRequest::GetAccountInfo, clock_response(&Clock { slot: SLOT, unix_timestamp: PUBLISH_TIME + 5, ..Clock::default() })),
+    (RpcRequest::GetMultipleAccounts, response(json!(oracles))),
+  ]
+  .into_iter()
+  .collect();
+  RpcClient::new_mock_with_mocks_map("fails", mocks)
+}
+
+#[tokio::test]
+async fn maintenance_matches_the_program_health_cache() {
+  let account = MarginfiUserAccount::from_pubkey(&mocked_rpc(), &LIQUIDATEE).await.unwrap();
+  let cache = account.account().health_cache;
+  let expected = I80F48::from(cache.asset_value_maint) - I80F48::from(cache.liability_value_maint);
+
+  let maintenance = account.maintenance(ValuationPolicy::PROTOCOL).unwrap();
+  assert!((maintenance - expected).abs() < I80F48::from_num(1e-6), "{} != {}", maintenance, expected);
+  assert!(account.is_liquidatable(ValuationPolicy::PROTOCOL).unwrap());
+}
+
+#[tokio::test]
+async fn liabilities_are_valued_at_the_liability_share_value() {
+  let account = MarginfiUserAccount::from_pubkey(&mocked_rpc(), &LIQUIDATEE).await.unwrap();
+  let usdc = &account.bank_accounts()[1];
+
+  // 1000 shares at 1.25, not at the 1.05 asset share value
+  assert_eq!(usdc.liability_amount_native().unwrap(), 1_250_000_000);
+}
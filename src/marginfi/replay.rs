@@ -0,0 +1,59 @@
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::Context;
+use solana_rpc_client_types::response::{Response, RpcLogsResponse};
+
+use super::Marginfi;
+
+/// Appends every log notification to a capture file, one JSON object per line
+pub struct NotificationRecorder {
+  writer: Mutex<BufWriter<File>>,
+}
+
+impl NotificationRecorder {
+  pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+    let path = path.as_ref();
+    let file = OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(path)
+      .with_context(|| format!("failed to open capture file {}", path.display()))?;
+
+    anyhow::Ok(Self { writer: Mutex::new(BufWriter::new(file)) })
+  }
+
+  pub fn record(&self, response: &Response<RpcLogsResponse>) -> anyhow::Result<()> {
+    let mut writer = self.writer.lock().unwrap();
+    serde_json::to_writer(&mut *writer, response).context("failed to serialize notification")?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+
+    anyhow::Ok(())
+  }
+}
+
+impl Marginfi {
+  /// Feeds notifications recorded by `NotificationRecorder` through `process_notification`, so the
+  /// event pipeline can be exercised without a live node.
+  pub async fn replay_from_file(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let file = File::open(path)
+      .with_context(|| format!("failed to open capture file {}", path.display()))?;
+
+    tracing::info!(path = %path.display(), "replaying notifications");
+    for (i, line) in BufReader::new(file).lines().enumerate() {
+      let line = line?;
+      if line.trim().is_empty() {
+        continue;
+      }
+      let response: Response<RpcLogsResponse> = serde_json::from_str(&line)
+        .with_context(|| format!("invalid notification on line {}", i + 1))?;
+      self.process_notification(response).await?;
+    }
+
+    anyhow::Ok(())
+  }
+}
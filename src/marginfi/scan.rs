@@ -0,0 +1,243 @@
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use anchor_lang::{prelude::Pubkey, Discriminator};
+use anyhow::Context;
+use solana_account_decoder::UiAccountEncoding;
+use solana_rpc_client_types::config::{RpcAccountInfoConfig, RpcProgramAccountsConfig};
+use solana_rpc_client_types::filter::{Memcmp, RpcFilterType};
+
+use crate::consts::MARGINFI_PROGRAM_ID;
+use crate::utils::parse_account;
+
+use super::output::AccountSnapshot;
+use super::types::{Bank, MarginfiAccount};
+use super::Marginfi;
+
+// Byte offsets within a marginfi account's data, discriminator included:
+// * 0..8     anchor discriminator
+// * 8..40    group
+// * 40..72   authority
+// * 72..1736 16 balances of 104 bytes each, a balance's liability shares at +56..+72
+//
+// and within a bank's data:
+// * 0..8     anchor discriminator
+// * 8..40    mint
+// * 40       mint decimals
+// * 41..73   group
+//
+// Whether any liability share is non-zero can't be expressed with memcmp (it only matches exact
+// bytes), so the RPC narrows by account type and group and liabilities are checked locally.
+const GROUP_OFFSET: usize = 8;
+const BANK_GROUP_OFFSET: usize = 41;
+
+/// `getProgramAccounts` filters matching marginfi accounts, of `group` if given
+pub fn marginfi_account_filters(group: Option<&Pubkey>) -> Vec<RpcFilterType> {
+  let mut filters = vec![
+    RpcFilterType::DataSize((8 + MarginfiAccount::LEN) as u64),
+    RpcFilterType::Memcmp(Memcmp::new_base58_encoded(0, MarginfiAccount::DISCRIMINATOR)),
+  ];
+  if let Some(group) = group {
+    filters.push(RpcFilterType::Memcmp(Memcmp::new_base58_encoded(GROUP_OFFSET, group.as_ref())));
+  }
+  filters
+}
+
+/// `getProgramAccounts` filters matching banks, of `group` if given
+pub fn bank_filters(group: Option<&Pubkey>) -> Vec<RpcFilterType> {
+  let mut filters = vec![
+    RpcFilterType::DataSize((8 + Bank::LEN) as u64),
+    RpcFilterType::Memcmp(Memcmp::new_base58_encoded(0, Bank::DISCRIMINATOR)),
+  ];
+  if let Some(group) = group {
+    filters.push(RpcFilterType::Memcmp(Memcmp::new_base58_encoded(BANK_GROUP_OFFSET, group.as_ref())));
+  }
+  filters
+}
+
+impl Marginfi {
+  /// All banks of the watched groups
+  pub async fn scan_banks(&self) -> anyhow::Result<Vec<(Pubkey, Bank)>> {
+    let mut banks = Vec::new();
+    for group in self.scanned_groups() {
+      let scanned = self.rpc_client
+        .get_program_accounts_with_config(&MARGINFI_PROGRAM_ID, scan_config(bank_filters(group)))
+        .await
+        .context("bank scan failed")?;
+
+      banks.extend(scanned.into_iter().filter_map(|(pubkey, account)| {
+        parse_account::<Bank>(&account.data).ok().map(|bank| (pubkey, bank))
+      }));
+    }
+
+    anyhow::Ok(banks)
+  }
+
+  /// Logs how many banks use each oracle setup and how many of them can't be priced, as any
+  /// account holding one of those can't be evaluated
+  pub async fn report_oracle_coverage(&self) -> anyhow::Result<()> {
+    let banks = self.scan_banks().await?;
+
+    let mut by_setup: BTreeMap<String, (usize, bool)> = BTreeMap::new();
+    for (_, bank) in &banks {
+      let setup = bank.config.oracle_setup;
+      by_setup.entry(format!("{:?}", setup)).or_insert((0, setup.is_supported())).0 += 1;
+    }
+    let unsupported: usize = by_setup
+      .values()
+      .filter(|(_, supported)| !supported)
+      .map(|(count, _)| count)
+      .sum();
+
+    tracing::info!(banks = banks.len(), "oracle coverage");
+    for (setup, (count, supported)) in &by_setup {
+      if *supported {
+        tracing::info!(setup = %setup, banks = count, "oracle setup");
+      } else {
+        tracing::warn!(setup = %setup, banks = count, "unsupported oracle setup");
+      }
+    }
+    if unsupported > 0 {
+      tracing::warn!(
+        unsupported,
+        banks = banks.len(),
+        "banks can't be priced, accounts holding them will fail to evaluate"
+      );
+    }
+
+    anyhow::Ok(())
+  }
+
+  fn scanned_groups(&self) -> Vec<Option<&Pubkey>> {
+    if self.config.groups.is_empty() {
+      vec![None]
+    } else {
+      self.config.groups.iter().map(Some).collect()
+    }
+  }
+
+  /// All marginfi accounts of the watched groups that borrow something, pure depositors can never
+  /// be liquidated
+  pub async fn scan_accounts_with_liabilities(&self) -> anyhow::Result<Vec<(Pubkey, MarginfiAccount)>> {
+    let mut accounts = Vec::new();
+    for group in self.scanned_groups() {
+      let scanned = self.rpc_client
+        .get_program_accounts_with_config(&MARGINFI_PROGRAM_ID, scan_config(marginfi_account_filters(group)))
+        .await
+        .context("marginfi account scan failed")?;
+
+      for (pubkey, account) in scanned {
+        let Ok(marginfi_account) = parse_account::<MarginfiAccount>(&account.data) else {
+          continue;
+        };
+        if marginfi_account.lending_account.has_liabilities() {
+          accounts.push((pubkey, marginfi_account));
+        }
+      }
+    }
+
+    anyhow::Ok(accounts)
+  }
+
+  /// Evaluates every borrowing account of the watched groups (see
+  /// `scan_accounts_with_liabilities`) and returns the snapshots of those below maintenance.
+  /// Liquidatable accounts are acted on like any other evaluation. Accounts that fail to load
+  /// are reported and skipped. `getProgramAccounts` isn't paginated, so groups are scanned one
+  /// request each to keep responses within the RPC's size limit.
+  pub async fn scan_all_accounts(&self) -> anyhow::Result<Vec<AccountSnapshot>> {
+    let accounts = self.scan_accounts_with_liabilities().await?;
+    let liquidatable = Mutex::new(Vec::new());
+
+    let targets = futures::stream::iter(accounts.into_iter().map(|(pubkey, _)| pubkey));
+    futures::StreamExt::for_each_concurrent(targets, self.concurrency.max(), |pubkey| {
+      let liquidatable = &liquidatable;
+      async move {
+        let _permit = self.concurrency.acquire().await;
+        match self.handle_account(&pubkey, None, None).await {
+          Ok(Some(snapshot)) if snapshot.is_liquidatable() => liquidatable.lock().unwrap().push(snapshot),
+          Ok(_) => {}
+          Err(err) => {
+            if let Err(err) = self.report_account_error(&pubkey, err) {
+              tracing::warn!(account = %pubkey, error = %err, "failed to evaluate");
+            }
+          }
+        }
+      }
+    })
+    .await;
+
+    anyhow::Ok(liquidatable.into_inner().unwrap())
+  }
+
+  /// Runs `scan_all_accounts` and logs what it found, see `Config::scan_interval_secs`
+  pub(super) async fn run_scan(&self) {
+    tracing::info!("scan started");
+    match self.scan_all_accounts().await {
+      Ok(liquidatable) => tracing::info!(liquidatable = liquidatable.len(), "scan done"),
+      Err(err) => tracing::warn!(error = %format!("{:#}", err), "scan failed"),
+    }
+  }
+}
+
+fn scan_config(filters: Vec<RpcFilterType>) -> RpcProgramAccountsConfig {
+  RpcProgramAccountsConfig {
+    filters: Some(filters),
+    account_config: RpcAccountInfoConfig {
+      encoding: Some(UiAccountEncoding::Base64),
+      ..RpcAccountInfoConfig::default()
+    },
+    ..RpcProgramAccountsConfig::default()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use bytemuck::Zeroable;
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::marginfi::fixtures::{bank, zero_copy, GROUP};
+
+  /// Whether the rpc would return an account holding `data` for `filters`
+  fn matches(filters: &[RpcFilterType], data: &[u8]) -> bool {
+    filters.iter().all(|filter| match filter {
+      RpcFilterType::DataSize(size) => *size == data.len() as u64,
+      RpcFilterType::Memcmp(memcmp) => memcmp.bytes_match(data),
+      other => panic!("unexpected filter {:?}", other),
+    })
+  }
+
+  fn marginfi_account() -> Vec<u8> {
+    let mut account = MarginfiAccount::zeroed();
+    account.group = GROUP;
+    zero_copy(&account)
+  }
+
+  #[test]
+  fn account_filters_are_the_discriminator_and_data_size() {
+    assert_eq!(marginfi_account_filters(None), vec![
+      RpcFilterType::DataSize((8 + MarginfiAccount::LEN) as u64),
+      RpcFilterType::Memcmp(Memcmp::new_base58_encoded(0, MarginfiAccount::DISCRIMINATOR)),
+    ]);
+    assert!(matches(&marginfi_account_filters(None), &marginfi_account()));
+  }
+
+  #[test]
+  fn account_filters_match_the_group() {
+    assert!(matches(&marginfi_account_filters(Some(&GROUP)), &marginfi_account()));
+    assert!(!matches(&marginfi_account_filters(Some(&Pubkey::new_unique())), &marginfi_account()));
+  }
+
+  #[test]
+  fn account_filters_skip_banks() {
+    assert!(!matches(&marginfi_account_filters(None), &zero_copy(&bank())));
+    assert!(!matches(&bank_filters(None), &marginfi_account()));
+  }
+
+  #[test]
+  fn bank_filters_match_the_group() {
+    assert!(matches(&bank_filters(None), &zero_copy(&bank())));
+    assert!(matches(&bank_filters(Some(&GROUP)), &zero_copy(&bank())));
+    assert!(!matches(&bank_filters(Some(&Pubkey::new_unique())), &zero_copy(&bank())));
+  }
+}
@@ -0,0 +1,83 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anchor_lang::prelude::Pubkey;
+
+use super::LiquidationOpportunity;
+
+/// A liquidation opportunity the bot acted on, i.e. found, decided for and simulated. The bot
+/// builds liquidations without sending them, so nothing confirms it beyond the account turning
+/// healthy again.
+#[derive(Clone, Debug)]
+pub struct SeenOpportunity {
+  pub opportunity: LiquidationOpportunity,
+  pub seen_at: Instant,
+}
+
+/// Opportunities acted on and not yet resolved. While one is open for an account, further
+/// actions on that account are suppressed so events seen right after don't trigger the same
+/// liquidation again. Accounts another liquidator was seen acting on are contested and
+/// suppressed the same way.
+pub struct SeenOpportunities {
+  cooldown: Duration,
+  opportunities: Mutex<HashMap<Pubkey, SeenOpportunity>>,
+  contested: Mutex<HashMap<Pubkey, Instant>>,
+}
+
+impl SeenOpportunities {
+  pub fn new(cooldown: Duration) -> Self {
+    Self {
+      cooldown,
+      opportunities: Mutex::new(HashMap::new()),
+      contested: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// Marks `account` as being liquidated by someone else
+  pub fn mark_contested(&self, account: Pubkey) {
+    self.contested.lock().unwrap().insert(account, Instant::now());
+  }
+
+  /// How long ago `account` was last seen liquidated by someone else, within the cooldown
+  pub fn contested_since(&self, account: &Pubkey) -> Option<Duration> {
+    let mut contested = self.contested.lock().unwrap();
+    let elapsed = contested.get(account)?.elapsed();
+    if elapsed > self.cooldown {
+      contested.remove(account);
+      return None;
+    }
+    Some(elapsed)
+  }
+
+  /// The opportunity open on `account`, if any. One older than the cooldown is dropped as
+  /// expired, leaving the account free to be acted on again.
+  pub fn get(&self, account: &Pubkey) -> Option<SeenOpportunity> {
+    let mut opportunities = self.opportunities.lock().unwrap();
+    let seen = opportunities.get(account)?;
+    if seen.seen_at.elapsed() > self.cooldown {
+      tracing::debug!(account = %account, cooldown = ?self.cooldown, "opportunity expired");
+      opportunities.remove(account);
+      return None;
+    }
+    Some(seen.clone())
+  }
+
+  /// Records that `opportunity` was acted on, starting the cooldown of its account
+  pub fn mark_seen(&self, opportunity: LiquidationOpportunity) {
+    let seen = SeenOpportunity { opportunity, seen_at: Instant::now() };
+    self.opportunities.lock().unwrap().insert(seen.opportunity.account, seen);
+  }
+
+  /// Clears the cooldown of `account`, e.g. once it's healthy again
+  pub fn resolve(&self, account: &Pubkey) -> Option<SeenOpportunity> {
+    self.opportunities.lock().unwrap().remove(account)
+  }
+
+  /// Opportunities still open, oldest first
+  pub fn open(&self) -> Vec<SeenOpportunity> {
+    let mut open: Vec<SeenOpportunity> = self.opportunities.lock().unwrap().values().cloned().collect();
+    open.sort_by_key(|seen| seen.seen_at);
+    open
+  }
+}
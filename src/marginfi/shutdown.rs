@@ -0,0 +1,84 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use tokio::sync::watch;
+
+/// Shutdown request on SIGINT (Ctrl-C) or, on unix, SIGTERM. Once `listen` was called, a signal
+/// is remembered until awaited, so one arriving while an event is handled isn't lost and the
+/// event finishes before shutting down.
+pub struct Shutdown {
+  sender: Arc<watch::Sender<bool>>,
+  receiver: watch::Receiver<bool>,
+  listening: AtomicBool,
+}
+
+impl Default for Shutdown {
+  fn default() -> Self {
+    let (sender, receiver) = watch::channel(false);
+    Self {
+      sender: Arc::new(sender),
+      receiver,
+      listening: AtomicBool::new(false),
+    }
+  }
+}
+
+impl Shutdown {
+  /// Starts listening for the signals, replacing their default of terminating the process
+  pub fn listen(&self) -> anyhow::Result<()> {
+    if self.listening.swap(true, Ordering::Relaxed) {
+      return anyhow::Ok(());
+    }
+
+    #[cfg(unix)]
+    let mut terminate = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate())?;
+    let sender = Arc::clone(&self.sender);
+    tokio::spawn(async move {
+      #[cfg(unix)]
+      tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+      }
+      #[cfg(not(unix))]
+      let _ = tokio::signal::ctrl_c().await;
+
+      let _ = sender.send(true);
+    });
+    anyhow::Ok(())
+  }
+
+  /// Requests shutdown as a signal would
+  #[cfg(test)]
+  pub fn request(&self) {
+    let _ = self.sender.send(true);
+  }
+
+  /// Resolves once shutdown was requested
+  pub async fn requested(&self) {
+    let mut receiver = self.receiver.clone();
+    let _ = receiver.wait_for(|requested| *requested).await;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::time::Duration;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn nothing_is_requested_until_a_signal() {
+    let shutdown = Shutdown::default();
+
+    assert!(tokio::time::timeout(Duration::from_millis(10), shutdown.requested()).await.is_err());
+  }
+
+  #[tokio::test]
+  async fn a_request_is_remembered_until_awaited() {
+    let shutdown = Shutdown::default();
+    shutdown.request();
+
+    tokio::time::timeout(Duration::from_millis(10), shutdown.requested()).await.unwrap();
+    tokio::time::timeout(Duration::from_millis(10), shutdown.requested()).await.unwrap();
+  }
+}
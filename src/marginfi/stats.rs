@@ -0,0 +1,124 @@
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Upper bounds in seconds of the `handle_account` latency histogram buckets
+const LATENCY_BUCKETS_SECS: [f64; 8] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0];
+
+/// Counters of what the bot has done since startup
+#[derive(Default, Debug)]
+pub struct BotStats {
+  /// Opportunities skipped because the estimated profit was below `min_profit_usd`
+  pub skipped_for_profit: AtomicU64,
+  /// Accounts loaded and valued
+  pub accounts_checked: AtomicU64,
+  /// Checked accounts found below maintenance
+  pub accounts_liquidatable: AtomicU64,
+  /// Accounts that failed to load
+  pub rpc_errors: AtomicU64,
+  /// Decoded events by type
+  events: Mutex<BTreeMap<&'static str, u64>>,
+  latency: LatencyHistogram,
+}
+
+#[derive(Default, Debug)]
+struct LatencyHistogram {
+  /// Non-cumulative counts per bucket of `LATENCY_BUCKETS_SECS`, plus the +Inf bucket
+  buckets: [AtomicU64; LATENCY_BUCKETS_SECS.len() + 1],
+  sum_micros: AtomicU64,
+}
+
+impl BotStats {
+  pub fn increment(counter: &AtomicU64) {
+    counter.fetch_add(1, Ordering::Relaxed);
+  }
+
+  pub fn get(counter: &AtomicU64) -> u64 {
+    counter.load(Ordering::Relaxed)
+  }
+
+  pub fn record_event(&self, event_type: &'static str) {
+    *self.events.lock().unwrap().entry(event_type).or_default() += 1;
+  }
+
+  /// Records how long loading and valuing an account took
+  pub fn record_latency(&self, latency: Duration) {
+    let secs = latency.as_secs_f64();
+    let bucket = LATENCY_BUCKETS_SECS
+      .iter()
+      .position(|bound| secs <= *bound)
+      .unwrap_or(LATENCY_BUCKETS_SECS.len());
+    Self::increment(&self.latency.buckets[bucket]);
+    self.latency.sum_micros.fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+  }
+
+  /// The counters in the Prometheus text exposition format
+  pub fn render_prometheus(&self) -> String {
+    let mut out = String::new();
+    let counters = [
+      ("accounts_checked_total", "Accounts loaded and valued", &self.accounts_checked),
+      ("accounts_liquidatable_total", "Checked accounts found below maintenance", &self.accounts_liquidatable),
+      ("rpc_errors_total", "Accounts that failed to load", &self.rpc_errors),
+      ("skipped_for_profit_total", "Opportunities below the minimum profit", &self.skipped_for_profit),
+    ];
+    for (name, help, counter) in counters {
+      let _ = writeln!(out, "# HELP {} {}\n# TYPE {} counter\n{} {}", name, help, name, name, Self::get(counter));
+    }
+
+    let _ = writeln!(out, "# HELP events_total Decoded program events\n# TYPE events_total counter");
+    for (event_type, count) in self.events.lock().unwrap().iter() {
+      let _ = writeln!(out, "events_total{{type=\"{}\"}} {}", event_type, count);
+    }
+
+    let _ = writeln!(out, "# HELP handle_account_seconds Time to load and value an account\n# TYPE handle_account_seconds histogram");
+    let mut cumulative = 0;
+    for (i, bucket) in self.latency.buckets.iter().enumerate() {
+      cumulative += Self::get(bucket);
+      let bound = LATENCY_BUCKETS_SECS.get(i).map(|bound| bound.to_string()).unwrap_or_else(|| "+Inf".to_string());
+      let _ = writeln!(out, "handle_account_seconds_bucket{{le=\"{}\"}} {}", bound, cumulative);
+    }
+    let sum_secs = Self::get(&self.latency.sum_micros) as f64 / 1_000_000.0;
+    let _ = writeln!(out, "handle_account_seconds_sum {}\nhandle_account_seconds_count {}", sum_secs, cumulative);
+
+    out
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn renders_the_expected_metrics_after_events() {
+    let stats = BotStats::default();
+    stats.record_event("LendingAccountDepositEvent");
+    stats.record_event("LendingAccountDepositEvent");
+    stats.record_event("LendingAccountBorrowEvent");
+    BotStats::increment(&stats.accounts_checked);
+    BotStats::increment(&stats.accounts_liquidatable);
+    stats.record_latency(Duration::from_millis(200));
+
+    let rendered = stats.render_prometheus();
+    let samples = rendered.lines().filter(|line| !line.starts_with('#')).collect::<Vec<_>>();
+    for expected in [
+      "accounts_checked_total 1",
+      "accounts_liquidatable_total 1",
+      "rpc_errors_total 0",
+      "skipped_for_profit_total 0",
+      "events_total{type=\"LendingAccountBorrowEvent\"} 1",
+      "events_total{type=\"LendingAccountDepositEvent\"} 2",
+      "handle_account_seconds_bucket{le=\"0.1\"} 0",
+      "handle_account_seconds_bucket{le=\"0.25\"} 1",
+      "handle_account_seconds_bucket{le=\"+Inf\"} 1",
+      "handle_account_seconds_sum 0.2",
+      "handle_account_seconds_count 1",
+    ] {
+      assert!(samples.contains(&expected), "missing {:?} in\n{}", expected, rendered);
+    }
+    assert_eq!(rendered.matches("# TYPE").count(), 6);
+  }
+}
@@ -0,0 +1,333 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use anchor_lang::prelude::Pubkey;
+use fixed::types::I80F48;
+
+use super::output::AccountSnapshot;
+use super::types::OraclePriceType;
+use super::{MarginfiUserAccount, ValuationPolicy};
+
+/// A balance change decoded from an event, e.g. a withdraw of `amount` native tokens from `bank`
+#[derive(Clone, Copy, Debug)]
+pub struct BalanceChange {
+  pub bank: Pubkey,
+  pub amount: u64,
+  pub kind: BalanceChangeKind,
+}
+
+/// The instruction a `BalanceChange` comes from
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum BalanceChangeKind {
+  Deposit,
+  Withdraw,
+  Borrow,
+  Repay,
+}
+
+/// Coarse closeness to liquidation, from the maintenance buffer relative to the liabilities
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum HealthTier {
+  Liquidatable,
+  /// Buffer below 5% of the liabilities
+  Critical,
+  /// Buffer below 20% of the liabilities
+  AtRisk,
+  Healthy,
+}
+
+/// Latest evaluation of an account the bot has seen
+#[derive(Clone, Debug)]
+pub struct TrackedAccount {
+  pub pubkey: Pubkey,
+  /// Maintenance buffer in usd, negative when liquidatable
+  pub maintenance: I80F48,
+  /// Borrowed value in usd
+  pub liability_value: I80F48,
+  /// Borrowed value in usd per bank, banks without a liability are left out
+  pub bank_liability_values: HashMap<Pubkey, I80F48>,
+  /// Usd value of one native token unit per bank, as last seen
+  pub unit_prices: HashMap<Pubkey, I80F48>,
+  /// Maintenance asset and liability weight per bank, as last seen
+  pub maintenance_weights: HashMap<Pubkey, (I80F48, I80F48)>,
+  /// Number of consecutive evaluations, this one included, that found the account liquidatable
+  pub liquidatable_streak: u32,
+  pub evaluated_at: Instant,
+}
+
+impl TrackedAccount {
+  pub fn tier(&self) -> HealthTier {
+    if self.maintenance < I80F48::ZERO {
+      return HealthTier::Liquidatable;
+    }
+    let ratio = self.maintenance
+      .checked_div(self.liability_value)
+      .unwrap_or(I80F48::MAX);
+    if ratio < I80F48::from_num(0.05) {
+      HealthTier::Critical
+    } else if ratio < I80F48::from_num(0.2) {
+      HealthTier::AtRisk
+    } else {
+      HealthTier::Healthy
+    }
+  }
+
+  /// Most urgent first: smallest maintenance buffer (most underwater), ties broken by the larger
+  /// liability so bigger liquidations win.
+  pub fn cmp_urgency(&self, other: &Self) -> Ordering {
+    self.maintenance
+      .cmp(&other.maintenance)
+      .then_with(|| other.liability_value.cmp(&self.liability_value))
+  }
+
+  /// Whether `change` clearly can't move this account across the liquidation threshold: its usd
+  /// value at the last seen price is below `threshold`, and the value weighted by the bank's
+  /// maintenance weight for the side it changes is smaller than the maintenance buffer.
+  /// Borrows are never immaterial, a new liability lowers maintenance by more than its value.
+  pub fn is_immaterial(&self, change: &BalanceChange, threshold: I80F48) -> bool {
+    if change.kind == BalanceChangeKind::Borrow {
+      return false;
+    }
+    let (Some(unit_price), Some((asset_weight, liability_weight))) =
+      (self.unit_prices.get(&change.bank), self.maintenance_weights.get(&change.bank))
+    else {
+      return false;
+    };
+    let weight = match change.kind {
+      BalanceChangeKind::Deposit | BalanceChangeKind::Withdraw => *asset_weight,
+      BalanceChangeKind::Borrow | BalanceChangeKind::Repay => *liability_weight,
+    };
+    let Some(change_value) = I80F48::from_num(change.amount).checked_mul(*unit_price) else {
+      return false;
+    };
+    let Some(weighted_change_value) = change_value.checked_mul(weight) else {
+      return false;
+    };
+
+    change_value < threshold && weighted_change_value < self.maintenance
+  }
+}
+
+#[derive(Default)]
+pub struct TrackedAccounts {
+  accounts: Mutex<HashMap<Pubkey, TrackedAccount>>,
+  /// Latest snapshot of every tracked account, to report how re-evaluations changed it
+  snapshots: Mutex<HashMap<Pubkey, AccountSnapshot>>,
+}
+
+impl TrackedAccounts {
+  pub fn update(&self, pubkey: Pubkey, account: &MarginfiUserAccount, policy: ValuationPolicy) -> anyhow::Result<TrackedAccount> {
+    let unit_prices = account
+      .bank_accounts()
+      .iter()
+      .filter_map(|bank_account| {
+        let price = bank_account.usd_price(OraclePriceType::RealTime, None).ok()?;
+        let unit_price = bank_account.bank.get_display_asset(price)?;
+        Some((bank_account.balance.bank_pk, unit_price))
+      })
+      .collect();
+    let maintenance_weights = account
+      .maintenance_weights()
+      .map(|(bank, asset_weight, liability_weight)| (bank, (asset_weight, liability_weight)))
+      .collect();
+    let mut bank_liability_values = HashMap::new();
+    for bank_account in account.bank_accounts() {
+      let liability_value = bank_account.liability_value()?;
+      if liability_value > I80F48::ZERO {
+        bank_liability_values.insert(bank_account.balance.bank_pk, liability_value);
+      }
+    }
+    let maintenance = account.maintenance(policy)?;
+    let mut accounts = self.accounts.lock().unwrap();
+    let liquidatable_streak = if maintenance < I80F48::ZERO {
+      accounts
+        .get(&pubkey)
+        .map_or(0, |previous| previous.liquidatable_streak)
+        .saturating_add(1)
+    } else {
+      0
+    };
+    let tracked = TrackedAccount {
+      pubkey,
+      maintenance,
+      liability_value: account.liability_value()?,
+      bank_liability_values,
+      unit_prices,
+      maintenance_weights,
+      liquidatable_streak,
+      evaluated_at: Instant::now(),
+    };
+    accounts.insert(pubkey, tracked.clone());
+
+    anyhow::Ok(tracked)
+  }
+
+  pub fn get(&self, pubkey: &Pubkey) -> Option<TrackedAccount> {
+    self.accounts.lock().unwrap().get(pubkey).cloned()
+  }
+
+  /// Stops tracking `pubkey`, dropping its snapshot too
+  pub fn remove(&self, pubkey: &Pubkey) -> Option<TrackedAccount> {
+    let mut accounts = self.accounts.lock().unwrap();
+    self.snapshots.lock().unwrap().remove(pubkey);
+    accounts.remove(pubkey)
+  }
+
+  /// Keeps `snapshot` as the latest of its account and returns the one it replaces. Only tracked
+  /// accounts keep a snapshot, so the snapshots never outgrow the tracked set.
+  pub fn replace_snapshot(&self, snapshot: &AccountSnapshot) -> Option<AccountSnapshot> {
+    let accounts = self.accounts.lock().unwrap();
+    if !accounts.contains_key(&snapshot.account) {
+      return None;
+    }
+    self.snapshots.lock().unwrap().insert(snapshot.account, snapshot.clone())
+  }
+
+  pub fn len(&self) -> usize {
+    self.accounts.lock().unwrap().len()
+  }
+
+  /// Borrowed value in usd per bank summed over the tracked accounts, largest first
+  pub fn liability_exposure(&self) -> Vec<(Pubkey, I80F48)> {
+    let mut exposure: HashMap<Pubkey, I80F48> = HashMap::new();
+    for account in self.accounts.lock().unwrap().values() {
+      for (bank, value) in &account.bank_liability_values {
+        let total = exposure.entry(*bank).or_insert(I80F48::ZERO);
+        *total = total.saturating_add(*value);
+      }
+    }
+
+    let mut exposure: Vec<(Pubkey, I80F48)> = exposure.into_iter().collect();
+    exposure.sort_by(|(_, a), (_, b)| b.cmp(a));
+    exposure
+  }
+
+  /// Tracked accounts ordered by `TrackedAccount::cmp_urgency`
+  pub fn by_urgency(&self) -> Vec<TrackedAccount> {
+    let mut accounts: Vec<TrackedAccount> = self.accounts.lock().unwrap().values().cloned().collect();
+    accounts.sort_by(TrackedAccount::cmp_urgency);
+    accounts
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use anchor_lang::Event;
+  use base64::{Engine as _, engine::general_purpose};
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::marginfi::event_registry::{EventAction, EventContext, EventRegistry};
+  use crate::marginfi::events::{AccountEventHeader, LendingAccountWithdrawEvent};
+
+  const BANK: Pubkey = Pubkey::new_from_array([1; 32]);
+  const ACCOUNT: Pubkey = Pubkey::new_from_array([2; 32]);
+
+  /// Decodes `event` from the `Program data: ` payload the program logs for it
+  fn decode_change(event: impl Event) -> BalanceChange {
+    let data = general_purpose::STANDARD.encode(event.data());
+    let ctx = EventContext { signature: "test" };
+    match EventRegistry::with_known_events().dispatch(&data, &ctx).unwrap() {
+      Some((_, EventAction::Evaluate { change: Some(change), .. })) => change,
+      other => panic!("expected a balance change, got {:?}", other.map(|(event_type, _)| event_type)),
+    }
+  }
+
+  fn withdraw(amount: u64) -> LendingAccountWithdrawEvent {
+    LendingAccountWithdrawEvent {
+      header: AccountEventHeader {
+        signer: None,
+        marginfi_account: ACCOUNT,
+        marginfi_account_authority: Pubkey::default(),
+        marginfi_group: Pubkey::default(),
+      },
+      bank: BANK,
+      mint: Pubkey::default(),
+      amount,
+      close_balance: false,
+    }
+  }
+
+  /// Tracked with a $100 buffer, $1 per native unit and a liability weight of 1.25 on `BANK`
+  fn tracked() -> TrackedAccount {
+    TrackedAccount {
+      pubkey: ACCOUNT,
+      maintenance: I80F48::from_num(100),
+      liability_value: I80F48::from_num(1_000),
+      bank_liability_values: HashMap::new(),
+      unit_prices: HashMap::from([(BANK, I80F48::ONE)]),
+      maintenance_weights: HashMap::from([(BANK, (I80F48::from_num(0.8), I80F48::from_num(1.25)))]),
+      liquidatable_streak: 0,
+      evaluated_at: Instant::now(),
+    }
+  }
+
+  #[test]
+  fn decodes_withdraw_event_as_balance_change() {
+    let change = decode_change(withdraw(90));
+
+    assert_eq!(change.bank, BANK);
+    assert_eq!(change.amount, 90);
+    assert_eq!(change.kind, BalanceChangeKind::Withdraw);
+  }
+
+  #[test]
+  fn withdraw_is_weighted_by_asset_weight() {
+    let threshold = I80F48::from_num(1_000);
+
+    // $120 withdrawn lowers maintenance by $96
+    assert!(tracked().is_immaterial(&decode_change(withdraw(120)), threshold));
+    assert!(!tracked().is_immaterial(&decode_change(withdraw(130)), threshold));
+  }
+
+  #[test]
+  fn repay_is_weighted_by_liability_weight() {
+    let threshold = I80F48::from_num(1_000);
+    let repay = |amount| BalanceChange { bank: BANK, amount, kind: BalanceChangeKind::Repay };
+
+    assert!(tracked().is_immaterial(&repay(79), threshold));
+    // $90 weighs $112.5 against the $100 buffer
+    assert!(!tracked().is_immaterial(&repay(90), threshold));
+  }
+
+  #[test]
+  fn borrow_is_never_immaterial() {
+    let borrow = BalanceChange { bank: BANK, amount: 1, kind: BalanceChangeKind::Borrow };
+
+    assert!(!tracked().is_immaterial(&borrow, I80F48::from_num(1_000)));
+  }
+
+  #[test]
+  fn change_above_threshold_is_material() {
+    assert!(!tracked().is_immaterial(&decode_change(withdraw(50)), I80F48::from_num(10)));
+  }
+
+  fn snapshot(maintenance: i32) -> AccountSnapshot {
+    AccountSnapshot {
+      account: ACCOUNT,
+      authority: Pubkey::default(),
+      asset_value: I80F48::from_num(1_100),
+      liability_value: I80F48::from_num(1_000),
+      maintenance: I80F48::from_num(maintenance),
+      health_factor: I80F48::from_num(1.1),
+      positions: Vec::new(),
+    }
+  }
+
+  #[test]
+  fn snapshots_are_kept_only_for_tracked_accounts() {
+    let accounts = TrackedAccounts::default();
+    assert!(accounts.replace_snapshot(&snapshot(100)).is_none());
+
+    accounts.accounts.lock().unwrap().insert(ACCOUNT, tracked());
+    // The untracked snapshot above wasn't kept
+    assert!(accounts.replace_snapshot(&snapshot(100)).is_none());
+    let replaced = accounts.replace_snapshot(&snapshot(50)).map(|snapshot| snapshot.maintenance);
+    assert_eq!(replaced, Some(I80F48::from_num(100)));
+
+    accounts.remove(&ACCOUNT);
+    assert!(accounts.snapshots.lock().unwrap().is_empty());
+  }
+}
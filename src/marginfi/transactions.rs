@@ -0,0 +1,113 @@
+use anchor_client::solana_sdk::compute_budget::ComputeBudgetInstruction;
+use anchor_client::solana_sdk::transaction::Transaction;
+use anchor_lang::solana_program::instruction::Instruction;
+use solana_rpc_client_types::config::RpcSimulateTransactionConfig;
+
+use super::{BotError, Marginfi};
+
+/// Max compute units a single transaction may request
+pub const MAX_COMPUTE_UNIT_LIMIT: u32 = 1_400_000;
+/// Headroom added on top of the simulated compute units, in bps
+pub const COMPUTE_UNIT_MARGIN_BPS: u64 = 1_000;
+
+/// `instructions` preceded by a compute unit limit and, when non-zero, a compute unit price
+pub fn with_compute_budget(instructions: Vec<Instruction>, cu_limit: u32, cu_price_micro_lamports: u64) -> Vec<Instruction> {
+  let mut budgeted = Vec::with_capacity(instructions.len() + 2);
+  budgeted.push(ComputeBudgetInstruction::set_compute_unit_limit(cu_limit));
+  if cu_price_micro_lamports > 0 {
+    budgeted.push(ComputeBudgetInstruction::set_compute_unit_price(cu_price_micro_lamports));
+  }
+  budgeted.extend(instructions);
+  budgeted
+}
+
+/// Compute unit limit of a transaction that consumed `units_consumed` when simulated, plus
+/// `COMPUTE_UNIT_MARGIN_BPS`. Errors if that exceeds `max_limit`, so the caller can split or
+/// skip the transaction.
+pub fn sized_compute_unit_limit(units_consumed: u64, max_limit: u32) -> anyhow::Result<u32> {
+  let limit = units_consumed
+    .saturating_add(units_consumed.saturating_mul(COMPUTE_UNIT_MARGIN_BPS) / 10_000);
+  if limit > max_limit as u64 {
+    return Err(anyhow::anyhow!("needs {} CU, exceeding the {} CU limit", limit, max_limit));
+  }
+  anyhow::Ok(limit as u32)
+}
+
+pub struct SimulationOutcome {
+  pub units_consumed: u64,
+  pub logs: Vec<String>,
+  /// Debug-formatted transaction error, if the simulation failed
+  pub err: Option<String>,
+}
+
+impl Marginfi {
+  /// Simulates `instructions` as a single transaction paid by the payer. Signatures aren't
+  /// verified and the blockhash is replaced, so the transaction doesn't need to be signed.
+  pub async fn simulate_instructions(&self, instructions: &[Instruction]) -> anyhow::Result<SimulationOutcome> {
+    let tx = Transaction::new_with_payer(instructions, Some(&self.program.payer()));
+    let result = self.rpc_client
+      .simulate_transaction_with_config(
+        &tx,
+        RpcSimulateTransactionConfig {
+          sig_verify: false,
+          replace_recent_blockhash: true,
+          inner_instructions: true,
+          commitment: Some(self.rpc_client.commitment()),
+          ..RpcSimulateTransactionConfig::default()
+        },
+      )
+      .await
+      .map_err(|e| BotError::Simulation(anyhow::Error::new(e).context("failed to simulate transaction")))?
+      .value;
+
+    anyhow::Ok(SimulationOutcome {
+      units_consumed: result.units_consumed.unwrap_or_default(),
+      logs: result.logs.unwrap_or_default(),
+      err: result.err.map(|err| format!("{:?}", err)),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use anchor_client::solana_sdk::compute_budget;
+  use anchor_lang::prelude::Pubkey;
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn budget_instructions_come_first() {
+    let ix = Instruction::new_with_bytes(Pubkey::new_unique(), &[1, 2, 3], vec![]);
+    let budgeted = with_compute_budget(vec![ix.clone()], 200_000, 5_000);
+
+    assert_eq!(budgeted, vec![
+      ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+      ComputeBudgetInstruction::set_compute_unit_price(5_000),
+      ix,
+    ]);
+    assert!(budgeted[..2].iter().all(|ix| ix.program_id == compute_budget::ID));
+  }
+
+  #[test]
+  fn zero_price_sets_only_the_limit() {
+    let ix = Instruction::new_with_bytes(Pubkey::new_unique(), &[], vec![]);
+
+    assert_eq!(with_compute_budget(vec![ix.clone()], 200_000, 0), vec![
+      ComputeBudgetInstruction::set_compute_unit_limit(200_000),
+      ix,
+    ]);
+  }
+
+  #[test]
+  fn limit_adds_the_margin_to_consumed_units() {
+    assert_eq!(sized_compute_unit_limit(100_000, 400_000).unwrap(), 110_000);
+    assert_eq!(sized_compute_unit_limit(0, 400_000).unwrap(), 0);
+  }
+
+  #[test]
+  fn limit_above_max_errors() {
+    assert_eq!(sized_compute_unit_limit(363_636, 400_000).unwrap(), 399_999);
+    assert!(sized_compute_unit_limit(363_640, 400_000).is_err());
+  }
+}
@@ -1,14 +1,15 @@
 use crate::{
-  assert_struct_align, assert_struct_size,
+  assert_struct_align, assert_struct_size, math_error,
 };
 
 use bytemuck::{Pod, Zeroable};
 
-use anchor_lang::prelude::Pubkey;
+use anchor_lang::{prelude::Pubkey, Discriminator};
 use fixed::types::I80F48;
 
 use super::{BankCache, BankConfig, EmodeSettings};
-use super::super::consts::discriminators;
+use super::super::consts::{discriminators, exp_10_i80f48, SECONDS_PER_YEAR};
+use super::super::prelude::MarginfiResult;
 use super::super::WrappedI80F48;
 
 assert_struct_size!(Bank, 1856);
@@ -69,6 +70,8 @@ pub struct Bank {
   ///   exactly (i.e Kamino ignores this and treats it as if it was using `mint_decimals`)
   pub total_asset_shares: WrappedI80F48,
 
+  /// Unix timestamp of the last interest accrual, the share values are as of then. The bank
+  /// doesn't record the slot.
   pub last_update: i64,
 
   pub config: BankConfig,
@@ -125,25 +128,100 @@ pub struct Bank {
   pub _padding_1: [[u64; 2]; 15], // 8 * 2 * 14 = 224B
 }
 
+impl Discriminator for Bank {
+  const DISCRIMINATOR: &[u8] = &discriminators::BANK;
+}
+
 impl Bank {
   pub const LEN: usize = std::mem::size_of::<Bank>();
-  pub const DISCRIMINATOR: [u8; 8] = discriminators::BANK;
 
   pub fn get_liability_amount(&self, shares: I80F48) -> Option<I80F48> {
     shares
         .checked_mul(self.liability_share_value.into())
   }
 
+  /// Native amount of `shares`. For Kamino banks this is an amount of collateral tokens, which
+  /// uses `mint_decimals` like everything else in the bank, not the collateral mint's decimals.
   pub fn get_asset_amount(&self, shares: I80F48) -> Option<I80F48> {
     shares
         .checked_mul(self.asset_share_value.into())
   }
 
+  /// Seconds of interest not yet accrued into the share values as of `unix_timestamp`, 0 if
+  /// `last_update` is in the future
+  pub fn interest_staleness(&self, unix_timestamp: i64) -> u64 {
+      unix_timestamp.saturating_sub(self.last_update).max(0) as u64
+  }
+
+  /// The bank with interest accrued into its share values up to `timestamp`, at the rates of its
+  /// current utilization. Projects the index forward, assuming balances hold; unchanged if
+  /// `timestamp` isn't after `last_update`.
+  pub fn accrue_interest_to(&self, timestamp: i64) -> MarginfiResult<Bank> {
+    let mut bank = *self;
+    let time_delta = timestamp.saturating_sub(self.last_update);
+    if time_delta <= 0 {
+      return Ok(bank);
+    }
+
+    let asset_share_value: I80F48 = self.asset_share_value.into();
+    let liability_share_value: I80F48 = self.liability_share_value.into();
+    let total_assets = I80F48::from(self.total_asset_shares)
+      .checked_mul(asset_share_value)
+      .ok_or_else(math_error!())?;
+    let total_liabilities = I80F48::from(self.total_liability_shares)
+      .checked_mul(liability_share_value)
+      .ok_or_else(math_error!())?;
+    if total_assets == I80F48::ZERO || total_liabilities == I80F48::ZERO {
+      bank.last_update = timestamp;
+      return Ok(bank);
+    }
+
+    let ir_config = &self.config.interest_rate_config;
+    let utilization = total_liabilities.checked_div(total_assets).ok_or_else(math_error!())?;
+    let base_rate = ir_config.base_rate(utilization).ok_or_else(math_error!())?;
+    let lending_rate = base_rate.checked_mul(utilization).ok_or_else(math_error!())?;
+    let borrowing_rate = ir_config.borrowing_rate(base_rate).ok_or_else(math_error!())?;
+
+    let accrue = |value: I80F48, apr: I80F48| {
+      apr.checked_mul(I80F48::from_num(time_delta))
+        .and_then(|interest| interest.checked_div(SECONDS_PER_YEAR))
+        .and_then(|rate| value.checked_mul(I80F48::ONE + rate))
+    };
+    bank.asset_share_value = accrue(asset_share_value, lending_rate).ok_or_else(math_error!())?.into();
+    bank.liability_share_value = accrue(liability_share_value, borrowing_rate).ok_or_else(math_error!())?.into();
+    bank.last_update = timestamp;
+
+    Ok(bank)
+  }
+
+  /// Converts a native amount into whole tokens. None if `mint_decimals` is beyond
+  /// `EXP_10_I80F48` or the division overflows. Always uses the bank's `mint_decimals`, also for
+  /// Kamino banks whose collateral mint reports 6 decimals.
   pub fn get_display_asset(&self, amount: I80F48) -> Option<I80F48> {
-    let div = I80F48::from_num(10_i128.pow(self.mint_decimals as u32));
+    let div = exp_10_i80f48(self.mint_decimals as usize)?;
     amount
       .checked_div(div)
   }
+
+  /// Factor the init-margin asset weight is scaled by once the bank's total deposits, valued at
+  /// `price`, exceed `total_asset_value_init_limit`: the limit over the deposits' usd value. None
+  /// when the limit is inactive or not exceeded.
+  pub fn asset_weight_init_discount(&self, price: I80F48) -> MarginfiResult<Option<I80F48>> {
+    if !self.config.is_init_limit_active() {
+      return Ok(None);
+    }
+
+    let total_asset_value = self.get_asset_amount(self.total_asset_shares.into())
+      .and_then(|total_assets| total_assets.checked_mul(price))
+      .and_then(|total_assets_with_decimals| self.get_display_asset(total_assets_with_decimals))
+      .ok_or_else(math_error!())?;
+    let limit = I80F48::from_num(self.config.total_asset_value_init_limit);
+    if total_asset_value <= limit {
+      return Ok(None);
+    }
+
+    Ok(Some(limit.checked_div(total_asset_value).ok_or_else(math_error!())?))
+  }
 }
 
 #[repr(u8)]
@@ -173,6 +251,28 @@ pub enum BankOperationalState {
 unsafe impl Zeroable for BankOperationalState {}
 unsafe impl Pod for BankOperationalState {}
 
+impl BankOperationalState {
+  /// Whether the program accepts `op` against a bank in this state.
+  /// * `ReduceOnly` banks only accept operations that shrink positions (withdraw, repay)
+  /// * `Paused` and `KilledByBankruptcy` banks accept nothing
+  pub fn permits(&self, op: BankOperation) -> bool {
+    match self {
+      Self::Operational => true,
+      Self::ReduceOnly => matches!(op, BankOperation::Withdraw | BankOperation::Repay),
+      Self::Paused | Self::KilledByBankruptcy => false,
+    }
+  }
+}
+
+/// Balance-changing operations a liquidation may need to perform against a bank
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+pub enum BankOperation {
+  Deposit,
+  Withdraw,
+  Borrow,
+  Repay,
+}
+
 #[repr(u8)]
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 pub enum OracleSetup {
@@ -190,6 +290,11 @@ unsafe impl Zeroable for OracleSetup {}
 unsafe impl Pod for OracleSetup {}
 
 impl OracleSetup {
+  /// Whether banks with this setup can be priced, see `get_oracle_keys_for_bank`
+  pub fn is_supported(&self) -> bool {
+      !matches!(self, Self::None | Self::PythLegacy | Self::SwitchboardV2)
+  }
+
   pub fn from_u8(value: u8) -> Option<Self> {
       match value {
           0 => Some(Self::None),
@@ -204,4 +309,162 @@ impl OracleSetup {
           _ => None,
       }
   }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+  use crate::marginfi::fixtures::bank;
+
+  const LAST_UPDATE: i64 = 1_700_000_000;
+
+  /// A fixture bank at half utilization, on a legacy curve reaching its 10% plateau there, with
+  /// no fees
+  fn half_utilized() -> Bank {
+    let mut bank = bank();
+    bank.last_update = LAST_UPDATE;
+    bank.total_asset_shares = I80F48::from_num(1_000).into();
+    bank.total_liability_shares = I80F48::from_num(500).into();
+    let ir_config = &mut bank.config.interest_rate_config;
+    ir_config.optimal_utilization_rate = I80F48::from_num(0.5).into();
+    ir_config.plateau_interest_rate = I80F48::from_num(0.1).into();
+    ir_config.max_interest_rate = I80F48::from_num(1).into();
+    bank
+  }
+
+  fn assert_close(actual: WrappedI80F48, expected: f64) {
+    let actual: I80F48 = actual.into();
+    assert!((actual - I80F48::from_num(expected)).abs() < I80F48::from_num(1e-9), "{} != {}", actual, expected);
+  }
+
+  #[test]
+  fn a_year_at_the_plateau_accrues_the_plateau_rate() {
+    let projected = half_utilized().accrue_interest_to(LAST_UPDATE + 31_536_000).unwrap();
+
+    // Borrowers pay 10%, lenders earn it on half the deposits
+    assert_close(projected.liability_share_value, 1.1);
+    assert_close(projected.asset_share_value, 1.05);
+    assert_eq!(projected.last_update, LAST_UPDATE + 31_536_000);
+  }
+
+  /// A bank at 61.76% utilization on a legacy curve with a 10% plateau at 80%, charging 15% of
+  /// the rate and 1% fixed in fees, with interest already accrued into its share values
+  fn accruing_with_fees() -> Bank {
+    let mut bank = bank();
+    bank.last_update = LAST_UPDATE;
+    bank.asset_share_value = I80F48::from_num(1.02).into();
+    bank.liability_share_value = I80F48::from_num(1.05).into();
+    bank.total_asset_shares = I80F48::from_num(1_000_000).into();
+    bank.total_liability_shares = I80F48::from_num(600_000).into();
+    let ir_config = &mut bank.config.interest_rate_config;
+    ir_config.optimal_utilization_rate = I80F48::from_num(0.8).into();
+    ir_config.plateau_interest_rate = I80F48::from_num(0.1).into();
+    ir_config.max_interest_rate = I80F48::from_num(1.5).into();
+    ir_config.insurance_ir_fee = I80F48::from_num(0.05).into();
+    ir_config.protocol_ir_fee = I80F48::from_num(0.1).into();
+    ir_config.protocol_fixed_fee_apr = I80F48::from_num(0.01).into();
+    bank
+  }
+
+  #[test]
+  fn six_hours_with_fees_match_the_share_values_worked_out_by_hand() {
+    // Utilization is 630,000 / 1,020,000, so the base rate is 7.7206%, lenders earn 4.7686% and
+    // borrowers pay 9.8787%. The share values were computed to 40 digits off the program's
+    // formula, independently of `accrue_interest_to`.
+    let projected = accruing_with_fees().accrue_interest_to(LAST_UPDATE + 21_600).unwrap();
+
+    assert_close(projected.asset_share_value, 1.020_033_314_867);
+    assert_close(projected.liability_share_value, 1.050_071_045_276);
+  }
+
+  #[test]
+  fn bank_updated_now_has_no_interest_staleness() {
+    assert_eq!(half_utilized().interest_staleness(LAST_UPDATE), 0);
+  }
+
+  #[test]
+  fn interest_staleness_is_the_time_since_the_last_update() {
+    assert_eq!(half_utilized().interest_staleness(LAST_UPDATE + 7_200), 7_200);
+  }
+
+  #[test]
+  fn timestamp_before_the_last_update_saturates_the_staleness_to_zero() {
+    assert_eq!(half_utilized().interest_staleness(LAST_UPDATE - 60), 0);
+    assert_eq!(half_utilized().interest_staleness(i64::MIN), 0);
+  }
+
+  #[test]
+  fn timestamps_before_the_last_update_leave_the_bank_as_is() {
+    let projected = half_utilized().accrue_interest_to(LAST_UPDATE - 60).unwrap();
+
+    assert_eq!(projected.asset_share_value, half_utilized().asset_share_value);
+    assert_eq!(projected.liability_share_value, half_utilized().liability_share_value);
+    assert_eq!(projected.last_update, LAST_UPDATE);
+  }
+
+  #[test]
+  fn a_bank_without_liabilities_accrues_nothing() {
+    let mut bank = half_utilized();
+    bank.total_liability_shares = I80F48::ZERO.into();
+
+    let projected = bank.accrue_interest_to(LAST_UPDATE + 86_400).unwrap();
+    assert_eq!(projected.asset_share_value, bank.asset_share_value);
+    assert_eq!(projected.liability_share_value, bank.liability_share_value);
+    assert_eq!(projected.last_update, LAST_UPDATE + 86_400);
+  }
+
+  #[test]
+  fn zero_shares_are_worth_nothing() {
+    let bank = half_utilized();
+
+    assert_eq!(bank.get_asset_amount(I80F48::ZERO), Some(I80F48::ZERO));
+    assert_eq!(bank.get_liability_amount(I80F48::ZERO), Some(I80F48::ZERO));
+  }
+
+  #[test]
+  fn a_higher_liability_share_value_owes_more() {
+    let shares = I80F48::from_num(500);
+    let bank = half_utilized();
+    let mut accrued = bank;
+    accrued.liability_share_value = I80F48::from_num(1.2).into();
+
+    assert_eq!(bank.get_liability_amount(shares), Some(I80F48::from_num(500)));
+    assert_eq!(accrued.get_liability_amount(shares), Some(I80F48::from_num(600)));
+  }
+
+  #[test]
+  fn accrued_interest_raises_the_amounts_of_the_same_shares() {
+    let shares = I80F48::from_num(500);
+    let bank = half_utilized();
+    let projected = bank.accrue_interest_to(LAST_UPDATE + 86_400).unwrap();
+
+    assert!(projected.get_asset_amount(shares).unwrap() > bank.get_asset_amount(shares).unwrap());
+    assert!(projected.get_liability_amount(shares).unwrap() > bank.get_liability_amount(shares).unwrap());
+  }
+
+  #[test]
+  fn display_amounts_apply_the_mint_decimals() {
+    let mut bank = half_utilized();
+    let shares = I80F48::from_num(1_500_000);
+    bank.liability_share_value = I80F48::from_num(2).into();
+
+    let deposited = bank.get_asset_amount(shares).unwrap();
+    let borrowed = bank.get_liability_amount(shares).unwrap();
+    assert_eq!(bank.get_display_asset(deposited), Some(I80F48::from_num(1.5)));
+    assert_eq!(bank.get_display_asset(borrowed), Some(I80F48::from_num(3)));
+
+    bank.mint_decimals = 9;
+    assert_eq!(bank.get_display_asset(I80F48::from_num(3_000_000_000u64)), Some(I80F48::from_num(3)));
+  }
+
+  #[test]
+  fn decimals_beyond_the_exponent_table_have_no_display_amount() {
+    let mut bank = half_utilized();
+    bank.mint_decimals = 23;
+    assert_eq!(bank.get_display_asset(I80F48::from_num(10u128.pow(23))), Some(I80F48::ONE));
+
+    bank.mint_decimals = 24;
+    assert_eq!(bank.get_display_asset(I80F48::ONE), None);
+  }
+}
@@ -0,0 +1,153 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anchor_lang::prelude::Pubkey;
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+
+use crate::utils::parse_account;
+use super::Bank;
+
+struct CachedBank {
+  bank: Bank,
+  fetched_at: Instant,
+}
+
+/// Banks decoded within the last `ttl`, shared across account evaluations so the hot banks most
+/// accounts use aren't fetched again for every account. Besides the mostly static config, a bank
+/// holds share values and the interest accrual timestamp, which go stale within `ttl`.
+pub struct BankAccountCache {
+  ttl: Duration,
+  entries: Mutex<HashMap<Pubkey, CachedBank>>,
+}
+
+impl BankAccountCache {
+  pub fn new(ttl: Duration) -> Self {
+    Self {
+      ttl,
+      entries: Mutex::new(HashMap::new()),
+    }
+  }
+
+  /// The cached bank, if decoded within `ttl`
+  pub fn get(&self, pubkey: &Pubkey) -> Option<Bank> {
+    let entries = self.entries.lock().unwrap();
+    entries
+      .get(pubkey)
+      .filter(|entry| entry.fetched_at.elapsed() <= self.ttl)
+      .map(|entry| entry.bank)
+  }
+
+  pub fn insert(&self, pubkey: Pubkey, bank: Bank) {
+    let mut entries = self.entries.lock().unwrap();
+    entries.retain(|_, entry| entry.fetched_at.elapsed() <= self.ttl);
+    entries.insert(pubkey, CachedBank { bank, fetched_at: Instant::now() });
+  }
+
+  /// The banks of `pubkeys`, in order. Banks missing from the cache are fetched in one
+  /// `get_multiple_accounts` call and cached.
+  pub async fn get_or_fetch_multiple(&self, rpc_client: &RpcClient, pubkeys: &[Pubkey]) -> anyhow::Result<Vec<Bank>> {
+    let cached: Vec<Option<Bank>> = pubkeys.iter().map(|pubkey| self.get(pubkey)).collect();
+    let missing: Vec<Pubkey> = pubkeys
+      .iter()
+      .zip(&cached)
+      .filter(|(_, bank)| bank.is_none())
+      .map(|(pubkey, _)| *pubkey)
+      .collect();
+    let mut fetched = HashMap::with_capacity(missing.len());
+    if !missing.is_empty() {
+      let accounts = rpc_client.get_multiple_accounts(&missing).await?;
+      for (pubkey, account) in missing.into_iter().zip(accounts) {
+        let account = account.ok_or_else(|| anyhow::anyhow!("bank {} not found", pubkey))?;
+        let bank = parse_account::<Bank>(&account.data)
+          .map_err(|e| anyhow::anyhow!("invalid bank data: {}", e))?;
+        self.insert(pubkey, bank);
+        fetched.insert(pubkey, bank);
+      }
+    }
+
+    anyhow::Ok(
+      cached
+        .into_iter()
+        .zip(pubkeys)
+        .map(|(bank, pubkey)| bank.or_else(|| fetched.get(pubkey).copied()).expect("missing banks were fetched"))
+        .collect()
+    )
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+  use solana_rpc_client::mock_sender::MocksMap;
+  use solana_rpc_client_types::request::RpcRequest;
+
+  use super::*;
+  use crate::consts::MARGINFI_PROGRAM_ID;
+  use crate::marginfi::fixtures::{bank, response, rpc_account, ui_account, zero_copy};
+
+  const BANK: Pubkey = Pubkey::new_from_array([1; 32]);
+
+  fn bank_with_decimals(mint_decimals: u8) -> Bank {
+    let mut bank = bank();
+    bank.mint_decimals = mint_decimals;
+    bank
+  }
+
+  /// Serves `bank` at `BANK` for a single multi-get, failing any other request
+  fn rpc_serving_once(bank: &Bank) -> RpcClient {
+    let data = ui_account(&BANK, &rpc_account(MARGINFI_PROGRAM_ID, zero_copy(bank)));
+    let mocks: MocksMap = [(RpcRequest::GetMultipleAccounts, response(serde_json::json!([data])))].into_iter().collect();
+    RpcClient::new_mock_with_mocks_map("fails", mocks)
+  }
+
+  #[tokio::test]
+  async fn fetched_bank_is_served_from_the_cache() {
+    let cache = BankAccountCache::new(Duration::from_secs(60));
+    let rpc_client = rpc_serving_once(&bank_with_decimals(9));
+
+    let fetched = cache.get_or_fetch_multiple(&rpc_client, &[BANK]).await.unwrap();
+    // The one mocked multi-get is spent, a second fetch would fail
+    let cached = cache.get_or_fetch_multiple(&rpc_client, &[BANK]).await.unwrap();
+    assert_eq!(fetched[0].mint_decimals, 9);
+    assert_eq!(cached[0].mint_decimals, 9);
+  }
+
+  #[test]
+  fn expired_bank_is_not_served() {
+    let cache = BankAccountCache::new(Duration::from_millis(1));
+    cache.insert(BANK, bank());
+    std::thread::sleep(Duration::from_millis(5));
+
+    assert!(cache.get(&BANK).is_none());
+  }
+
+  #[tokio::test]
+  async fn expired_bank_is_fetched_again() {
+    let cache = BankAccountCache::new(Duration::from_millis(1));
+    cache.insert(BANK, bank_with_decimals(6));
+    std::thread::sleep(Duration::from_millis(5));
+
+    let banks = cache.get_or_fetch_multiple(&rpc_serving_once(&bank_with_decimals(9)), &[BANK]).await.unwrap();
+    assert_eq!(banks[0].mint_decimals, 9);
+  }
+
+  #[test]
+  fn concurrent_inserts_and_gets_see_every_bank() {
+    let cache = BankAccountCache::new(Duration::from_secs(60));
+    std::thread::scope(|scope| {
+      for i in 0..8u8 {
+        let cache = &cache;
+        scope.spawn(move || {
+          let pubkey = Pubkey::new_from_array([i; 32]);
+          cache.insert(pubkey, bank_with_decimals(i));
+          assert_eq!(cache.get(&pubkey).map(|bank| bank.mint_decimals), Some(i));
+        });
+      }
+    });
+
+    for i in 0..8u8 {
+      assert_eq!(cache.get(&Pubkey::new_from_array([i; 32])).map(|bank| bank.mint_decimals), Some(i));
+    }
+  }
+}
@@ -13,9 +13,9 @@ use super::{
 };
 use super::super::WrappedI80F48;
 use super::super::consts::{
-  ASSET_TAG_DEFAULT, MAX_ORACLE_KEYS,
+  ASSET_TAG_DEFAULT, ASSET_TAG_SOL, ASSET_TAG_STAKED, ASSET_TAG_KAMINO, MAX_ORACLE_KEYS,
   TOTAL_ASSET_VALUE_INIT_LIMIT_INACTIVE,
-  MAX_PYTH_ORACLE_AGE
+  MAX_PYTH_ORACLE_AGE, U32_MAX, U32_MAX_DIV_10
 };
 
 assert_struct_size!(BankConfig, 544);
@@ -93,6 +93,28 @@ pub struct BankConfig {
 }
 
 impl BankConfig {
+  /// Whether positions in banks of this and `other`'s asset tag may share an account, see
+  /// `asset_tag`. Unknown tags comingle with nothing.
+  pub fn comingles_with(&self, other: &BankConfig) -> bool {
+      let (low, high) = if self.asset_tag <= other.asset_tag {
+          (self.asset_tag, other.asset_tag)
+      } else {
+          (other.asset_tag, self.asset_tag)
+      };
+      matches!(
+          (low, high),
+          (ASSET_TAG_DEFAULT, ASSET_TAG_DEFAULT | ASSET_TAG_SOL | ASSET_TAG_KAMINO)
+              | (ASSET_TAG_SOL, ASSET_TAG_SOL | ASSET_TAG_STAKED)
+              | (ASSET_TAG_STAKED, ASSET_TAG_STAKED)
+              | (ASSET_TAG_KAMINO, ASSET_TAG_KAMINO)
+      )
+  }
+
+  /// `TOTAL_ASSET_VALUE_INIT_LIMIT_INACTIVE` marks the init-margin asset value limit as inactive
+  pub fn is_init_limit_active(&self) -> bool {
+      self.total_asset_value_init_limit != TOTAL_ASSET_VALUE_INIT_LIMIT_INACTIVE
+  }
+
   #[inline]
   pub fn get_oracle_max_age(&self) -> u64 {
       match (self.oracle_max_age, self.oracle_setup) {
@@ -100,6 +122,20 @@ impl BankConfig {
           (n, _) => n as u64,
       }
   }
+
+  /// `oracle_max_confidence` (with 0 resolved to the default 10%) scaled by `multiplier`, clamped
+  /// to the valid (0, 100%] range.
+  pub fn get_oracle_max_confidence_scaled(&self, multiplier: I80F48) -> u32 {
+      let max_confidence = match self.oracle_max_confidence {
+          0 => U32_MAX_DIV_10,
+          n => I80F48::from_num(n),
+      };
+
+      max_confidence
+          .saturating_mul(multiplier)
+          .clamp(I80F48::ONE, U32_MAX)
+          .to_num::<u32>()
+  }
 }
 
 impl Default for BankConfig {
@@ -128,4 +164,57 @@ impl Default for BankConfig {
           _padding1: [0; 16],
       }
   }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn tagged(asset_tag: u8) -> BankConfig {
+    BankConfig { asset_tag, ..BankConfig::default() }
+  }
+
+  #[test]
+  fn comingling_follows_the_asset_tag_rules() {
+    let tags = [ASSET_TAG_DEFAULT, ASSET_TAG_SOL, ASSET_TAG_STAKED, ASSET_TAG_KAMINO];
+    // Rows and columns in the order of `tags`
+    let expected = [
+      [true, true, false, true],
+      [true, true, true, false],
+      [false, true, true, false],
+      [true, false, false, true],
+    ];
+
+    for (i, a) in tags.iter().enumerate() {
+      for (j, b) in tags.iter().enumerate() {
+        assert_eq!(tagged(*a).comingles_with(&tagged(*b)), expected[i][j], "tags {} and {}", a, b);
+      }
+    }
+  }
+
+  #[test]
+  fn unknown_tags_comingle_with_nothing() {
+    for tag in [ASSET_TAG_DEFAULT, ASSET_TAG_SOL, ASSET_TAG_STAKED, ASSET_TAG_KAMINO, 42] {
+      assert!(!tagged(42).comingles_with(&tagged(tag)));
+    }
+  }
+
+  #[test]
+  fn unset_max_confidence_scales_the_default_ten_percent() {
+    let config = BankConfig::default();
+
+    assert_eq!(config.get_oracle_max_confidence_scaled(I80F48::ONE), 429_496_730);
+    assert_eq!(config.get_oracle_max_confidence_scaled(I80F48::from_num(0.5)), 214_748_365);
+  }
+
+  #[test]
+  fn scaled_max_confidence_is_clamped_to_the_valid_range() {
+    let config = BankConfig { oracle_max_confidence: 1_000, ..BankConfig::default() };
+
+    assert_eq!(config.get_oracle_max_confidence_scaled(I80F48::from_num(2)), 2_000);
+    assert_eq!(config.get_oracle_max_confidence_scaled(I80F48::ZERO), 1);
+    assert_eq!(config.get_oracle_max_confidence_scaled(I80F48::from_num(-3)), 1);
+    assert_eq!(config.get_oracle_max_confidence_scaled(I80F48::MAX), u32::MAX);
+  }
+}
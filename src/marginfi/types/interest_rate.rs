@@ -56,6 +56,76 @@ pub struct InterestRateConfig {
     pub _padding3: [u8; 8],
 }
 
+impl InterestRateConfig {
+    /// Base APR at `utilization` (0-1) on the bank's curve, before fees
+    pub fn base_rate(&self, utilization: I80F48) -> Option<I80F48> {
+        match self.curve_type {
+            INTEREST_CURVE_SEVEN_POINT => self.multi_point_base_rate(utilization),
+            _ => self.legacy_base_rate(utilization),
+        }
+    }
+
+    /// Linear up to the plateau rate at the optimal utilization, then linear up to the max rate
+    fn legacy_base_rate(&self, utilization: I80F48) -> Option<I80F48> {
+        let optimal_utilization: I80F48 = self.optimal_utilization_rate.into();
+        let plateau_rate: I80F48 = self.plateau_interest_rate.into();
+        let max_rate: I80F48 = self.max_interest_rate.into();
+
+        if utilization <= optimal_utilization {
+            if optimal_utilization == I80F48::ZERO {
+                return Some(plateau_rate);
+            }
+            utilization.checked_div(optimal_utilization)?.checked_mul(plateau_rate)
+        } else {
+            let excess = utilization.checked_sub(optimal_utilization)?
+                .checked_div(I80F48::ONE.checked_sub(optimal_utilization)?)?;
+            excess.checked_mul(max_rate.checked_sub(plateau_rate)?)?.checked_add(plateau_rate)
+        }
+    }
+
+    /// Piece-wise linear through `zero_util_rate`, the non-zero `points` and `hundred_util_rate`
+    fn multi_point_base_rate(&self, utilization: I80F48) -> Option<I80F48> {
+        let util = centi_to_u32(utilization);
+        let mut previous = RatePoint::new(0, self.zero_util_rate);
+        let points = self.points
+            .iter()
+            .filter(|point| point.util != 0)
+            .copied()
+            .chain(std::iter::once(RatePoint::new(u32::MAX, self.hundred_util_rate)));
+        for point in points {
+            if util <= point.util {
+                let span = I80F48::from_num(point.util.checked_sub(previous.util)?);
+                let into = I80F48::from_num(util.saturating_sub(previous.util));
+                let from_rate = I80F48::from_num(previous.rate);
+                let to_rate = I80F48::from_num(point.rate);
+                let rate = if span == I80F48::ZERO {
+                    to_rate
+                } else {
+                    from_rate.checked_add(to_rate.checked_sub(from_rate)?.checked_mul(into)?.checked_div(span)?)?
+                };
+                return u32_to_milli(rate);
+            }
+            previous = point;
+        }
+        u32_to_milli(I80F48::from_num(self.hundred_util_rate))
+    }
+
+    /// APR borrowers pay for `base_rate`: the base rate plus the rate-proportional and fixed fees
+    pub fn borrowing_rate(&self, base_rate: I80F48) -> Option<I80F48> {
+        let ir_fees = I80F48::from(self.insurance_ir_fee).checked_add(self.protocol_ir_fee.into())?;
+        let fixed_fees = I80F48::from(self.insurance_fee_fixed_apr).checked_add(self.protocol_fixed_fee_apr.into())?;
+        base_rate
+            .checked_mul(I80F48::ONE.checked_add(ir_fees)?)?
+            .checked_add(fixed_fees)
+    }
+}
+
+/// Inverse of `milli_to_u32`: a rate as u32 out of 1000% into a fraction, e.g. 0.1 * u32::MAX
+/// into 1 (100%)
+fn u32_to_milli(rate: I80F48) -> Option<I80F48> {
+    rate.checked_div(I80F48::from_num(u32::MAX))?.checked_mul(I80F48::from_num(10))
+}
+
 #[derive(Clone, Copy, Default, Zeroable, Pod, Debug, PartialEq, Eq)]
 #[repr(C)]
 pub struct RatePoint {
@@ -192,4 +262,78 @@ impl From<InterestRateConfig> for InterestRateConfigCompact {
             points: ir_config.points,
         }
     }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_rate(actual: Option<I80F48>, expected: f64) {
+    let actual = actual.unwrap();
+    assert!((actual - I80F48::from_num(expected)).abs() < I80F48::from_num(1e-6), "{} != {}", actual, expected);
+  }
+
+  /// Legacy curve reaching a 10% plateau at 50% utilization and 100% when fully utilized
+  fn legacy() -> InterestRateConfig {
+    InterestRateConfig {
+      optimal_utilization_rate: I80F48::from_num(0.5).into(),
+      plateau_interest_rate: I80F48::from_num(0.1).into(),
+      max_interest_rate: I80F48::ONE.into(),
+      ..InterestRateConfig::default()
+    }
+  }
+
+  /// Multi-point curve from 0% through 10% at 50% utilization and 50% at 80%, to 300% when fully
+  /// utilized
+  fn multi_point() -> InterestRateConfig {
+    InterestRateConfig {
+      curve_type: INTEREST_CURVE_SEVEN_POINT,
+      zero_util_rate: 0,
+      hundred_util_rate: milli_to_u32(I80F48::from_num(3)),
+      points: make_points(&[
+        RatePoint::new(centi_to_u32(I80F48::from_num(0.5)), milli_to_u32(I80F48::from_num(0.1))),
+        RatePoint::new(centi_to_u32(I80F48::from_num(0.8)), milli_to_u32(I80F48::from_num(0.5))),
+      ]),
+      ..InterestRateConfig::default()
+    }
+  }
+
+  #[test]
+  fn legacy_rate_below_optimal_rises_linearly_to_the_plateau() {
+    assert_rate(legacy().base_rate(I80F48::ZERO), 0.0);
+    assert_rate(legacy().base_rate(I80F48::from_num(0.25)), 0.05);
+  }
+
+  #[test]
+  fn legacy_rate_at_optimal_is_the_plateau() {
+    assert_rate(legacy().base_rate(I80F48::from_num(0.5)), 0.1);
+  }
+
+  #[test]
+  fn legacy_rate_above_optimal_rises_linearly_to_the_max() {
+    assert_rate(legacy().base_rate(I80F48::from_num(0.75)), 0.55);
+    assert_rate(legacy().base_rate(I80F48::ONE), 1.0);
+  }
+
+  #[test]
+  fn multi_point_rate_interpolates_between_its_points() {
+    assert_rate(multi_point().base_rate(I80F48::from_num(0.25)), 0.05);
+    assert_rate(multi_point().base_rate(I80F48::from_num(0.5)), 0.1);
+    assert_rate(multi_point().base_rate(I80F48::from_num(0.65)), 0.3);
+    assert_rate(multi_point().base_rate(I80F48::from_num(0.9)), 1.75);
+    assert_rate(multi_point().base_rate(I80F48::ONE), 3.0);
+  }
+
+  #[test]
+  fn borrowing_rate_adds_the_fees_to_the_base_rate() {
+    let ir_config = InterestRateConfig {
+      insurance_ir_fee: I80F48::from_num(0.05).into(),
+      protocol_ir_fee: I80F48::from_num(0.1).into(),
+      insurance_fee_fixed_apr: I80F48::from_num(0.005).into(),
+      protocol_fixed_fee_apr: I80F48::from_num(0.01).into(),
+      ..legacy()
+    };
+
+    // 10% grown by 15% of itself, plus 1.5% fixed
+    assert_rate(ir_config.borrowing_rate(I80F48::from_num(0.1)), 0.13);
+  }
+}
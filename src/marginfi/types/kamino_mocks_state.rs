@@ -2,7 +2,8 @@ use crate::{assert_struct_align, assert_struct_size, math_error};
 use anchor_lang::{ZeroCopy, prelude::*};
 use bytemuck::{Pod, Zeroable};
 use fixed::types::I80F48;
-use super::super::consts::EXP_10_I80F48;
+use super::super::consts::exp_10_i80f48;
+use super::super::MarginfiError;
 use crate::consts::MARGINFI_PROGRAM_ID;
 
 #[error_code]
@@ -135,10 +136,25 @@ impl ZeroCopy for MinimalReserve {}
 // * `mint_total_supply` aka `total_col` - total amount of collateral tokens that exist
 // * `total_supply` aka `total_liq` - total amount of liquidity tokens under the reserve's control
 impl MinimalReserve {
+    /// The reserve's liquidity decimals must be the bank's `mint_decimals`: bank amounts of Kamino
+    /// collateral are denominated in them too, never in the collateral mint's own (6) decimals.
+    pub fn check_mint_decimals(&self, bank_mint_decimals: u8) -> Result<()> {
+        if self.mint_decimals != bank_mint_decimals as u64 {
+            msg!(
+                "Kamino reserve uses {} decimals but the bank uses {}",
+                self.mint_decimals,
+                bank_mint_decimals
+            );
+            return err!(MarginfiError::KaminoReserveValidationFailed);
+        }
+
+        Ok(())
+    }
+
     /// Returns `(total_liquidity_tokens, total_collateral_tokens)` both in “no-decimals” I80F48
     /// form (i.e. scaled down by 10^mint_decimals).
     pub fn scaled_supplies(&self) -> Result<(I80F48, I80F48)> {
-        let decimals: I80F48 = EXP_10_I80F48[self.mint_decimals as usize];
+        let decimals: I80F48 = exp_10_i80f48(self.mint_decimals as usize).ok_or_else(math_error!())?;
         let total_liq = self
             .calculate_total_supply_i80f48()
             .checked_div(decimals)
@@ -150,6 +166,22 @@ impl MinimalReserve {
         Ok((total_liq, total_col))
     }
 
+    /// Liquidity tokens per collateral token, used to price collateral off the liquidity oracle.
+    /// A reserve without collateral or liquidity has no meaningful ratio and is unusable.
+    pub fn collateral_exchange_ratio(&self) -> Result<I80F48> {
+        let (total_liq, total_col) = self.scaled_supplies()?;
+        if total_col <= I80F48::ZERO || total_liq <= I80F48::ZERO {
+            msg!(
+                "Kamino reserve is empty (liquidity {}, collateral {})",
+                total_liq,
+                total_col
+            );
+            return err!(MarginfiError::KaminoReserveValidationFailed);
+        }
+
+        Ok(total_liq.checked_div(total_col).ok_or_else(math_error!())?)
+    }
+
     // Note: our conversion has less precision than Kamino's internal representation (which uses
     //  U256 to avoid any precision loss), but sufficient for our purposes because we only use these
     //  to sanity check that the user got the expected amount of tokens +/- 1 when
@@ -359,11 +391,8 @@ pub fn convert_decimals(n: I80F48, from_dec: u8, to_dec: u8) -> Result<I80F48> {
     let diff = (to_dec as i32) - (from_dec as i32);
     let abs = diff.unsigned_abs() as usize;
 
-    // The largest amount we support in `EXP_10_I80F48`
-    if abs > 23 {
-        panic!("this many decimals is not supported.")
-    }
-    let scale = EXP_10_I80F48[abs];
+    // Anything beyond the largest amount we support in `EXP_10_I80F48` is a math error
+    let scale = exp_10_i80f48(abs).ok_or_else(math_error!())?;
 
     // if diff > 0, we need more decimals → multiply
     // if diff < 0, we need fewer decimals → divide
@@ -378,4 +407,63 @@ pub fn convert_decimals(n: I80F48, from_dec: u8, to_dec: u8) -> Result<I80F48> {
 
 // Note: see "local_tests.rs" in the mrgnfi program for cargo tests for above functions. We
 // typically run `cargo test --lib` on just marginfi to save time in CI so this is easier than
-// workspace configuration.
\ No newline at end of file
+// workspace configuration.
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  /// A 6 decimal reserve holding `liquidity` native tokens against `collateral` native
+  /// collateral tokens, nothing borrowed
+  fn reserve(liquidity: u64, collateral: u64) -> MinimalReserve {
+    let mut reserve = MinimalReserve::zeroed();
+    reserve.mint_decimals = 6;
+    reserve.available_amount = liquidity;
+    reserve.mint_total_supply = collateral;
+    reserve
+  }
+
+  fn assert_validation_failed<T: std::fmt::Debug>(result: Result<T>) {
+    match result {
+      Err(anchor_lang::error::Error::AnchorError(err)) => {
+        assert_eq!(MarginfiError::from(err.error_code_number), MarginfiError::KaminoReserveValidationFailed);
+      }
+      other => panic!("expected KaminoReserveValidationFailed, got {:?}", other),
+    }
+  }
+
+  #[test]
+  fn reserve_matching_the_bank_decimals_is_accepted() {
+    assert!(reserve(1_000_000, 1_000_000).check_mint_decimals(6).is_ok());
+  }
+
+  #[test]
+  fn reserve_with_other_decimals_than_the_bank_is_rejected() {
+    assert_validation_failed(reserve(1_000_000, 1_000_000).check_mint_decimals(9));
+  }
+
+  #[test]
+  fn reserve_without_collateral_is_rejected() {
+    assert_validation_failed(reserve(1_000_000, 0).collateral_exchange_ratio());
+  }
+
+  #[test]
+  fn reserve_without_liquidity_is_rejected() {
+    assert_validation_failed(reserve(0, 1_000_000).collateral_exchange_ratio());
+  }
+
+  #[test]
+  fn liquidity_above_collateral_is_a_ratio_above_one() {
+    assert_eq!(reserve(1_500_000, 1_000_000).collateral_exchange_ratio().unwrap(), I80F48::from_num(1.5));
+  }
+
+  #[test]
+  fn a_single_native_collateral_token_does_not_overflow() {
+    let ratio = reserve(u64::MAX, 1).collateral_exchange_ratio().unwrap();
+
+    // Scaling both supplies by 1e-6 rounds, so the ratio is only close to the native one
+    let expected = I80F48::from_num(u64::MAX);
+    assert!((ratio - expected).abs() < expected / I80F48::from_num(1_000_000), "{} != {}", ratio, expected);
+  }
+}
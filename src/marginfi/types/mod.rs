@@ -1,17 +1,21 @@
 mod bank;
+mod bank_account_cache;
 mod bank_cache;
 mod bank_config;
 mod emode;
 mod interest_rate;
 mod kamino_mocks_state;
+mod oracle_cache;
 mod price;
 mod user_account;
 
 pub use bank::*;
+pub use bank_account_cache::*;
 pub use bank_cache::*;
 pub use bank_config::*;
 pub use emode::*;
 pub use interest_rate::*;
 pub use kamino_mocks_state::*;
+pub use oracle_cache::*;
 pub use price::*;
 pub use user_account::*;
\ No newline at end of file
@@ -0,0 +1,106 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use anchor_lang::prelude::Pubkey;
+use solana_account::Account;
+
+use crate::utils::Slot;
+
+struct CachedOracleAccount {
+  account: Account,
+  slot: Slot,
+  fetched_at: Instant,
+}
+
+/// Oracle accounts fetched within the last `ttl`, shared across account evaluations so a feed
+/// referenced by many banks (e.g. SOL/USD) is fetched once per `ttl`. Prices are time sensitive,
+/// keep `ttl` well below a second.
+pub struct OracleAccountCache {
+  ttl: Duration,
+  entries: Mutex<HashMap<Pubkey, CachedOracleAccount>>,
+  hits: AtomicU64,
+  misses: AtomicU64,
+}
+
+impl OracleAccountCache {
+  pub fn new(ttl: Duration) -> Self {
+    Self {
+      ttl,
+      entries: Mutex::new(HashMap::new()),
+      hits: AtomicU64::new(0),
+      misses: AtomicU64::new(0),
+    }
+  }
+
+  /// The cached account and the slot it was fetched at, if fetched within `ttl`
+  pub fn get(&self, pubkey: &Pubkey) -> Option<(Account, Slot)> {
+    let entries = self.entries.lock().unwrap();
+    let fresh = entries
+      .get(pubkey)
+      .filter(|entry| entry.fetched_at.elapsed() <= self.ttl)
+      .map(|entry| (entry.account.clone(), entry.slot));
+
+    let counter = if fresh.is_some() { &self.hits } else { &self.misses };
+    counter.fetch_add(1, Ordering::Relaxed);
+    fresh
+  }
+
+  pub fn insert(&self, pubkey: Pubkey, account: Account, slot: Slot) {
+    let mut entries = self.entries.lock().unwrap();
+    entries.retain(|_, entry| entry.fetched_at.elapsed() <= self.ttl);
+    entries.insert(pubkey, CachedOracleAccount { account, slot, fetched_at: Instant::now() });
+  }
+
+  pub fn hits(&self) -> u64 {
+    self.hits.load(Ordering::Relaxed)
+  }
+
+  pub fn misses(&self) -> u64 {
+    self.misses.load(Ordering::Relaxed)
+  }
+
+  /// Fraction of lookups served from the cache, 0 before any lookup
+  pub fn hit_rate(&self) -> f64 {
+    let hits = self.hits();
+    let total = hits + self.misses();
+    if total == 0 {
+      return 0.0;
+    }
+    hits as f64 / total as f64
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  const ORACLE: Pubkey = Pubkey::new_from_array([1; 32]);
+
+  fn oracle_account() -> Account {
+    Account { data: vec![1, 2, 3], ..Account::default() }
+  }
+
+  #[test]
+  fn fetched_oracle_is_served_with_its_slot() {
+    let cache = OracleAccountCache::new(Duration::from_secs(60));
+    cache.insert(ORACLE, oracle_account(), 42);
+
+    assert_eq!(cache.get(&ORACLE), Some((oracle_account(), 42)));
+    assert_eq!((cache.hits(), cache.misses()), (1, 0));
+  }
+
+  #[test]
+  fn expired_oracle_is_a_miss() {
+    let cache = OracleAccountCache::new(Duration::from_millis(1));
+    cache.insert(ORACLE, oracle_account(), 42);
+    std::thread::sleep(Duration::from_millis(5));
+
+    assert_eq!(cache.get(&ORACLE), None);
+    assert_eq!((cache.hits(), cache.misses()), (0, 1));
+    assert_eq!(cache.hit_rate(), 0.0);
+  }
+}
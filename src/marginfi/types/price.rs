@@ -3,19 +3,20 @@ use super::super::consts::{
   SWITCHBOARD_PULL_ID,
 };
 use anchor_lang::prelude::sysvar::clock;
+use anyhow::Context;
 use anchor_lang::prelude::*;
 use anchor_client::solana_sdk::{borsh::try_from_slice_unchecked, stake::state::StakeStateV2};
 use solana_account::Account;
 use solana_rpc_client::nonblocking::rpc_client::RpcClient;
-use crate::utils::parse_account;
+use crate::utils::{fetch_account, fetch_multiple_accounts, parse_account};
 use crate::{check, check_eq, debug, live, math_error};
 use super::super::prelude::*;
 use anchor_spl::token::Mint;
 use enum_dispatch::enum_dispatch;
 use fixed::types::I80F48;
 use super::kamino_mocks_state::{adjust_i128, adjust_i64, adjust_u64, MinimalReserve};
-use super::super::consts::{CONF_INTERVAL_MULTIPLE, EXP_10_I80F48, MAX_CONF_INTERVAL, STD_DEV_MULTIPLE, U32_MAX, U32_MAX_DIV_10};
-use super::{Bank, BankConfig, OracleSetup};
+use super::super::consts::{exp_10_i80f48, CONF_INTERVAL_MULTIPLE, MAX_CONF_INTERVAL, SLOT_DURATION_MS, STD_DEV_MULTIPLE, U32_MAX, U32_MAX_DIV_10};
+use super::{Bank, BankConfig, OracleAccountCache, OracleSetup};
 use pyth_solana_receiver_sdk::price_update::{self, FeedId, PriceUpdateV2};
 use pyth_solana_receiver_sdk::PYTH_PUSH_ORACLE_ID;
 use std::{cell::Ref, cmp::min};
@@ -53,6 +54,36 @@ pub trait PriceAdapter {
   ) -> MarginfiResult<I80F48> {
       self.get_price_of_type(t, b, u32::MAX)
   }
+
+  /// The numbers behind an `OracleMaxConfidenceExceeded` of `get_price_of_type`, None when the
+  /// confidence is within `oracle_max_confidence` or the feed has no confidence interval
+  fn confidence_breach(
+      &self,
+      _price_type: OraclePriceType,
+      _oracle_max_confidence: u32,
+  ) -> MarginfiResult<Option<ConfidenceBreach>> {
+      Ok(None)
+  }
+}
+
+/// A price rejected for a confidence interval wider than the bank allows
+#[derive(Clone, Copy, Debug)]
+pub struct ConfidenceBreach {
+  pub price: I80F48,
+  /// Confidence interval the price was quoted with, scaled like the program scales it
+  pub conf_interval: I80F48,
+  /// Widest confidence interval allowed at `price` by `oracle_max_confidence`
+  pub max_conf: I80F48,
+}
+
+impl std::fmt::Display for ConfidenceBreach {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "confidence interval {} exceeds the max of {} at price {}", self.conf_interval, self.max_conf, self.price)?;
+    if let Some(ratio) = self.conf_interval.checked_div(self.max_conf) {
+      write!(f, " ({:.2}x)", ratio.to_num::<f64>())?;
+    }
+    Ok(())
+  }
 }
 
 #[error_code]
@@ -61,6 +92,56 @@ pub enum OraclePriceFeedAdapterConfigError {
   RpcError,
 }
 
+/// Where `load_multiple_inner` reads oracle accounts from
+#[derive(Clone, Copy)]
+enum OracleSource<'a> {
+  Latest,
+  Cached(&'a OracleAccountCache),
+}
+
+/// `get_multiple_accounts` serving fresh accounts from `cache` and caching the rest with the slot
+/// they were fetched at
+async fn get_multiple_accounts_cached(
+  client: &RpcClient,
+  keys: &[Pubkey],
+  cache: &OracleAccountCache,
+) -> anyhow::Result<Vec<solana_account::Account>> {
+  let mut accounts: Vec<Option<solana_account::Account>> = keys
+    .iter()
+    .map(|key| cache.get(key).map(|(account, _)| account))
+    .collect();
+  let missing: Vec<Pubkey> = keys
+    .iter()
+    .zip(&accounts)
+    .filter(|(_, account)| account.is_none())
+    .map(|(key, _)| *key)
+    .collect();
+
+  let mut fetched = Vec::with_capacity(missing.len());
+  for chunk in missing.chunks(100) {
+    let response = client
+      .get_multiple_accounts_with_commitment(chunk, client.commitment())
+      .await
+      .map_err(|e| anyhow::anyhow!(OraclePriceFeedAdapterConfigError::RpcError).context(e))?;
+    let slot = response.context.slot;
+    for (key, account) in chunk.iter().zip(response.value) {
+      let account = account.ok_or_else(|| anyhow::anyhow!("Oracle account not found: {}", key))?;
+      cache.insert(*key, account.clone(), slot);
+      fetched.push(account);
+    }
+  }
+
+  let mut fetched = fetched.into_iter();
+  for account in accounts.iter_mut().filter(|account| account.is_none()) {
+    *account = fetched.next();
+  }
+
+  accounts
+    .into_iter()
+    .collect::<Option<Vec<_>>>()
+    .context("oracle account missing after fetch")
+}
+
 async fn get_multiple_accounts(
   client: &RpcClient,
   keys: &[Pubkey],
@@ -74,8 +155,7 @@ async fn get_multiple_accounts(
   let mut all_accounts = Vec::with_capacity(keys.len());
   
   for chunk in keys.chunks(BATCH_SIZE) {
-    let accounts = client
-      .get_multiple_accounts(chunk)
+    let accounts = fetch_multiple_accounts(client, chunk)
       .await
       .map_err(|e| anyhow::anyhow!(OraclePriceFeedAdapterConfigError::RpcError).context(e))?;
     
@@ -136,13 +216,28 @@ impl<'info> OraclePriceFeedAdapterConfig<'info> {
     Self::load_multiple_with_max_ages(client, banks, &max_ages).await
   }
 
+  /// Same as `load_multiple`, but oracle accounts fetched within the cache's ttl are reused
+  /// instead of fetched again
+  pub async fn load_multiple_cached(
+    client: &RpcClient,
+    banks: &'info [Bank],
+    cache: &OracleAccountCache
+  ) -> anyhow::Result<Vec<Self>> {
+    let max_ages: Vec<u64> = banks
+      .iter()
+      .map(|bank| bank.config.get_oracle_max_age())
+      .collect();
+    let clock = fetch_clock(client).await?;
+
+    Self::load_multiple_inner(client, banks, clock, &max_ages, OracleSource::Cached(cache)).await
+  }
+
   pub async fn load_multiple_with_max_ages(
     client: &RpcClient,
     banks: &'info [Bank],
     max_ages: &[u64]
   ) -> anyhow::Result<Vec<Self>> {
-    let clock_account = client.get_account(&clock::ID).await?;
-    let clock: Clock = bincode::deserialize(&clock_account.data)?;
+    let clock = fetch_clock(client).await?;
     
     Self::load_multiple_with_clock_and_max_ages(client, banks, clock, max_ages).await
   }
@@ -152,6 +247,16 @@ impl<'info> OraclePriceFeedAdapterConfig<'info> {
     banks: &'info [Bank],
     clock: Clock,
     max_ages: &[u64]
+  ) -> anyhow::Result<Vec<Self>> {
+    Self::load_multiple_inner(client, banks, clock, max_ages, OracleSource::Latest).await
+  }
+
+  async fn load_multiple_inner(
+    client: &RpcClient,
+    banks: &'info [Bank],
+    clock: Clock,
+    max_ages: &[u64],
+    source: OracleSource<'_>
   ) -> anyhow::Result<Vec<Self>> {
     if banks.len() != max_ages.len() {
       return Err(anyhow::anyhow!("banks and max_ages must have same length"));
@@ -178,10 +283,10 @@ impl<'info> OraclePriceFeedAdapterConfig<'info> {
       bank_oracle_mappings.push(bank_indices);
     }
 
-    let oracle_accounts = if unique_oracle_keys.is_empty() {
-      Vec::new()
-    } else {
-      get_multiple_accounts(client, &unique_oracle_keys).await?
+    let oracle_accounts = match source {
+      _ if unique_oracle_keys.is_empty() => Vec::new(),
+      OracleSource::Latest => get_multiple_accounts(client, &unique_oracle_keys).await?,
+      OracleSource::Cached(cache) => get_multiple_accounts_cached(client, &unique_oracle_keys, cache).await?,
     };
 
     let mut configs = Vec::with_capacity(banks.len());
@@ -232,13 +337,60 @@ impl<'info> OraclePriceFeedAdapterConfig<'info> {
   }
 }
 
-fn get_oracle_keys_for_bank(bank: &Bank) -> anyhow::Result<Vec<Pubkey>> {
+/// Fetches the Clock sysvar. If the sysvar can't be decoded (e.g. the RPC returns an unexpected
+/// layout), falls back to synthesizing a clock from `get_slot` + `get_block_time`.
+pub async fn fetch_clock(client: &RpcClient) -> anyhow::Result<Clock> {
+  match fetch_clock_sysvar(client).await {
+    Ok(clock) => Ok(clock),
+    Err(err) => {
+      tracing::warn!(error = %format!("{:#}", err), "clock sysvar unavailable, falling back to get_slot + get_block_time");
+      let slot = client.get_slot().await
+        .context("failed to fetch slot for clock fallback")?;
+      let unix_timestamp = client.get_block_time(slot).await
+        .context("failed to fetch block time for clock fallback")?;
+
+      Ok(Clock {
+        slot,
+        unix_timestamp,
+        ..Clock::default()
+      })
+    }
+  }
+}
+
+async fn fetch_clock_sysvar(client: &RpcClient) -> anyhow::Result<Clock> {
+  const CLOCK_LEN: usize = std::mem::size_of::<Clock>();
+
+  let clock_account = fetch_account(client, &clock::ID).await
+    .context("failed to fetch Clock sysvar")?;
+  if clock_account.data.len() != CLOCK_LEN {
+    return Err(anyhow::anyhow!(
+      "failed to decode Clock sysvar; RPC may be returning an unexpected layout (expected {} bytes, got {})",
+      CLOCK_LEN,
+      clock_account.data.len()
+    ));
+  }
+
+  bincode::deserialize::<Clock>(&clock_account.data)
+    .context("failed to decode Clock sysvar; RPC may be returning an unexpected layout")
+}
+
+/// The program rejects banks on the legacy Pyth and Switchboard V2 setups in every health check,
+/// so an account holding one can't be liquidated whatever its price. Both oracle networks have
+/// also stopped updating these accounts on Solana.
+fn deprecated_oracle_error(setup: OracleSetup) -> anyhow::Error {
+  anyhow::anyhow!(ErrorCode::Deprecated)
+    .context(format!("{:?} oracles are deprecated and rejected by the program", setup))
+}
+
+/// Oracle accounts of `bank`, in the order the program expects them after the bank
+pub(crate) fn get_oracle_keys_for_bank(bank: &Bank) -> anyhow::Result<Vec<Pubkey>> {
   match bank.config.oracle_setup {
     OracleSetup::None => {
       Err(anyhow::anyhow!(MarginfiError::OracleNotSetup))
     }
-    OracleSetup::PythLegacy | OracleSetup::SwitchboardV2 => {
-      Err(anyhow::anyhow!(ErrorCode::Deprecated))
+    setup @ (OracleSetup::PythLegacy | OracleSetup::SwitchboardV2) => {
+      Err(deprecated_oracle_error(setup))
     }
     OracleSetup::PythPushOracle | OracleSetup::SwitchboardPull => {
       Ok(vec![bank.config.oracle_keys[0]])
@@ -260,8 +412,8 @@ fn build_oracle_accounts(bank: &Bank, accounts: Vec<Account>) -> anyhow::Result<
     OracleSetup::None => {
       Err(anyhow::anyhow!(MarginfiError::OracleNotSetup))
     }
-    OracleSetup::PythLegacy | OracleSetup::SwitchboardV2 => {
-      Err(anyhow::anyhow!(ErrorCode::Deprecated))
+    setup @ (OracleSetup::PythLegacy | OracleSetup::SwitchboardV2) => {
+      Err(deprecated_oracle_error(setup))
     }
     OracleSetup::PythPushOracle => {
       Ok(OracleAccounts::PythPush { 
@@ -306,6 +458,14 @@ pub enum OraclePriceFeedAdapter {
 }
 
 impl OraclePriceFeedAdapter {
+  /// Observed age of switchboard feeds, None for other oracles
+  pub fn switchboard_age(&self) -> Option<SwitchboardAge> {
+      match self {
+          OraclePriceFeedAdapter::SwitchboardPull(feed) => Some(feed.age),
+          _ => None,
+      }
+  }
+
   pub fn try_from_config<'info>(config: OraclePriceFeedAdapterConfig<'info>) -> MarginfiResult<Self> {
       match config.accounts {
           OracleAccounts::None => {
@@ -320,9 +480,7 @@ impl OraclePriceFeedAdapter {
               Ok(OraclePriceFeedAdapter::PythPushOracle(feed))
           }
           OracleAccounts::SwitchboardPull { oracle } => {
-              let feed = SwitchboardPullPriceFeed::load_checked(
-                &oracle, config.clock.unix_timestamp, config.max_age
-              )?;
+              let feed = SwitchboardPullPriceFeed::load_checked(&oracle, &config.clock, config.max_age)?;
               Ok(OraclePriceFeedAdapter::SwitchboardPull(feed))
           }
           OracleAccounts::StakedWithPythPush { price, lst_mint, stake_state } => {
@@ -333,10 +491,12 @@ impl OraclePriceFeedAdapter {
                   _ => return Err(ErrorCode::Deprecated.into()), // not supported
               };
 
+              // The pool's first SOL is a rent reserve that backs no LST. A freshly created or
+              // minimally funded pool can delegate less than that, its LST then backs nothing
+              // and is priced at zero rather than failing the whole valuation.
               let sol_pool_balance = stake.delegation.stake;
               let lamports_per_sol: u64 = 1_000_000_000;
-              let sol_pool_adjusted_balance =
-                  sol_pool_balance.checked_sub(lamports_per_sol).ok_or_else(math_error!())?;
+              let sol_pool_adjusted_balance = sol_pool_balance.saturating_sub(lamports_per_sol);
 
               let mut feed = PythPushOraclePriceFeed::load_checked(&price, &config.clock, config.max_age)?;
               let lst_supply = lst_mint.supply;
@@ -358,31 +518,27 @@ impl OraclePriceFeedAdapter {
           }
           OracleAccounts::KaminoPythPush { price, reserve } => {
               let mut price_feed = PythPushOraclePriceFeed::load_checked(&price, &config.clock, config.max_age)?;
-              let (total_liq, total_col) = parse_account::<MinimalReserve>(&reserve.data)
-                  .map_err(|_| ErrorCode::AccountDidNotDeserialize)?
-                  .scaled_supplies()?;
-              if total_col > I80F48::ZERO {
-                  let ratio = total_liq / total_col;
-                  price_feed.price.price = adjust_i64(price_feed.price.price, ratio)?;
-                  price_feed.ema_price.price = adjust_i64(price_feed.ema_price.price, ratio)?;
-                  price_feed.price.conf = adjust_u64(price_feed.price.conf, ratio)?;
-                  price_feed.ema_price.conf = adjust_u64(price_feed.ema_price.conf, ratio)?;
-              }
+              let reserve = parse_account::<MinimalReserve>(&reserve.data)
+                  .map_err(|_| ErrorCode::AccountDidNotDeserialize)?;
+              reserve.check_mint_decimals(config.bank.mint_decimals)?;
+              let ratio = reserve.collateral_exchange_ratio()?;
+              price_feed.price.price = adjust_i64(price_feed.price.price, ratio)?;
+              price_feed.ema_price.price = adjust_i64(price_feed.ema_price.price, ratio)?;
+              price_feed.price.conf = adjust_u64(price_feed.price.conf, ratio)?;
+              price_feed.ema_price.conf = adjust_u64(price_feed.ema_price.conf, ratio)?;
               Ok(OraclePriceFeedAdapter::PythPushOracle(price_feed))
           }
           OracleAccounts::KaminoSwitchboardPull { oracle, reserve } => {
               let mut price_feed =
-                  SwitchboardPullPriceFeed::load_checked(&oracle, config.clock.unix_timestamp, config.max_age)?;
-              let (total_liq, total_col) = parse_account::<MinimalReserve>(&reserve.data)
-                  .map_err(|_| ErrorCode::AccountDidNotDeserialize)?
-                  .scaled_supplies()?;
-              if total_col > I80F48::ZERO {
-                  let ratio = total_liq / total_col;
-                  price_feed.feed.result.value =
-                      adjust_i128(price_feed.feed.result.value, ratio)?;
-                  price_feed.feed.result.std_dev =
-                      adjust_i128(price_feed.feed.result.std_dev, ratio)?;
-              }
+                  SwitchboardPullPriceFeed::load_checked(&oracle, &config.clock, config.max_age)?;
+              let reserve = parse_account::<MinimalReserve>(&reserve.data)
+                  .map_err(|_| ErrorCode::AccountDidNotDeserialize)?;
+              reserve.check_mint_decimals(config.bank.mint_decimals)?;
+              let ratio = reserve.collateral_exchange_ratio()?;
+              price_feed.feed.result.value =
+                  adjust_i128(price_feed.feed.result.value, ratio)?;
+              price_feed.feed.result.std_dev =
+                  adjust_i128(price_feed.feed.result.std_dev, ratio)?;
               Ok(OraclePriceFeedAdapter::SwitchboardPull(price_feed))
           }
       }
@@ -408,14 +564,28 @@ impl PriceAdapter for FixedPriceFeed {
 #[derive(Clone, Debug)]
 pub struct SwitchboardPullPriceFeed {
   pub feed: Box<LitePullFeedAccountData>,
+  pub age: SwitchboardAge,
+}
+
+/// How old a switchboard feed was when loaded. A feed can be written recently while its result
+/// still comes from old samples, so both are tracked.
+#[derive(Copy, Clone, Debug)]
+pub struct SwitchboardAge {
+  /// Seconds since the account was last written
+  pub write_secs: i64,
+  /// Slots since the slot the current result was produced at
+  pub result_slots: u64,
 }
 
 impl SwitchboardPullPriceFeed {
+    /// Fails with `SwitchboardStaleResult` when the account write is within `max_age` but its
+    /// result's slot isn't. A stale write itself is only logged.
     pub fn load_checked(
         account: &solana_account::Account,
-        current_timestamp: i64,
+        clock: &Clock,
         max_age: u64,
     ) -> MarginfiResult<Self> {
+        let current_timestamp = clock.unix_timestamp;
         let account_data = &account.data;
 
         let feed: PullFeedAccountData = parse_swb_ignore_alignment(account_data)?;
@@ -433,8 +603,16 @@ impl SwitchboardPullPriceFeed {
           msg!("SwitchboardPull price is stale for {} secs!", age - max_age as i64)
         }
 
+        let result_slots = clock.slot.saturating_sub(feed.result.slot);
+        let max_age_slots = max_age.saturating_mul(1_000) / SLOT_DURATION_MS;
+        if !is_stale && result_slots > max_age_slots {
+          msg!("SwitchboardPull result is {} slots old despite a write {} secs ago", result_slots, age);
+          return err!(MarginfiError::SwitchboardStaleResult);
+        }
+
         Ok(Self {
             feed: Box::new(lite_feed),
+            age: SwitchboardAge { write_secs: age, result_slots },
         })
     }
 
@@ -449,36 +627,37 @@ impl SwitchboardPullPriceFeed {
       Ok(())
   }
 
-  fn get_price(&self) -> MarginfiResult<I80F48> {
+  /// The mean of the result's samples for `TimeWeighted`, their median for `RealTime`
+  fn get_price(&self, price_type: OraclePriceType) -> MarginfiResult<I80F48> {
       let sw_result = self.feed.result;
-      // Note: Pull oracles support mean (result.mean) or median (result.value)
-      let price: I80F48 = I80F48::from_num(sw_result.value)
-          .checked_div(EXP_10_I80F48[switchboard_on_demand::PRECISION as usize])
+      let value = match price_type {
+          OraclePriceType::TimeWeighted => sw_result.mean,
+          OraclePriceType::RealTime => sw_result.value,
+      };
+      let price: I80F48 = I80F48::from_num(value)
+          .checked_div(exp_10_i80f48(switchboard_on_demand::PRECISION as usize).ok_or_else(math_error!())?)
           .ok_or_else(math_error!())?;
       Ok(price)
   }
 
-  fn get_confidence_interval(&self, oracle_max_confidence: u32) -> MarginfiResult<I80F48> {
+  /// The price, its confidence interval and the widest interval `oracle_max_confidence` allows
+  fn confidence_bounds(&self, price_type: OraclePriceType, oracle_max_confidence: u32) -> MarginfiResult<ConfidenceBreach> {
       let conf_interval: I80F48 = I80F48::from_num(self.feed.result.std_dev)
-          .checked_div(EXP_10_I80F48[switchboard_on_demand::PRECISION as usize])
+          .checked_div(exp_10_i80f48(switchboard_on_demand::PRECISION as usize).ok_or_else(math_error!())?)
           .ok_or_else(math_error!())?
           .checked_mul(STD_DEV_MULTIPLE)
           .ok_or_else(math_error!())?;
 
-      let price = self.get_price()?;
+      let price = self.get_price(price_type)?;
+      let max_conf = max_conf_interval(price, oracle_max_confidence)?;
+
+      Ok(ConfidenceBreach { price, conf_interval, max_conf })
+  }
+
+  fn get_confidence_interval(&self, price_type: OraclePriceType, oracle_max_confidence: u32) -> MarginfiResult<I80F48> {
+      let ConfidenceBreach { price, conf_interval, max_conf } = self.confidence_bounds(price_type, oracle_max_confidence)?;
 
       // Fail the price fetch if confidence > price * oracle_max_confidence
-      let oracle_max_confidence = if oracle_max_confidence > 0 {
-          I80F48::from_num(oracle_max_confidence)
-      } else {
-          // The default max confidence is 10%
-          U32_MAX_DIV_10
-      };
-      let max_conf = price
-          .checked_mul(oracle_max_confidence)
-          .ok_or_else(math_error!())?
-          .checked_div(U32_MAX)
-          .ok_or_else(math_error!())?;
       if conf_interval > max_conf {
           let conf_interval = conf_interval.to_num::<f64>();
           let max_conf = max_conf.to_num::<f64>();
@@ -508,15 +687,15 @@ impl SwitchboardPullPriceFeed {
 impl PriceAdapter for SwitchboardPullPriceFeed {
   fn get_price_of_type(
       &self,
-      _price_type: OraclePriceType,
+      price_type: OraclePriceType,
       bias: Option<PriceBias>,
       oracle_max_confidence: u32,
   ) -> MarginfiResult<I80F48> {
-      let price = self.get_price()?;
+      let price = self.get_price(price_type)?;
 
       match bias {
           Some(price_bias) => {
-              let confidence_interval = self.get_confidence_interval(oracle_max_confidence)?;
+              let confidence_interval = self.get_confidence_interval(price_type, oracle_max_confidence)?;
 
               match price_bias {
                   PriceBias::Low => Ok(price
@@ -530,6 +709,30 @@ impl PriceAdapter for SwitchboardPullPriceFeed {
           None => Ok(price),
       }
   }
+
+  fn confidence_breach(
+      &self,
+      price_type: OraclePriceType,
+      oracle_max_confidence: u32,
+  ) -> MarginfiResult<Option<ConfidenceBreach>> {
+      let bounds = self.confidence_bounds(price_type, oracle_max_confidence)?;
+      Ok((bounds.conf_interval > bounds.max_conf).then_some(bounds))
+  }
+}
+
+/// Widest confidence interval `oracle_max_confidence` allows at `price`, 10% of it when unset
+fn max_conf_interval(price: I80F48, oracle_max_confidence: u32) -> MarginfiResult<I80F48> {
+    let oracle_max_confidence = if oracle_max_confidence > 0 {
+        I80F48::from_num(oracle_max_confidence)
+    } else {
+        // The default max confidence is 10%
+        U32_MAX_DIV_10
+    };
+    Ok(price
+        .checked_mul(oracle_max_confidence)
+        .ok_or_else(math_error!())?
+        .checked_div(U32_MAX)
+        .ok_or_else(math_error!())?)
 }
 
 // TODO remove when swb fixes the alignment issue in their crate
@@ -554,8 +757,9 @@ pub fn parse_swb_ignore_alignment(data: &[u8]) -> MarginfiResult<PullFeedAccount
 }
 
 pub fn load_price_update_v2_checked(account: &solana_account::Account) -> MarginfiResult<PriceUpdateV2> {
-  let price_feed_data = &account.data;
-  let discriminator = &price_feed_data[0..8];
+  let Some((discriminator, price_feed_data)) = account.data.split_at_checked(8) else {
+      return Err(MarginfiError::PythPushInvalidAccount.into());
+  };
   let expected_discrim = <PriceUpdateV2 as anchor_lang::Discriminator>::DISCRIMINATOR;
 
   check_eq!(
@@ -565,7 +769,7 @@ pub fn load_price_update_v2_checked(account: &solana_account::Account) -> Margin
   );
 
   Ok(PriceUpdateV2::deserialize(
-      &mut &price_feed_data[8..],
+      &mut &price_feed_data[..],
   )?)
 }
 
@@ -643,7 +847,7 @@ impl PythPushOraclePriceFeed {
       let price = price_feed_account
           .get_price_unchecked(&price_feed_account.price_message.feed_id)
           .map_err(|e| {
-              println!("Pyth push oracle error: {:?}", e);
+              tracing::debug!(error = ?e, "pyth push oracle error");
               let error: MarginfiError = e.into();
               error
           })?;
@@ -671,17 +875,30 @@ impl PythPushOraclePriceFeed {
       })
   }
 
+  /// A feed quoting `price` and `ema_price` with confidences `conf` and `ema_conf`, all scaled by
+  /// 10^`exponent`
+  #[cfg(test)]
+  pub fn from_components(price: i64, conf: u64, ema_price: i64, ema_conf: u64, exponent: i32) -> Self {
+      let component = |price, conf| Box::new(pyth_solana_receiver_sdk::price_update::Price {
+          price,
+          conf,
+          exponent,
+          publish_time: 0,
+      });
+      Self {
+          price: component(price, conf),
+          ema_price: component(ema_price, ema_conf),
+      }
+  }
+
   pub fn peek_feed_id(account: &solana_account::Account) -> MarginfiResult<FeedId> {
       let price_feed_account = load_price_update_v2_checked(account)?;
 
       Ok(price_feed_account.price_message.feed_id)
   }
 
-  fn get_confidence_interval(
-      &self,
-      use_ema: bool,
-      oracle_max_confidence: u32,
-  ) -> MarginfiResult<I80F48> {
+  /// The price, its confidence interval and the widest interval `oracle_max_confidence` allows
+  fn confidence_bounds(&self, use_ema: bool, oracle_max_confidence: u32) -> MarginfiResult<ConfidenceBreach> {
       let price = if use_ema {
           &self.ema_price
       } else {
@@ -694,19 +911,19 @@ impl PythPushOraclePriceFeed {
               .ok_or_else(math_error!())?;
 
       let price = pyth_price_components_to_i80f48(I80F48::from_num(price.price), price.exponent)?;
+      let max_conf = max_conf_interval(price, oracle_max_confidence)?;
+
+      Ok(ConfidenceBreach { price, conf_interval, max_conf })
+  }
+
+  fn get_confidence_interval(
+      &self,
+      use_ema: bool,
+      oracle_max_confidence: u32,
+  ) -> MarginfiResult<I80F48> {
+      let ConfidenceBreach { price, conf_interval, max_conf } = self.confidence_bounds(use_ema, oracle_max_confidence)?;
 
       // Fail the price fetch if confidence > price * oracle_max_confidence
-      let oracle_max_confidence = if oracle_max_confidence > 0 {
-          I80F48::from_num(oracle_max_confidence)
-      } else {
-          // The default max confidence is 10%
-          U32_MAX_DIV_10
-      };
-      let max_conf = price
-          .checked_mul(oracle_max_confidence)
-          .ok_or_else(math_error!())?
-          .checked_div(U32_MAX)
-          .ok_or_else(math_error!())?;
       if conf_interval > max_conf {
           let price = price.to_num::<f64>();
           let conf_interval = conf_interval.to_num::<f64>();
@@ -794,6 +1011,18 @@ impl PriceAdapter for PythPushOraclePriceFeed {
           }
       }
   }
+
+  fn confidence_breach(
+      &self,
+      price_type: OraclePriceType,
+      oracle_max_confidence: u32,
+  ) -> MarginfiResult<Option<ConfidenceBreach>> {
+      let bounds = self.confidence_bounds(
+          matches!(price_type, OraclePriceType::TimeWeighted),
+          oracle_max_confidence,
+      )?;
+      Ok((bounds.conf_interval > bounds.max_conf).then_some(bounds))
+  }
 }
 
 /// A slimmed down version of the PullFeedAccountData struct copied from the
@@ -827,7 +1056,7 @@ impl From<Ref<'_, PullFeedAccountData>> for LitePullFeedAccountData {
 
 #[inline(always)]
 fn pyth_price_components_to_i80f48(price: I80F48, exponent: i32) -> MarginfiResult<I80F48> {
-  let scaling_factor = EXP_10_I80F48[exponent.unsigned_abs() as usize];
+  let scaling_factor = exp_10_i80f48(exponent.unsigned_abs() as usize).ok_or_else(math_error!())?;
 
   let price = if exponent == 0 {
       price
@@ -842,4 +1071,256 @@ fn pyth_price_components_to_i80f48(price: I80F48, exponent: i32) -> MarginfiResu
   };
 
   Ok(price)
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+  use anchor_lang::solana_program::program_pack::Pack;
+  use bytemuck::Zeroable;
+  use pretty_assertions::assert_eq;
+  use solana_rpc_client::mock_sender::MocksMap;
+  use solana_rpc_client_types::request::RpcRequest;
+
+  use super::*;
+  use crate::marginfi::fixtures::{clock_response, response, rpc_account, ui_account};
+
+  const MAX_AGE: u64 = 60;
+
+  fn clock() -> Clock {
+    Clock { slot: 100_000, unix_timestamp: 1_700_000_000, ..Clock::default() }
+  }
+
+  /// A switchboard feed account written `write_secs` before `clock` with a $10 result produced
+  /// `result_slots` before it
+  fn switchboard_account(write_secs: i64, result_slots: u64) -> Account {
+    let mut feed = PullFeedAccountData::zeroed();
+    feed.last_update_timestamp = clock().unix_timestamp - write_secs;
+    feed.result.slot = clock().slot - result_slots;
+    feed.result.value = 10 * 10i128.pow(switchboard_on_demand::PRECISION);
+    let mut data = PullFeedAccountData::DISCRIMINATOR.to_vec();
+    data.extend_from_slice(bytemuck::bytes_of(&feed));
+    Account { data, ..Account::default() }
+  }
+
+  fn marginfi_error(err: anchor_lang::error::Error) -> Option<MarginfiError> {
+    match err {
+      anchor_lang::error::Error::AnchorError(err) => Some(MarginfiError::from(err.error_code_number)),
+      _ => None,
+    }
+  }
+
+  #[test]
+  fn fresh_switchboard_feed_reports_its_age() {
+    let feed = SwitchboardPullPriceFeed::load_checked(&switchboard_account(5, 10), &clock(), MAX_AGE).unwrap();
+
+    assert_eq!(feed.age.write_secs, 5);
+    assert_eq!(feed.age.result_slots, 10);
+    assert_eq!(
+      OraclePriceFeedAdapter::SwitchboardPull(feed).switchboard_age().map(|age| age.result_slots),
+      Some(10),
+    );
+  }
+
+  #[test]
+  fn recent_write_of_an_old_result_is_stale() {
+    // 60 secs are 150 slots
+    let err = SwitchboardPullPriceFeed::load_checked(&switchboard_account(5, 151), &clock(), MAX_AGE).unwrap_err();
+
+    assert_eq!(marginfi_error(err), Some(MarginfiError::SwitchboardStaleResult));
+  }
+
+  #[test]
+  fn old_write_is_not_a_stale_result() {
+    let feed = SwitchboardPullPriceFeed::load_checked(&switchboard_account(120, 400), &clock(), MAX_AGE).unwrap();
+
+    assert_eq!(feed.age.write_secs, 120);
+  }
+
+  #[test]
+  fn other_oracles_have_no_switchboard_age() {
+    let feed = OraclePriceFeedAdapter::Fixed(FixedPriceFeed { price: I80F48::ONE });
+
+    assert!(feed.switchboard_age().is_none());
+  }
+
+  /// A switchboard feed whose samples have a $10 median and an $11 mean
+  fn switchboard_feed() -> SwitchboardPullPriceFeed {
+    let mut feed = PullFeedAccountData::zeroed();
+    feed.result.value = 10 * 10i128.pow(switchboard_on_demand::PRECISION);
+    feed.result.mean = 11 * 10i128.pow(switchboard_on_demand::PRECISION);
+    SwitchboardPullPriceFeed {
+      feed: Box::new(LitePullFeedAccountData::from(&feed)),
+      age: SwitchboardAge { write_secs: 0, result_slots: 0 },
+    }
+  }
+
+  #[test]
+  fn switchboard_real_time_price_is_the_median() {
+    let price = switchboard_feed().get_price_of_type(OraclePriceType::RealTime, None, 0).unwrap();
+
+    assert_eq!(price, I80F48::from_num(10));
+  }
+
+  #[test]
+  fn switchboard_time_weighted_price_is_the_mean() {
+    let price = switchboard_feed().get_price_of_type(OraclePriceType::TimeWeighted, None, 0).unwrap();
+
+    assert_eq!(price, I80F48::from_num(11));
+  }
+
+  #[tokio::test]
+  async fn every_oracle_load_sees_the_current_clock() {
+    let at = |unix_timestamp| Clock { unix_timestamp, ..clock() };
+    let mocks: MocksMap = [
+      (RpcRequest::GetAccountInfo, clock_response(&at(1_700_000_000))),
+      (RpcRequest::GetAccountInfo, clock_response(&at(1_700_003_600))),
+    ]
+    .into_iter()
+    .collect();
+    let rpc_client = RpcClient::new_mock_with_mocks_map("fails", mocks);
+
+    assert_eq!(fetch_clock(&rpc_client).await.unwrap().unix_timestamp, 1_700_000_000);
+    assert_eq!(fetch_clock(&rpc_client).await.unwrap().unix_timestamp, 1_700_003_600);
+  }
+
+  #[tokio::test]
+  async fn undecodable_clock_falls_back_to_slot_and_block_time() {
+    let truncated = ui_account(&clock::ID, &rpc_account(Pubkey::default(), vec![0; 8]));
+    let mocks: MocksMap = [
+      (RpcRequest::GetAccountInfo, response(truncated)),
+      (RpcRequest::GetSlot, serde_json::json!(42)),
+      (RpcRequest::GetBlockTime, serde_json::json!(1_700_000_000)),
+    ]
+    .into_iter()
+    .collect();
+    let rpc_client = RpcClient::new_mock_with_mocks_map("fails", mocks);
+
+    let clock = fetch_clock(&rpc_client).await.unwrap();
+    assert_eq!((clock.slot, clock.unix_timestamp), (42, 1_700_000_000));
+  }
+
+  /// A full-verified pyth update of $10 published at `publish_time`
+  fn pyth_account(publish_time: i64) -> Account {
+    let update = PriceUpdateV2 {
+      write_authority: Pubkey::default(),
+      verification_level: price_update::VerificationLevel::Full,
+      price_message: price_update::PriceFeedMessage {
+        feed_id: [0; 32],
+        price: 1_000,
+        conf: 1,
+        exponent: -2,
+        publish_time,
+        prev_publish_time: publish_time - 1,
+        ema_price: 1_000,
+        ema_conf: 1,
+      },
+      posted_slot: clock().slot,
+    };
+    let mut data = <PriceUpdateV2 as anchor_lang::Discriminator>::DISCRIMINATOR.to_vec();
+    update.serialize(&mut data).unwrap();
+    Account { data, owner: pyth_solana_receiver_sdk::ID, ..Account::default() }
+  }
+
+  #[tokio::test]
+  async fn cached_oracle_is_still_checked_against_the_current_clock() {
+    let oracle = Pubkey::new_unique();
+    let mut bank = crate::marginfi::fixtures::bank();
+    bank.config.oracle_setup = OracleSetup::PythPushOracle;
+    bank.config.oracle_keys[0] = oracle;
+    bank.config.oracle_max_age = MAX_AGE as u16;
+    let cache = OracleAccountCache::new(std::time::Duration::from_secs(60));
+    cache.insert(oracle, pyth_account(clock().unix_timestamp), clock().slot);
+    // Only the clock is served, the oracle has to come from the cache
+    let later = Clock { unix_timestamp: clock().unix_timestamp + MAX_AGE as i64 + 1, ..clock() };
+    let mocks: MocksMap = [(RpcRequest::GetAccountInfo, clock_response(&later))].into_iter().collect();
+    let rpc_client = RpcClient::new_mock_with_mocks_map("fails", mocks);
+    let banks = [bank];
+
+    let mut configs = OraclePriceFeedAdapterConfig::load_multiple_cached(&rpc_client, &banks, &cache).await.unwrap();
+    assert_eq!(cache.hits(), 1);
+    let err = OraclePriceFeedAdapter::try_from_config(configs.remove(0)).err().unwrap();
+    assert_eq!(marginfi_error(err), Some(MarginfiError::PythPushStalePrice));
+  }
+
+  /// A staked bank's oracle config: the $10 pyth feed, 1 LST minted and `stake` lamports delegated
+  /// by the pool
+  fn staked_config(bank: &Bank, stake: u64) -> OraclePriceFeedAdapterConfig<'_> {
+    use anchor_client::solana_sdk::stake::stake_flags::StakeFlags;
+    use anchor_client::solana_sdk::stake::state::{Delegation, Meta, Stake};
+
+    let delegation = Delegation { stake, ..Delegation::default() };
+    let stake_state = StakeStateV2::Stake(Meta::default(), Stake { delegation, credits_observed: 0 }, StakeFlags::empty());
+    let mut stake_data = Vec::new();
+    stake_state.serialize(&mut stake_data).unwrap();
+    let mut mint = vec![0; anchor_spl::token::spl_token::state::Mint::LEN];
+    anchor_spl::token::spl_token::state::Mint { is_initialized: true, supply: 1_000_000_000, decimals: 9, ..Default::default() }
+      .pack_into_slice(&mut mint);
+    OraclePriceFeedAdapterConfig {
+      bank,
+      accounts: OracleAccounts::StakedWithPythPush {
+        price: pyth_account(clock().unix_timestamp),
+        lst_mint: Mint::try_deserialize(&mut mint.as_slice()).unwrap(),
+        stake_state: Account { data: stake_data, ..Account::default() },
+      },
+      clock: clock(),
+      max_age: MAX_AGE,
+    }
+  }
+
+  fn staked_price(stake: u64) -> I80F48 {
+    let bank = crate::marginfi::fixtures::bank();
+    OraclePriceFeedAdapter::try_from_config(staked_config(&bank, stake))
+      .unwrap()
+      .get_price_of_type(OraclePriceType::RealTime, None, 0)
+      .unwrap()
+  }
+
+  #[test]
+  fn pool_delegating_less_than_its_rent_sol_prices_the_lst_at_zero() {
+    assert_eq!(staked_price(500_000_000), I80F48::ZERO);
+  }
+
+  #[test]
+  fn only_stake_beyond_the_first_sol_backs_the_lst() {
+    assert_eq!(staked_price(1_000_000_000), I80F48::ZERO);
+    assert_eq!(staked_price(2_000_000_000), I80F48::from_num(10));
+  }
+
+  #[tokio::test]
+  async fn mixed_oracle_banks_load_in_one_multi_get() {
+    let key = |i: u8| Pubkey::new_from_array([i; 32]);
+    let with_oracles = |setup: OracleSetup, keys: &[Pubkey]| {
+      let mut bank = crate::marginfi::fixtures::bank();
+      bank.config.oracle_setup = setup;
+      bank.config.oracle_keys[..keys.len()].copy_from_slice(keys);
+      bank
+    };
+    // The kamino bank shares the pyth bank's feed, which is fetched once
+    let banks = [
+      with_oracles(OracleSetup::PythPushOracle, &[key(1)]),
+      with_oracles(OracleSetup::KaminoPythPush, &[key(1), key(2)]),
+      with_oracles(OracleSetup::StakedWithPythPush, &[key(3), key(4), key(5)]),
+    ];
+    let mut mint = vec![0; anchor_spl::token::spl_token::state::Mint::LEN];
+    anchor_spl::token::spl_token::state::Mint { is_initialized: true, ..Default::default() }.pack_into_slice(&mut mint);
+    let accounts: Vec<_> = [(1, vec![1]), (2, vec![2]), (3, vec![3]), (4, mint), (5, vec![5])]
+      .into_iter()
+      .map(|(i, data)| ui_account(&key(i), &rpc_account(Pubkey::default(), data)))
+      .collect();
+    // Unmocked requests fail, so a second multi-get would fail the load
+    let mocks: MocksMap = [(RpcRequest::GetMultipleAccounts, response(serde_json::json!(accounts)))].into_iter().collect();
+    let rpc_client = RpcClient::new_mock_with_mocks_map("fails", mocks);
+
+    let configs = OraclePriceFeedAdapterConfig::load_multiple_with_clock_and_max_ages(&rpc_client, &banks, clock(), &[60; 3])
+      .await
+      .unwrap();
+    assert!(matches!(&configs[0].accounts, OracleAccounts::PythPush { price } if price.data == [1]));
+    assert!(matches!(
+      &configs[1].accounts,
+      OracleAccounts::KaminoPythPush { price, reserve } if price.data == [1] && reserve.data == [2]
+    ));
+    assert!(matches!(
+      &configs[2].accounts,
+      OracleAccounts::StakedWithPythPush { price, stake_state, .. } if price.data == [3] && stake_state.data == [5]
+    ));
+  }
+}
@@ -93,6 +93,41 @@ impl MarginfiAccount {
           program_id,
       )
   }
+
+  pub fn get_flag(&self, flag: u64) -> bool {
+      self.account_flags & flag != 0
+  }
+
+  pub fn is_disabled(&self) -> bool {
+      self.get_flag(ACCOUNT_DISABLED)
+  }
+
+  pub fn in_flashloan(&self) -> bool {
+      self.get_flag(ACCOUNT_IN_FLASHLOAN)
+  }
+
+  pub fn in_receivership(&self) -> bool {
+      self.get_flag(ACCOUNT_IN_RECEIVERSHIP)
+  }
+
+  pub fn in_deleverage(&self) -> bool {
+      self.get_flag(ACCOUNT_IN_DELEVERAGE)
+  }
+
+  /// Why the account's flags forbid liquidating it, if they do
+  pub fn liquidation_blocked_reason(&self) -> Option<&'static str> {
+      if self.is_disabled() {
+          Some("account is disabled")
+      } else if self.in_flashloan() {
+          Some("account is in a flashloan")
+      } else if self.in_receivership() {
+          Some("account is already in receivership")
+      } else if self.in_deleverage() {
+          Some("account is being deleveraged")
+      } else {
+          None
+      }
+  }
 }
 
 impl Discriminator for MarginfiAccount {
@@ -128,8 +163,28 @@ impl LendingAccount {
   pub fn get_active_balances_iter(&self) -> impl Iterator<Item = &Balance> {
       self.balances.iter().filter(|b| b.is_active())
   }
+
+  pub fn has_liabilities(&self) -> bool {
+      self.get_active_balances_iter()
+          .any(|balance| !balance.is_empty(BalanceSide::Liabilities))
+  }
+
+  /// Active balances holding a deposit
+  pub fn active_asset_count(&self) -> usize {
+      self.get_active_balances_iter()
+          .filter(|balance| !balance.is_empty(BalanceSide::Assets))
+          .count()
+  }
+
+  /// Active balances holding a borrow
+  pub fn active_liability_count(&self) -> usize {
+      self.get_active_balances_iter()
+          .filter(|balance| !balance.is_empty(BalanceSide::Liabilities))
+          .count()
+  }
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum BalanceSide {
   Assets,
   Liabilities,
@@ -167,13 +222,12 @@ impl Balance {
   /// that might have occured during depositing/withdrawing.
   #[inline]
   pub fn is_empty(&self, side: BalanceSide) -> bool {
-      let shares: I80F48 = match side {
+      let shares = match side {
           BalanceSide::Assets => self.asset_shares,
           BalanceSide::Liabilities => self.liability_shares,
-      }
-      .into();
+      };
 
-      shares < EMPTY_BALANCE_THRESHOLD
+      shares < WrappedI80F48::from(EMPTY_BALANCE_THRESHOLD)
   }
 
   pub fn get_side(&self) -> Option<BalanceSide> {
@@ -206,4 +260,81 @@ impl Balance {
           _padding: [0; 1],
       }
   }
-}
\ No newline at end of file
+}
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn account_with_flags(account_flags: u64) -> MarginfiAccount {
+    MarginfiAccount { account_flags, ..MarginfiAccount::zeroed() }
+  }
+
+  /// An account with one balance per entry of `shares`, holding (asset, liability) shares
+  fn account_with_balances(shares: &[(f64, f64)]) -> MarginfiAccount {
+    let mut account = MarginfiAccount::zeroed();
+    for (balance, (asset_shares, liability_shares)) in account.lending_account.balances.iter_mut().zip(shares) {
+      balance.set_active(true);
+      balance.asset_shares = I80F48::from_num(*asset_shares).into();
+      balance.liability_shares = I80F48::from_num(*liability_shares).into();
+    }
+    account
+  }
+
+  #[test]
+  fn only_assets_has_no_liabilities() {
+    let lending_account = account_with_balances(&[(100.0, 0.0), (5.0, 0.0)]).lending_account;
+
+    assert_eq!(lending_account.active_asset_count(), 2);
+    assert_eq!(lending_account.active_liability_count(), 0);
+    assert!(!lending_account.has_liabilities());
+  }
+
+  #[test]
+  fn only_liabilities_has_no_assets() {
+    let lending_account = account_with_balances(&[(0.0, 100.0)]).lending_account;
+
+    assert_eq!(lending_account.active_asset_count(), 0);
+    assert_eq!(lending_account.active_liability_count(), 1);
+    assert!(lending_account.has_liabilities());
+  }
+
+  #[test]
+  fn mixed_balances_are_counted_per_side() {
+    let mut account = account_with_balances(&[(100.0, 0.0), (0.0, 50.0), (0.0, 0.0001), (20.0, 0.0)]);
+    // Inactive balances don't count whatever their shares
+    account.lending_account.balances[4].asset_shares = I80F48::from_num(10).into();
+
+    // Dust below the empty threshold isn't a liability
+    assert_eq!(account.lending_account.active_asset_count(), 2);
+    assert_eq!(account.lending_account.active_liability_count(), 1);
+    assert!(account.lending_account.has_liabilities());
+  }
+
+  #[test]
+  fn each_blocking_flag_has_its_reason() {
+    let reason = |flags| account_with_flags(flags).liquidation_blocked_reason();
+
+    assert_eq!(reason(ACCOUNT_DISABLED), Some("account is disabled"));
+    assert_eq!(reason(ACCOUNT_IN_FLASHLOAN), Some("account is in a flashloan"));
+    assert_eq!(reason(ACCOUNT_IN_RECEIVERSHIP), Some("account is already in receivership"));
+    assert_eq!(reason(ACCOUNT_IN_DELEVERAGE), Some("account is being deleveraged"));
+  }
+
+  #[test]
+  fn deprecated_flags_do_not_block() {
+    let account = account_with_flags(ACCOUNT_FLAG_DEPRECATED | ACCOUNT_TRANSFER_AUTHORITY_DEPRECATED);
+
+    assert_eq!(account.liquidation_blocked_reason(), None);
+    assert_eq!(account_with_flags(0).liquidation_blocked_reason(), None);
+  }
+
+  #[test]
+  fn disabled_is_reported_before_other_flags() {
+    let account = account_with_flags(ACCOUNT_IN_FLASHLOAN | ACCOUNT_DISABLED);
+
+    assert!(account.in_flashloan());
+    assert_eq!(account.liquidation_blocked_reason(), Some("account is disabled"));
+  }
+}
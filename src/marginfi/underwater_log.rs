@@ -0,0 +1,99 @@
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::Context;
+
+use super::AccountSnapshot;
+
+/// A line of the underwater log. `I80F48` values are rendered as decimal strings so no precision
+/// is lost.
+#[derive(serde::Serialize)]
+struct UnderwaterRecord {
+  account: String,
+  /// Unix timestamp of the evaluation
+  timestamp: u64,
+  health_factor: String,
+  asset_value: String,
+  liability_value: String,
+  /// Transaction whose event triggered the evaluation, none for sweeps
+  signature: Option<String>,
+}
+
+/// Appends every account found liquidatable to a file, one JSON object per line
+pub struct UnderwaterLog {
+  writer: Mutex<BufWriter<std::fs::File>>,
+}
+
+impl UnderwaterLog {
+  pub fn open(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+    let path = path.as_ref();
+    let file = OpenOptions::new()
+      .create(true)
+      .append(true)
+      .open(path)
+      .with_context(|| format!("failed to open underwater log {}", path.display()))?;
+
+    anyhow::Ok(Self { writer: Mutex::new(BufWriter::new(file)) })
+  }
+
+  pub fn record(&self, snapshot: &AccountSnapshot, signature: Option<&str>) -> anyhow::Result<()> {
+    let record = UnderwaterRecord {
+      account: snapshot.account.to_string(),
+      timestamp: SystemTime::now().duration_since(UNIX_EPOCH).map(|now| now.as_secs()).unwrap_or_default(),
+      health_factor: snapshot.health_factor.to_string(),
+      asset_value: snapshot.asset_value.to_string(),
+      liability_value: snapshot.liability_value.to_string(),
+      signature: signature.map(str::to_string),
+    };
+
+    let mut writer = self.writer.lock().unwrap();
+    serde_json::to_writer(&mut *writer, &record).context("failed to serialize underwater record")?;
+    writer.write_all(b"\n")?;
+    writer.flush()?;
+
+    anyhow::Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use anchor_lang::prelude::Pubkey;
+  use fixed::types::I80F48;
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  fn snapshot(account: u8) -> AccountSnapshot {
+    AccountSnapshot {
+      account: Pubkey::new_from_array([account; 32]),
+      authority: Pubkey::default(),
+      asset_value: I80F48::from_num(1_000),
+      liability_value: I80F48::from_num(1_100),
+      maintenance: I80F48::from_num(-300),
+      health_factor: I80F48::from_num(0.75),
+      positions: Vec::new(),
+    }
+  }
+
+  #[test]
+  fn records_are_appended_across_opens() {
+    let path = std::env::temp_dir().join(format!("liquidation_searcher_underwater_{}.jsonl", std::process::id()));
+    UnderwaterLog::open(&path).unwrap().record(&snapshot(1), Some("signature")).unwrap();
+    UnderwaterLog::open(&path).unwrap().record(&snapshot(2), None).unwrap();
+
+    let contents = std::fs::read_to_string(&path).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let records: Vec<serde_json::Value> = contents.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+    assert_eq!(records.len(), 2);
+    assert_eq!(records[0]["account"], Pubkey::new_from_array([1; 32]).to_string());
+    assert_eq!(records[0]["health_factor"], "0.75");
+    assert_eq!(records[0]["asset_value"], "1000");
+    assert_eq!(records[0]["liability_value"], "1100");
+    assert_eq!(records[0]["signature"], "signature");
+    assert_eq!(records[1]["account"], Pubkey::new_from_array([2; 32]).to_string());
+    assert!(records[1]["signature"].is_null());
+  }
+}
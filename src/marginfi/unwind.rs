@@ -0,0 +1,191 @@
+use std::future::Future;
+use std::pin::Pin;
+use std::str::FromStr;
+
+use anchor_lang::prelude::{AccountMeta, Pubkey};
+use anchor_lang::solana_program::instruction::Instruction;
+use anyhow::Context;
+use base64::{Engine as _, engine::general_purpose};
+use fixed::types::I80F48;
+
+/// Sale of seized collateral back into the repaid asset
+#[derive(Clone, Debug)]
+pub struct UnwindRequest {
+  /// Wallet holding the seized collateral, signs the swap
+  pub owner: Pubkey,
+  pub input_mint: Pubkey,
+  pub output_mint: Pubkey,
+  /// Collateral to sell, in native units of `input_mint`
+  pub amount: u64,
+}
+
+/// Instructions performing an unwind, with its expected cost
+#[derive(Clone, Debug)]
+pub struct UnwindPlan {
+  pub instructions: Vec<Instruction>,
+  /// Lookup tables the instructions rely on, the transaction must be a v0 one when non-empty
+  pub address_lookup_tables: Vec<Pubkey>,
+  /// Expected output, in native units of `output_mint`
+  pub expected_out: u64,
+  /// Output below which the swap fails, in native units of `output_mint`
+  pub min_out: u64,
+  /// Fraction of the input value expected to be lost to price impact and worst-case slippage
+  pub cost_fraction: I80F48,
+}
+
+pub type UnwindFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<UnwindPlan>> + 'a>>;
+
+/// Turns seized collateral back into the repaid asset after a liquidation, so the profit is
+/// realized. Operators that settle manually run without one.
+pub trait CollateralUnwinder {
+  fn plan<'a>(&'a self, request: &'a UnwindRequest) -> UnwindFuture<'a>;
+}
+
+/// Routes the unwind through the Jupiter swap API
+pub struct JupiterUnwinder {
+  http: reqwest::Client,
+  api_url: String,
+  slippage_bps: u16,
+}
+
+impl JupiterUnwinder {
+  pub fn new(api_url: impl Into<String>, slippage_bps: u16) -> Self {
+    Self {
+      http: reqwest::Client::new(),
+      api_url: api_url.into().trim_end_matches('/').to_string(),
+      slippage_bps,
+    }
+  }
+
+  async fn quote(&self, request: &UnwindRequest) -> anyhow::Result<serde_json::Value> {
+    self.http
+      .get(format!("{}/quote", self.api_url))
+      .query(&[
+        ("inputMint", request.input_mint.to_string()),
+        ("outputMint", request.output_mint.to_string()),
+        ("amount", request.amount.to_string()),
+        ("slippageBps", self.slippage_bps.to_string()),
+      ])
+      .send()
+      .await?
+      .error_for_status()
+      .context("jupiter quote failed")?
+      .json()
+      .await
+      .context("invalid jupiter quote")
+  }
+
+  async fn swap_instructions(&self, request: &UnwindRequest, quote: &serde_json::Value) -> anyhow::Result<SwapInstructionsResponse> {
+    self.http
+      .post(format!("{}/swap-instructions", self.api_url))
+      .json(&serde_json::json!({
+        "quoteResponse": quote,
+        "userPublicKey": request.owner.to_string(),
+        "wrapAndUnwrapSol": false,
+      }))
+      .send()
+      .await?
+      .error_for_status()
+      .context("jupiter swap instructions failed")?
+      .json()
+      .await
+      .context("invalid jupiter swap instructions")
+  }
+
+  async fn plan_swap(&self, request: &UnwindRequest) -> anyhow::Result<UnwindPlan> {
+    let quote = self.quote(request).await?;
+    let expected_out = quote_amount(&quote, "outAmount")?;
+    let min_out = quote_amount(&quote, "otherAmountThreshold")?;
+    let price_impact = quote["priceImpactPct"]
+      .as_str()
+      .context("quote is missing priceImpactPct")?
+      .parse::<f64>()
+      .context("invalid priceImpactPct")?;
+    let slippage = I80F48::from_num(self.slippage_bps) / I80F48::from_num(10_000);
+    let cost_fraction = I80F48::checked_from_num(price_impact)
+      .context("invalid priceImpactPct")?
+      .saturating_add(slippage);
+
+    let response = self.swap_instructions(request, &quote).await?;
+    // Compute budget instructions are left out, the liquidation transaction sizes its own budget
+    let instructions = response.setup_instructions
+      .into_iter()
+      .chain(std::iter::once(response.swap_instruction))
+      .chain(response.cleanup_instruction)
+      .map(Instruction::try_from)
+      .collect::<anyhow::Result<Vec<_>>>()?;
+    let address_lookup_tables = response.address_lookup_table_addresses
+      .iter()
+      .map(|address| Pubkey::from_str(address).context("invalid lookup table address"))
+      .collect::<anyhow::Result<Vec<_>>>()?;
+
+    anyhow::Ok(UnwindPlan {
+      instructions,
+      address_lookup_tables,
+      expected_out,
+      min_out,
+      cost_fraction,
+    })
+  }
+}
+
+impl CollateralUnwinder for JupiterUnwinder {
+  fn plan<'a>(&'a self, request: &'a UnwindRequest) -> UnwindFuture<'a> {
+    Box::pin(self.plan_swap(request))
+  }
+}
+
+fn quote_amount(quote: &serde_json::Value, field: &str) -> anyhow::Result<u64> {
+  quote[field]
+    .as_str()
+    .with_context(|| format!("quote is missing {}", field))?
+    .parse()
+    .with_context(|| format!("invalid {}", field))
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct SwapInstructionsResponse {
+  #[serde(default)]
+  setup_instructions: Vec<JupiterInstruction>,
+  swap_instruction: JupiterInstruction,
+  cleanup_instruction: Option<JupiterInstruction>,
+  #[serde(default)]
+  address_lookup_table_addresses: Vec<String>,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JupiterInstruction {
+  program_id: String,
+  accounts: Vec<JupiterAccountMeta>,
+  data: String,
+}
+
+#[derive(serde::Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct JupiterAccountMeta {
+  pubkey: String,
+  is_signer: bool,
+  is_writable: bool,
+}
+
+impl TryFrom<JupiterInstruction> for Instruction {
+  type Error = anyhow::Error;
+
+  fn try_from(ix: JupiterInstruction) -> anyhow::Result<Self> {
+    let accounts = ix.accounts
+      .into_iter()
+      .map(|meta| {
+        let pubkey = Pubkey::from_str(&meta.pubkey).context("invalid account in swap instruction")?;
+        anyhow::Ok(AccountMeta { pubkey, is_signer: meta.is_signer, is_writable: meta.is_writable })
+      })
+      .collect::<anyhow::Result<Vec<_>>>()?;
+
+    anyhow::Ok(Instruction {
+      program_id: Pubkey::from_str(&ix.program_id).context("invalid swap program id")?,
+      accounts,
+      data: general_purpose::STANDARD.decode(&ix.data).context("invalid swap instruction data")?,
+    })
+  }
+}
@@ -3,66 +3,277 @@ use fixed::types::I80F48;
 use solana_rpc_client::nonblocking::rpc_client::RpcClient;
 use anchor_lang::prelude::{Pubkey};
 
-use crate::{marginfi::types::{Balance, BalanceSide, Bank, EmodeConfig, MarginfiAccount, OraclePriceFeedAdapter, OraclePriceFeedAdapterConfig, OraclePriceType, PriceAdapter, reconcile_emode_configs}, utils::parse_account};
+use crate::{math_error, config::ValuationPreset, marginfi::{BotError, MarginfiError, types::{Balance, BalanceSide, Bank, BankAccountCache, BankOperation, BankOperationalState, EmodeConfig, MarginfiAccount, OracleAccountCache, OraclePriceFeedAdapter, OraclePriceFeedAdapterConfig, OraclePriceType, OracleSetup, PriceAdapter, PriceBias, PythPushOraclePriceFeed, reconcile_emode_configs}}, utils::{fetch_account, fetch_multiple_accounts, format_usd, parse_account}};
+
+/// Price type and bias one side of an account is valued with
+#[derive(Clone, Copy, Debug)]
+pub struct SideValuation {
+  pub price_type: OraclePriceType,
+  pub bias: Option<PriceBias>,
+  /// Whether a price wider than the bank's max confidence fails the valuation. When not, the
+  /// price is taken regardless of its confidence and the breach is logged.
+  pub strict_confidence: bool,
+}
+
+/// How assets and liabilities are priced when computing health
+#[derive(Clone, Copy, Debug)]
+pub struct ValuationPolicy {
+  pub assets: SideValuation,
+  pub liabilities: SideValuation,
+}
+
+impl ValuationPolicy {
+  /// Low-biased EMA for assets and high-biased spot for liabilities. A sudden dip in an asset's
+  /// spot price doesn't make an account liquidatable before its EMA follows.
+  pub const CONSERVATIVE: Self = Self {
+    assets: SideValuation { price_type: OraclePriceType::TimeWeighted, bias: Some(PriceBias::Low), strict_confidence: true },
+    liabilities: SideValuation { price_type: OraclePriceType::RealTime, bias: Some(PriceBias::High), strict_confidence: true },
+  };
+  /// Unbiased spot on both sides, acting as soon as the spot price crosses
+  pub const AGGRESSIVE: Self = Self {
+    assets: SideValuation { price_type: OraclePriceType::RealTime, bias: None, strict_confidence: true },
+    liabilities: SideValuation { price_type: OraclePriceType::RealTime, bias: None, strict_confidence: true },
+  };
+  /// Biased spot, as the program values maintenance health
+  pub const PROTOCOL: Self = Self {
+    assets: SideValuation { price_type: OraclePriceType::RealTime, bias: Some(PriceBias::Low), strict_confidence: true },
+    liabilities: SideValuation { price_type: OraclePriceType::RealTime, bias: Some(PriceBias::High), strict_confidence: true },
+  };
+
+  /// The same policy, taking prices wider than the max confidence instead of failing on them.
+  /// The resulting health ranks accounts but isn't what the program would compute.
+  pub fn lenient(self) -> Self {
+    Self {
+      assets: SideValuation { strict_confidence: false, ..self.assets },
+      liabilities: SideValuation { strict_confidence: false, ..self.liabilities },
+    }
+  }
+}
+
+impl From<ValuationPreset> for ValuationPolicy {
+  fn from(preset: ValuationPreset) -> Self {
+    match preset {
+      ValuationPreset::Conservative => Self::CONSERVATIVE,
+      ValuationPreset::Aggressive => Self::AGGRESSIVE,
+      ValuationPreset::Protocol => Self::PROTOCOL,
+    }
+  }
+}
+
+/// Converts the price of a bank whose oracle quotes in another token into usd
+#[derive(Clone)]
+pub struct UsdReference {
+  /// Bank whose oracle prices the quote token in usd
+  pub bank: Pubkey,
+  pub price_feed: OraclePriceFeedAdapter,
+  pub oracle_max_confidence: u32,
+}
+
+/// A bank's weighted contribution to an account's health, in usd
+#[derive(Clone, Copy, Debug)]
+pub struct WeightedBankValue {
+  pub bank: Pubkey,
+  pub asset_value: I80F48,
+  pub liability_value: I80F48,
+}
+
+/// What decides whether an account can be liquidated
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HealthConstraint {
+  /// The account's flags rule out a liquidation regardless of health
+  Blocked(&'static str),
+  /// Nothing is borrowed, so there is nothing to repay
+  NoLiabilities,
+  /// Weighted assets cover weighted liabilities
+  MaintenanceBuffer,
+  /// Weighted liabilities exceed weighted assets
+  Liquidatable,
+}
+
+/// Breakdown of an account's maintenance health, see `MarginfiUserAccount::explain_health`
+#[derive(Clone, Debug)]
+pub struct HealthExplanation {
+  pub weighted_asset_value: I80F48,
+  pub weighted_liability_value: I80F48,
+  /// Weighted assets minus weighted liabilities
+  pub buffer: I80F48,
+  pub constraint: HealthConstraint,
+  /// Banks with a weighted asset value, largest first
+  pub asset_banks: Vec<(Pubkey, I80F48)>,
+  /// Banks with a weighted liability value, largest first
+  pub liability_banks: Vec<(Pubkey, I80F48)>,
+}
+
+impl std::fmt::Display for HealthExplanation {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    writeln!(f, "Weighted assets: {}", format_usd(self.weighted_asset_value))?;
+    for (bank, value) in &self.asset_banks {
+      writeln!(f, "  {}: {}", bank, format_usd(*value))?;
+    }
+    writeln!(f, "Weighted liabilities: {}", format_usd(self.weighted_liability_value))?;
+    for (bank, value) in &self.liability_banks {
+      writeln!(f, "  {}: {}", bank, format_usd(*value))?;
+    }
+    writeln!(f, "Buffer: {}", format_usd(self.buffer))?;
+    match self.constraint {
+      HealthConstraint::Blocked(reason) => write!(f, "Not liquidatable: {}", reason),
+      HealthConstraint::NoLiabilities => write!(f, "Not liquidatable: nothing is borrowed"),
+      HealthConstraint::MaintenanceBuffer => write!(f, "Not liquidatable: weighted assets cover weighted liabilities"),
+      HealthConstraint::Liquidatable => write!(f, "Liquidatable: weighted liabilities exceed weighted assets"),
+    }
+  }
+}
+
+/// Which weights health is computed with
+#[derive(Clone, Copy)]
+enum HealthRequirement {
+  Initial,
+  Maintenance,
+}
+
+/// A bank's oracle failed to load, e.g. because its price is stale
+#[derive(Debug)]
+pub struct OracleLoadError {
+  pub bank: Pubkey,
+  pub source: anyhow::Error,
+}
+
+impl std::fmt::Display for OracleLoadError {
+  fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+    write!(f, "failed to load oracle of bank {}: {}", self.bank, self.source)
+  }
+}
+
+impl std::error::Error for OracleLoadError {}
+
+/// A bank of an account's active balance whose oracle failed to load
+#[derive(Clone, Debug)]
+pub struct UnpricedBank {
+  pub bank: Pubkey,
+  pub reason: String,
+}
 
 #[derive(Clone)]
 pub struct MarginfiUserAccount {
   account: MarginfiAccount,
   bank_accounts: Vec<BankAccount>,
-  emode_config: EmodeConfig
+  emode_config: EmodeConfig,
+  unpriced_banks: Vec<UnpricedBank>,
 }
 
 impl MarginfiUserAccount {
   pub async fn from_pubkey(rpc_client: &RpcClient, account_pubkey: &Pubkey) -> anyhow::Result<Self> {
-    let account_data = rpc_client.get_account(account_pubkey).await?.data;
-    let account = parse_account::<MarginfiAccount>(&account_data)
-      .map_err(|e| anyhow::anyhow!("invalid account data: {}", e))?;
-    
+    let account = Self::fetch_account(rpc_client, account_pubkey).await?;
+    Self::load_banks(rpc_client, account, None, None).await
+  }
+
+  /// Only the account itself, without its banks or oracles, e.g. to rule it out cheaply before
+  /// loading them with `from_account_cached`
+  pub async fn fetch_account(rpc_client: &RpcClient, account_pubkey: &Pubkey) -> anyhow::Result<MarginfiAccount> {
+    let account_data = fetch_account(rpc_client, account_pubkey).await?.data;
+    parse_account::<MarginfiAccount>(&account_data)
+      .map_err(|e| BotError::Parse(anyhow::anyhow!("invalid account data: {}", e)).into())
+  }
+
+  /// Loads the banks and oracles of an already fetched account, reusing the ones recently
+  /// fetched for other accounts from whichever cache is given
+  pub async fn from_account_cached(
+    rpc_client: &RpcClient,
+    account: MarginfiAccount,
+    bank_cache: Option<&BankAccountCache>,
+    oracle_cache: Option<&OracleAccountCache>,
+  ) -> anyhow::Result<Self> {
+    Self::load_banks(rpc_client, account, bank_cache, oracle_cache).await
+  }
+
+  async fn load_banks(
+    rpc_client: &RpcClient,
+    account: MarginfiAccount,
+    bank_cache: Option<&BankAccountCache>,
+    oracle_cache: Option<&OracleAccountCache>,
+  ) -> anyhow::Result<Self> {
     let bank_pubkeys: Vec<Pubkey> = account
       .lending_account
       .get_active_balances_iter()
       .map(|balance| balance.bank_pk)
       .collect();
 
-    let bank_accounts = rpc_client.get_multiple_accounts(&bank_pubkeys).await?
-      .into_iter()
-      .collect::<Option<Vec<_>>>()
-      .ok_or(anyhow::anyhow!("get_multiple_accounts failed to load all bank accounts"))?;
+    let banks = match bank_cache {
+      Some(cache) => cache.get_or_fetch_multiple(rpc_client, &bank_pubkeys).await?,
+      None => {
+        let bank_accounts = fetch_multiple_accounts(rpc_client, &bank_pubkeys).await?
+          .into_iter()
+          .collect::<Option<Vec<_>>>()
+          .ok_or(anyhow::anyhow!("get_multiple_accounts failed to load all bank accounts"))?;
 
-    let banks = bank_accounts
-      .iter()
-      .map(|account| parse_account::<Bank>(&account.data))
-      .collect::<Result<Vec<_>, _>>()
-      .map_err(|e| anyhow::anyhow!("invalid bank data: {}", e))?;
+        bank_accounts
+          .iter()
+          .map(|account| parse_account::<Bank>(&account.data))
+          .collect::<Result<Vec<_>, _>>()
+          .map_err(|e| BotError::Parse(anyhow::anyhow!("invalid bank data: {}", e)))?
+      }
+    };
 
-    let configs = OraclePriceFeedAdapterConfig::load_multiple(rpc_client, &banks).await?;
-    let price_feeds = configs
-      .into_iter()
-      .map(|cfg| OraclePriceFeedAdapter::try_from_config(cfg))
-      .collect::<Result<Vec<_>, _>>()?;
+    let configs = match oracle_cache {
+      Some(cache) => OraclePriceFeedAdapterConfig::load_multiple_cached(rpc_client, &banks, cache).await?,
+      None => OraclePriceFeedAdapterConfig::load_multiple(rpc_client, &banks).await?,
+    };
+    let mut bank_accounts = Vec::with_capacity(banks.len());
+    let mut unpriced_banks = Vec::new();
+    let balances = account.lending_account.get_active_balances_iter();
+    for ((bank, balance), (cfg, bank_pk)) in banks.into_iter().zip(balances).zip(configs.into_iter().zip(&bank_pubkeys)) {
+      match OraclePriceFeedAdapter::try_from_config(cfg) {
+        Ok(price_feed) => bank_accounts.push(BankAccount {
+          oracle_max_confidence: bank.config.oracle_max_confidence,
+          liquidation_target: true,
+          usd_reference: None,
+          bank,
+          price_feed,
+          balance: *balance,
+        }),
+        Err(err) => unpriced_banks.push(UnpricedBank { bank: *bank_pk, reason: err.to_string() }),
+      }
+    }
 
-    let banks: Vec<BankAccount> = banks
-      .into_iter()
-      .zip(account
-        .lending_account
-        .get_active_balances_iter())
-      .zip(price_feeds)
-      .map(|((bank, balance), price_feed)| BankAccount { bank, price_feed, balance: *balance })
-      .collect();
+    anyhow::Ok(Self::from_parts(account, bank_accounts, unpriced_banks))
+  } 
 
+  /// An account valued with already loaded banks, the emode config reconciled from the banks it
+  /// borrows from
+  pub fn from_parts(account: MarginfiAccount, bank_accounts: Vec<BankAccount>, unpriced_banks: Vec<UnpricedBank>) -> Self {
     let reconciled_emode_config = reconcile_emode_configs(
-      banks
+      bank_accounts
         .iter()
         .filter(|b| !b.balance.is_empty(BalanceSide::Liabilities))
         .map(|b| b.bank.emode.emode_config),
     );
 
-    anyhow::Ok(Self {
+    Self {
       account,
-      bank_accounts: banks,
-      emode_config: reconciled_emode_config
-    })
-  } 
+      bank_accounts,
+      emode_config: reconciled_emode_config,
+      unpriced_banks,
+    }
+  }
+
+  /// Whether every active balance could be priced, otherwise values and health leave out the
+  /// `unpriced_banks` and can't be trusted
+  pub fn has_complete_pricing(&self) -> bool {
+    self.unpriced_banks.is_empty()
+  }
+
+  /// Banks of active balances whose oracle failed to load, e.g. because its price is stale
+  pub fn unpriced_banks(&self) -> &[UnpricedBank] {
+    &self.unpriced_banks
+  }
+
+  /// The account if fully priced, otherwise an `OracleLoadError` for the first unpriced bank
+  pub fn require_complete_pricing(self) -> anyhow::Result<Self> {
+    match self.unpriced_banks.first() {
+      Some(unpriced) => Err(BotError::Oracle(OracleLoadError { bank: unpriced.bank, source: anyhow::anyhow!("{}", unpriced.reason) }).into()),
+      None => anyhow::Ok(self),
+    }
+  }
 
   pub fn account(&self) -> &MarginfiAccount {
     &self.account
@@ -72,6 +283,141 @@ impl MarginfiUserAccount {
     &self.bank_accounts
   }
 
+  /// Scales every bank's `oracle_max_confidence` by `multiplier` (see
+  /// `BankConfig::get_oracle_max_confidence_scaled`), e.g. < 1 to avoid acting on wide spreads
+  /// during a fast market.
+  pub fn with_oracle_confidence_multiplier(mut self, multiplier: I80F48) -> Self {
+    for bank_account in &mut self.bank_accounts {
+      bank_account.oracle_max_confidence = bank_account.bank.config.get_oracle_max_confidence_scaled(multiplier);
+    }
+    self
+  }
+
+  /// Attaches a `UsdReference` to every bank quoted in another token, `references` maps such a
+  /// bank to the bank pricing its quote token in usd. Only references of banks the account holds
+  /// are loaded.
+  pub async fn with_usd_references(mut self, rpc_client: &RpcClient, references: &[(Pubkey, Pubkey)]) -> anyhow::Result<Self> {
+    let mut reference_pubkeys: Vec<Pubkey> = references
+      .iter()
+      .filter(|(quoted, _)| self.bank_accounts.iter().any(|bank_account| bank_account.balance.bank_pk == *quoted))
+      .map(|(_, reference)| *reference)
+      .collect();
+    reference_pubkeys.sort();
+    reference_pubkeys.dedup();
+    if reference_pubkeys.is_empty() {
+      return anyhow::Ok(self);
+    }
+
+    let banks = fetch_multiple_accounts(rpc_client, &reference_pubkeys).await?
+      .into_iter()
+      .zip(&reference_pubkeys)
+      .map(|(account, pubkey)| {
+        let account = account.with_context(|| format!("usd reference bank {} not found", pubkey))?;
+        parse_account::<Bank>(&account.data)
+          .map_err(|e| BotError::Parse(anyhow::anyhow!("invalid usd reference bank data: {}", e)).into())
+      })
+      .collect::<anyhow::Result<Vec<_>>>()?;
+    let configs = OraclePriceFeedAdapterConfig::load_multiple(rpc_client, &banks).await?;
+    let mut usd_references = Vec::with_capacity(banks.len());
+    for ((bank, cfg), bank_pk) in banks.iter().zip(configs).zip(&reference_pubkeys) {
+      let price_feed = OraclePriceFeedAdapter::try_from_config(cfg)
+        .map_err(|e| BotError::Oracle(OracleLoadError { bank: *bank_pk, source: e.into() }))?;
+      usd_references.push(UsdReference {
+        bank: *bank_pk,
+        price_feed,
+        oracle_max_confidence: bank.config.oracle_max_confidence,
+      });
+    }
+
+    anyhow::Ok(self.attach_usd_references(references, &usd_references))
+  }
+
+  /// Attaches the loaded `usd_references` to the banks `references` maps to them, so their
+  /// price is multiplied by the reference's usd price, see `BankAccount::usd_price_with`
+  fn attach_usd_references(mut self, references: &[(Pubkey, Pubkey)], usd_references: &[UsdReference]) -> Self {
+    for bank_account in &mut self.bank_accounts {
+      let reference = references
+        .iter()
+        .find(|(quoted, _)| *quoted == bank_account.balance.bank_pk)
+        .and_then(|(_, reference)| usd_references.iter().find(|usd_reference| usd_reference.bank == *reference));
+      bank_account.usd_reference = reference.cloned();
+    }
+    self
+  }
+
+  /// Re-prices the pyth push banks of `shards`' mints off the oracle of the given shard instead of
+  /// the stored one, e.g. when the other sponsor's feed is fresher. The derived oracle must carry
+  /// the stored oracle's feed id.
+  pub async fn with_pyth_shard_overrides(mut self, rpc_client: &RpcClient, shards: &[(Pubkey, u16)]) -> anyhow::Result<Self> {
+    for bank_account in &mut self.bank_accounts {
+      let Some((_, shard)) = shards.iter().find(|(mint, _)| *mint == bank_account.bank.mint) else {
+        continue;
+      };
+      let uses_pyth_push = matches!(
+        bank_account.bank.config.oracle_setup,
+        OracleSetup::PythPushOracle | OracleSetup::StakedWithPythPush | OracleSetup::KaminoPythPush
+      );
+      if !uses_pyth_push {
+        continue;
+      }
+
+      let bank_pk = bank_account.balance.bank_pk;
+      let oracle_error = |e: anchor_lang::error::Error| BotError::Oracle(OracleLoadError { bank: bank_pk, source: e.into() });
+      let stored_key = bank_account.bank.config.oracle_keys[0];
+      let stored = fetch_account(rpc_client, &stored_key).await?;
+      let feed_id = PythPushOraclePriceFeed::peek_feed_id(&stored).map_err(oracle_error)?;
+      let (derived_key, _) = PythPushOraclePriceFeed::find_oracle_address(*shard, &feed_id);
+      if derived_key == stored_key {
+        continue;
+      }
+      let derived = fetch_account(rpc_client, &derived_key).await
+        .with_context(|| format!("pyth oracle {} of shard {} not found", derived_key, shard))?;
+      if PythPushOraclePriceFeed::peek_feed_id(&derived).map_err(oracle_error)? != feed_id {
+        anyhow::bail!("pyth oracle {} of shard {} doesn't carry the feed of bank {}", derived_key, shard, bank_pk);
+      }
+
+      let mut bank = bank_account.bank;
+      bank.config.oracle_keys[0] = derived_key;
+      let cfg = OraclePriceFeedAdapterConfig::load_multiple(rpc_client, std::slice::from_ref(&bank)).await?
+        .remove(0);
+      bank_account.price_feed = OraclePriceFeedAdapter::try_from_config(cfg).map_err(oracle_error)?;
+    }
+    anyhow::Ok(self)
+  }
+
+  /// Marks banks `allows` rejects as not `liquidation_target`. They still count towards health.
+  pub fn with_bank_filter<F>(mut self, allows: F) -> Self
+  where
+    F: Fn(&Pubkey) -> bool,
+  {
+    for bank_account in &mut self.bank_accounts {
+      bank_account.liquidation_target = allows(&bank_account.balance.bank_pk);
+    }
+    self
+  }
+
+  /// The first two banks of the account whose asset tags may not comingle, see
+  /// `BankConfig::comingles_with`. The program never lets an account get there, so such an account
+  /// was loaded from banks retagged since or is corrupt.
+  pub fn asset_tag_conflict(&self) -> Option<(Pubkey, Pubkey)> {
+    self.bank_accounts.iter().enumerate().find_map(|(i, a)| {
+      self.bank_accounts[i + 1..]
+        .iter()
+        .find(|b| !a.bank.config.comingles_with(&b.bank.config))
+        .map(|b| (a.balance.bank_pk, b.balance.bank_pk))
+    })
+  }
+
+  /// Banks whose group differs from the account's group. Such banks must never contribute to
+  /// this account's health.
+  pub fn mismatched_group_banks(&self) -> Vec<Pubkey> {
+    self.bank_accounts
+      .iter()
+      .filter(|bank_account| bank_account.bank.group != self.account.group)
+      .map(|bank_account| bank_account.balance.bank_pk)
+      .collect()
+  }
+
   /// returns lended value in usd
   pub fn asset_value(&self) -> anyhow::Result<I80F48> {
     let total_asset_value: I80F48 = self.bank_accounts.iter()
@@ -96,32 +442,214 @@ impl MarginfiUserAccount {
     anyhow::Ok(total_liability_value)
   }
 
-  pub fn maintenance(&self) -> anyhow::Result<I80F48> {
-    let mut total_asset_value: I80F48 = I80F48::ZERO;
-    let mut total_liability_value: I80F48 = I80F48::ZERO;
+  /// Maintenance health in usd, negative when liquidatable
+  pub fn maintenance(&self, policy: ValuationPolicy) -> anyhow::Result<I80F48> {
+    self.health(policy, HealthRequirement::Maintenance)
+  }
+
+  /// The account with every bank's interest accrued up to `timestamp`, see
+  /// `Bank::accrue_interest_to`. Prices stay as loaded.
+  pub fn accrued_to(&self, timestamp: i64) -> anyhow::Result<Self> {
+    let mut account = self.clone();
+    for bank_account in &mut account.bank_accounts {
+      bank_account.bank = bank_account.bank.accrue_interest_to(timestamp)?;
+    }
+    anyhow::Ok(account)
+  }
+
+  /// Maintenance health in usd once interest has accrued up to `timestamp`, e.g. to see whether
+  /// an account drifts below maintenance before the next check
+  pub fn maintenance_at(&self, policy: ValuationPolicy, timestamp: i64) -> anyhow::Result<I80F48> {
+    self.accrued_to(timestamp)?.maintenance(policy)
+  }
+
+  /// Initial health in usd, negative when the account can't take on more liabilities
+  pub fn initial_health(&self, policy: ValuationPolicy) -> anyhow::Result<I80F48> {
+    self.health(policy, HealthRequirement::Initial)
+  }
+
+  /// Collateral value in usd backing new borrows: assets weighted by `asset_weight_init`, each
+  /// bank discounted once its deposits exceed `total_asset_value_init_limit`, see
+  /// `Bank::asset_weight_init_discount`
+  pub fn init_margin_value(&self, policy: ValuationPolicy) -> anyhow::Result<I80F48> {
+    let (total_asset_value, _) = self.weighted_totals(policy, HealthRequirement::Initial)?;
+    anyhow::Ok(total_asset_value)
+  }
+
+  /// Maintenance health under `policy` broken down per bank, with what rules a liquidation in or
+  /// out
+  pub fn explain_health(&self, policy: ValuationPolicy) -> anyhow::Result<HealthExplanation> {
+    let weighted_values = self.weighted_values(policy, HealthRequirement::Maintenance)?;
+    let (weighted_asset_value, weighted_liability_value) = sum_weighted_values(&weighted_values)?;
+    let buffer = weighted_asset_value.checked_sub(weighted_liability_value)
+      .ok_or_else(math_error!())
+      .map_err(anchor_lang::error::Error::from)?;
+
+    let constraint = if let Some(reason) = self.account.liquidation_blocked_reason() {
+      HealthConstraint::Blocked(reason)
+    } else if !self.account.lending_account.has_liabilities() {
+      HealthConstraint::NoLiabilities
+    } else if buffer >= I80F48::ZERO {
+      HealthConstraint::MaintenanceBuffer
+    } else {
+      HealthConstraint::Liquidatable
+    };
+    let ranked = |value: fn(&WeightedBankValue) -> I80F48| {
+      let mut banks: Vec<(Pubkey, I80F48)> = weighted_values
+        .iter()
+        .map(|weighted| (weighted.bank, value(weighted)))
+        .filter(|(_, amount)| *amount > I80F48::ZERO)
+        .collect();
+      banks.sort_by(|(_, a), (_, b)| b.cmp(a));
+      banks
+    };
+
+    anyhow::Ok(HealthExplanation {
+      weighted_asset_value,
+      weighted_liability_value,
+      buffer,
+      constraint,
+      asset_banks: ranked(|weighted| weighted.asset_value),
+      liability_banks: ranked(|weighted| weighted.liability_value),
+    })
+  }
+
+  /// Maintenance-weighted assets over maintenance-weighted liabilities, `I80F48::MAX` without
+  /// liabilities. Below 1 the account is liquidatable.
+  pub fn health_factor(&self, policy: ValuationPolicy) -> anyhow::Result<I80F48> {
+    let (total_asset_value, total_liability_value) = self.weighted_totals(policy, HealthRequirement::Maintenance)?;
+    if total_liability_value == I80F48::ZERO {
+      return anyhow::Ok(I80F48::MAX);
+    }
+
+    let health_factor = total_asset_value.checked_div(total_liability_value)
+      .ok_or_else(math_error!())
+      .map_err(anchor_lang::error::Error::from)?;
+    anyhow::Ok(health_factor)
+  }
+
+  pub fn is_liquidatable(&self, policy: ValuationPolicy) -> anyhow::Result<bool> {
+    anyhow::Ok(self.health_factor(policy)? < I80F48::ONE)
+  }
+
+  fn health(&self, policy: ValuationPolicy, requirement: HealthRequirement) -> anyhow::Result<I80F48> {
+    let (total_asset_value, total_liability_value) = self.weighted_totals(policy, requirement)?;
+
+    let health = total_asset_value.checked_sub(total_liability_value)
+      .ok_or_else(math_error!())
+      .map_err(anchor_lang::error::Error::from)?;
+    anyhow::Ok(health)
+  }
+
+  /// Total weighted asset and liability values
+  fn weighted_totals(&self, policy: ValuationPolicy, requirement: HealthRequirement) -> anyhow::Result<(I80F48, I80F48)> {
+    sum_weighted_values(&self.weighted_values(policy, requirement)?)
+  }
+
+  /// Maintenance asset and liability weights of each bank of the account, see `weights`
+  pub fn maintenance_weights(&self) -> impl Iterator<Item = (Pubkey, I80F48, I80F48)> + '_ {
+    self.bank_accounts.iter().map(|bank_account| {
+      let (asset_weight, liability_weight) = self.weights(bank_account, HealthRequirement::Maintenance);
+      (bank_account.balance.bank_pk, asset_weight, liability_weight)
+    })
+  }
+
+  /// Asset and liability weights of `bank_account` under `requirement`, before the init-limit
+  /// discount
+  fn weights(&self, bank_account: &BankAccount, requirement: HealthRequirement) -> (I80F48, I80F48) {
+    let config = &bank_account.bank.config;
+    let (bank_asset_weight, liability_weight): (I80F48, I80F48) = match requirement {
+      HealthRequirement::Initial => (config.asset_weight_init.into(), config.liability_weight_init.into()),
+      HealthRequirement::Maintenance => (config.asset_weight_maint.into(), config.liability_weight_maint.into()),
+    };
+
+    // If an emode entry exists for this bank's emode tag in the reconciled config of
+    // all borrowing banks, use its weight, otherwise use the weight designated on the
+    // collateral bank itself. If the bank's weight is higher, always use that weight.
+    let asset_weight: I80F48 = if let Some(emode_entry) = self.emode_config.find_with_tag(bank_account.bank.emode.emode_tag) {
+      let emode_weight = I80F48::from(match requirement {
+        HealthRequirement::Initial => emode_entry.asset_weight_init,
+        HealthRequirement::Maintenance => emode_entry.asset_weight_maint,
+      });
+      std::cmp::max(bank_asset_weight, emode_weight)
+    } else {
+      bank_asset_weight
+    };
+
+    (asset_weight, liability_weight)
+  }
+
+  fn weighted_values(&self, policy: ValuationPolicy, requirement: HealthRequirement) -> anyhow::Result<Vec<WeightedBankValue>> {
+    let mut weighted_values = Vec::with_capacity(self.bank_accounts.len());
     for bank_account in &self.bank_accounts {
-      let asset_value = bank_account.asset_value()?;
-      let liability_value = bank_account.liability_value()?;
-
-      // If an emode entry exists for this bank's emode tag in the reconciled config of
-      // all borrowing banks, use its weight, otherwise use the weight designated on the
-      // collateral bank itself. If the bank's weight is higher, always use that weight.
-      let bank_asset_weight: I80F48 = bank_account.bank.config.asset_weight_maint.into();
-      let asset_weight: I80F48 = if let Some(emode_entry) = self.emode_config.find_with_tag(bank_account.bank.emode.emode_tag) {
-        let emode_weight = I80F48::from(emode_entry.asset_weight_maint);
-        std::cmp::max(bank_asset_weight, emode_weight)
-      } else {
-        bank_asset_weight
-      };
-      let liability_weight: I80F48 = bank_account.bank.config.liability_weight_maint.into();
+      let asset_value = bank_account.asset_value_with(policy.assets)?;
+      let liability_value = bank_account.liability_value_with(policy.liabilities)?;
+      let (mut asset_weight, liability_weight) = self.weights(bank_account, requirement);
+      // Like the program, init margin discounts banks whose deposits exceed their usd limit
+      if matches!(requirement, HealthRequirement::Initial) && asset_value > I80F48::ZERO {
+        let price = bank_account.usd_price_with(policy.assets)?;
+        if let Some(discount) = bank_account.bank.asset_weight_init_discount(price)? {
+          asset_weight = asset_weight.checked_mul(discount)
+            .context("discounted asset weight calculation failed")?;
+        }
+      }
 
-      total_asset_value += asset_value.checked_mul(asset_weight)
-        .context("asset maintenance value calculation failed")?;
-      total_liability_value += liability_value.checked_mul(liability_weight)
-        .context("liability maintenance value calculation failed")?;
+      let weighted_asset_value = asset_value.checked_mul(asset_weight)
+        .context("weighted asset value calculation failed")?;
+      let weighted_liability_value = liability_value.checked_mul(liability_weight)
+        .context("weighted liability value calculation failed")?;
+      weighted_values.push(WeightedBankValue {
+        bank: bank_account.balance.bank_pk,
+        asset_value: weighted_asset_value,
+        liability_value: weighted_liability_value,
+      });
     }
 
-    anyhow::Ok(total_asset_value - total_liability_value)
+    anyhow::Ok(weighted_values)
+  }
+}
+
+/// Total weighted asset and liability values
+fn sum_weighted_values(weighted_values: &[WeightedBankValue]) -> anyhow::Result<(I80F48, I80F48)> {
+  let mut total_asset_value: I80F48 = I80F48::ZERO;
+  let mut total_liability_value: I80F48 = I80F48::ZERO;
+  for weighted in weighted_values {
+    total_asset_value = total_asset_value.checked_add(weighted.asset_value)
+      .ok_or_else(math_error!())
+      .map_err(anchor_lang::error::Error::from)?;
+    total_liability_value = total_liability_value.checked_add(weighted.liability_value)
+      .ok_or_else(math_error!())
+      .map_err(anchor_lang::error::Error::from)?;
+  }
+
+  anyhow::Ok((total_asset_value, total_liability_value))
+}
+
+/// Price of `price_feed` per `valuation`. A price wider than `oracle_max_confidence` fails with
+/// its `ConfidenceBreach` attached as context, or without `strict_confidence` is logged and taken
+/// anyway.
+fn price_with(
+  price_feed: &OraclePriceFeedAdapter,
+  valuation: SideValuation,
+  oracle_max_confidence: u32,
+  bank: &Pubkey,
+) -> anyhow::Result<I80F48> {
+  match price_feed.get_price_of_type(valuation.price_type, valuation.bias, oracle_max_confidence) {
+    Err(anchor_lang::error::Error::AnchorError(err))
+      if MarginfiError::from(err.error_code_number) == MarginfiError::OracleMaxConfidenceExceeded =>
+    {
+      let breach = price_feed.confidence_breach(valuation.price_type, oracle_max_confidence)?;
+      if valuation.strict_confidence {
+        let err = anyhow::Error::from(anchor_lang::error::Error::AnchorError(err));
+        return Err(match breach {
+          Some(breach) => err.context(breach),
+          None => err,
+        });
+      }
+      tracing::warn!(bank = %bank, breach = ?breach, "oracle exceeds its max confidence, pricing it regardless");
+      anyhow::Ok(price_feed.get_price_of_type_ignore_conf(valuation.price_type, valuation.bias)?)
+    }
+    result => anyhow::Ok(result?),
   }
 }
 
@@ -129,19 +657,54 @@ impl MarginfiUserAccount {
 pub struct BankAccount {
   pub bank: Bank,
   pub price_feed: OraclePriceFeedAdapter,
-  pub balance: Balance
+  pub balance: Balance,
+  /// Max confidence used for valuation, defaults to `bank.config.oracle_max_confidence`
+  pub oracle_max_confidence: u32,
+  /// Whether a liquidation may seize from or repay to this bank, see `with_bank_filter`
+  pub liquidation_target: bool,
+  /// Set when the oracle quotes in another token than usd, see `with_usd_references`
+  pub usd_reference: Option<UsdReference>
 }
 
 impl BankAccount {
+  pub fn operational_state(&self) -> BankOperationalState {
+    self.bank.config.operational_state
+  }
+
+  /// Oracle price in usd, converted through the `usd_reference` if the oracle quotes in another
+  /// token. The reference price is biased the same way.
+  pub fn usd_price(&self, price_type: OraclePriceType, bias: Option<PriceBias>) -> anyhow::Result<I80F48> {
+    self.usd_price_with(SideValuation { price_type, bias, strict_confidence: true })
+  }
+
+  /// `usd_price` priced per `valuation`
+  pub fn usd_price_with(&self, valuation: SideValuation) -> anyhow::Result<I80F48> {
+    let price = price_with(&self.price_feed, valuation, self.oracle_max_confidence, &self.balance.bank_pk)?;
+    let Some(reference) = &self.usd_reference else {
+      return anyhow::Ok(price);
+    };
+    let reference_price = price_with(&reference.price_feed, valuation, reference.oracle_max_confidence, &reference.bank)
+      .map_err(|e| BotError::Oracle(OracleLoadError { bank: reference.bank, source: e.into() }))?;
+
+    price.checked_mul(reference_price)
+      .context("usd price calculation failed")
+  }
+
+  /// Whether the bank currently permits `op`, see `BankOperationalState::permits`
+  pub fn is_operational(&self, op: BankOperation) -> bool {
+    self.operational_state().permits(op)
+  }
+
   pub fn asset_value(&self) -> anyhow::Result<I80F48> {
+    self.asset_value_with(ValuationPolicy::PROTOCOL.assets)
+  }
+
+  /// Deposited value in usd, priced per `valuation`
+  pub fn asset_value_with(&self, valuation: SideValuation) -> anyhow::Result<I80F48> {
     if self.balance.is_empty(BalanceSide::Assets) {
       return anyhow::Ok(I80F48::ZERO);
     }
-    let price = self.price_feed.get_price_of_type(
-      OraclePriceType::RealTime,
-      Some(super::types::PriceBias::Low),
-      self.bank.config.oracle_max_confidence
-    )?;
+    let price = self.usd_price_with(valuation)?;
 
     let asset = self.bank.get_asset_amount(self.balance.asset_shares.into())
       .context("asset shares calculation failed")?;
@@ -155,17 +718,19 @@ impl BankAccount {
     anyhow::Ok(asset_value)
   }
 
+  /// Borrowed value in usd at the high-biased spot price, so health errs on the conservative side
   pub fn liability_value(&self) -> anyhow::Result<I80F48> {
+    self.liability_value_with(ValuationPolicy::PROTOCOL.liabilities)
+  }
+
+  /// Borrowed value in usd, priced per `valuation`
+  pub fn liability_value_with(&self, valuation: SideValuation) -> anyhow::Result<I80F48> {
     if self.balance.is_empty(BalanceSide::Liabilities) {
       return anyhow::Ok(I80F48::ZERO);
     }
-    let price = self.price_feed.get_price_of_type(
-      OraclePriceType::RealTime,
-      Some(super::types::PriceBias::Low),
-      self.bank.config.oracle_max_confidence
-    )?;
+    let price = self.usd_price_with(valuation)?;
 
-    let liability = self.bank.get_asset_amount(self.balance.liability_shares.into())
+    let liability = self.bank.get_liability_amount(self.balance.liability_shares.into())
       .context("liability shares calculation failed")?;
 
     let liability_value_with_decimals = liability.checked_mul(price)
@@ -176,4 +741,513 @@ impl BankAccount {
 
     anyhow::Ok(liability_value)
   }
-}
\ No newline at end of file
+
+  /// returns deposited amount in native token units (respecting `mint_decimals`), rounded down
+  pub fn asset_amount_native(&self) -> anyhow::Result<u64> {
+    if self.balance.is_empty(BalanceSide::Assets) {
+      return anyhow::Ok(0);
+    }
+    let asset = self.bank.get_asset_amount(self.balance.asset_shares.into())
+      .context("asset shares calculation failed")?;
+
+    asset.floor()
+      .checked_to_num::<u64>()
+      .context("asset amount does not fit in u64")
+  }
+
+  /// returns borrowed amount in native token units (respecting `mint_decimals`), rounded up so a
+  /// repay of this amount fully covers the liability
+  pub fn liability_amount_native(&self) -> anyhow::Result<u64> {
+    if self.balance.is_empty(BalanceSide::Liabilities) {
+      return anyhow::Ok(0);
+    }
+    let liability = self.bank.get_liability_amount(self.balance.liability_shares.into())
+      .context("liability shares calculation failed")?;
+
+    liability.checked_ceil()
+      .and_then(|amount| amount.checked_to_num::<u64>())
+      .context("liability amount does not fit in u64")
+  }
+}
+#[cfg(test)]
+mod tests {
+  use anchor_lang::prelude::Clock;
+  use bytemuck::Zeroable;
+  use pretty_assertions::assert_eq;
+  use solana_rpc_client::mock_sender::MocksMap;
+  use solana_rpc_client_types::request::RpcRequest;
+
+  use super::*;
+  use crate::consts::MARGINFI_PROGRAM_ID;
+  use crate::marginfi::consts::{ASSET_TAG_DEFAULT, ASSET_TAG_SOL, ASSET_TAG_STAKED};
+  use crate::marginfi::AccountSnapshot;
+  use crate::marginfi::fixtures::{bank, bank_account, clock_response, response, rpc_account, ui_account, user_account, zero_copy};
+  use crate::marginfi::types::{ConfidenceBreach, EmodeEntry, FixedPriceFeed, LitePullFeedAccountData, SwitchboardAge, SwitchboardPullPriceFeed};
+
+  const BANK: Pubkey = Pubkey::new_from_array([1; 32]);
+
+  /// $1000 deposited in a bank holding `bank_deposits` tokens at $10, whose deposits are limited
+  /// to $5000 for init margin
+  fn depositor(bank_deposits: f64) -> MarginfiUserAccount {
+    let mut bank = bank();
+    bank.config.asset_weight_init = I80F48::from_num(0.75).into();
+    bank.config.total_asset_value_init_limit = 5_000;
+    bank.total_asset_shares = I80F48::from_num(bank_deposits * 1e6).into();
+    user_account(vec![bank_account(BANK, bank, 10.0, 100.0, 0.0)])
+  }
+
+  #[test]
+  fn init_margin_value_below_the_limit_is_not_discounted() {
+    let value = depositor(200.0).init_margin_value(ValuationPolicy::PROTOCOL).unwrap();
+
+    assert_eq!(value, I80F48::from_num(750));
+  }
+
+  #[test]
+  fn init_margin_value_at_the_limit_is_not_discounted() {
+    let value = depositor(500.0).init_margin_value(ValuationPolicy::PROTOCOL).unwrap();
+
+    assert_eq!(value, I80F48::from_num(750));
+  }
+
+  #[test]
+  fn init_margin_value_above_the_limit_is_discounted_by_limit_over_deposits() {
+    // $10000 of deposits against the $5000 limit halves the weight
+    let value = depositor(1_000.0).init_margin_value(ValuationPolicy::PROTOCOL).unwrap();
+
+    assert_eq!(value, I80F48::from_num(375));
+  }
+
+  /// $1000 of collateral on a pyth feed whose spot dipped to $10 below its $12 EMA, against $750
+  /// borrowed at $1
+  fn dipped_collateral() -> MarginfiUserAccount {
+    let mut collateral = bank_account(BANK, bank(), 10.0, 100.0, 0.0);
+    collateral.price_feed = OraclePriceFeedAdapter::PythPushOracle(
+      PythPushOraclePriceFeed::from_components(1_000, 1, 1_200, 1, -2),
+    );
+    let debt = bank_account(Pubkey::new_from_array([2; 32]), bank(), 1.0, 0.0, 750.0);
+    user_account(vec![collateral, debt])
+  }
+
+  #[test]
+  fn dip_is_liquidatable_under_aggressive_but_not_conservative() {
+    let account = dipped_collateral();
+
+    // $800 of weighted spot collateral against $900 of weighted debt
+    assert!(account.maintenance(ValuationPolicy::AGGRESSIVE).unwrap() < I80F48::ZERO);
+    // The EMA still values the collateral near $960
+    assert!(account.maintenance(ValuationPolicy::CONSERVATIVE).unwrap() > I80F48::ZERO);
+    assert!(account.is_liquidatable(ValuationPolicy::AGGRESSIVE).unwrap());
+    assert!(!account.is_liquidatable(ValuationPolicy::CONSERVATIVE).unwrap());
+  }
+
+  #[test]
+  fn initial_health_uses_init_weights_under_the_policy() {
+    let account = dipped_collateral();
+
+    // $700 of spot collateral at 0.7 against $975 of debt at 1.3
+    let expected = I80F48::from_num(1_000) * I80F48::from_num(0.7) - I80F48::from_num(750) * I80F48::from_num(1.3);
+    assert_eq!(account.initial_health(ValuationPolicy::AGGRESSIVE).unwrap(), expected);
+    assert!(account.initial_health(ValuationPolicy::CONSERVATIVE).unwrap() > expected);
+  }
+
+  #[test]
+  fn unpriced_bank_is_left_out_of_the_valuation() {
+    let stale = Pubkey::new_from_array([3; 32]);
+    let priced = user_account(vec![bank_account(BANK, bank(), 10.0, 100.0, 0.0)]);
+    let account = MarginfiUserAccount::from_parts(
+      *priced.account(),
+      priced.bank_accounts().to_vec(),
+      vec![UnpricedBank { bank: stale, reason: "stale price".to_string() }],
+    );
+
+    assert!(priced.has_complete_pricing());
+    assert!(!account.has_complete_pricing());
+    assert_eq!(account.asset_value().unwrap(), I80F48::from_num(1_000));
+    let err = account.require_complete_pricing().err().unwrap();
+    match err.downcast_ref::<BotError>() {
+      Some(BotError::Oracle(err)) => assert_eq!(err.bank, stale),
+      _ => panic!("expected an oracle error, got {:#}", err),
+    }
+  }
+
+  #[test]
+  fn init_limit_leaves_maintenance_alone() {
+    let maintenance = depositor(1_000.0).maintenance(ValuationPolicy::PROTOCOL).unwrap();
+
+    assert_eq!(maintenance, I80F48::from_num(1_000) * I80F48::from_num(0.8));
+  }
+
+  #[test]
+  fn maintenance_at_projects_debt_growing_faster_than_collateral() {
+    let mut lending_bank = bank();
+    lending_bank.last_update = 1_700_000_000;
+    lending_bank.total_asset_shares = I80F48::from_num(1_000e6).into();
+    lending_bank.total_liability_shares = I80F48::from_num(500e6).into();
+    let ir_config = &mut lending_bank.config.interest_rate_config;
+    ir_config.optimal_utilization_rate = I80F48::from_num(0.5).into();
+    ir_config.plateau_interest_rate = I80F48::from_num(0.1).into();
+    ir_config.max_interest_rate = I80F48::from_num(1).into();
+    let account = user_account(vec![bank_account(BANK, lending_bank, 1.0, 1_000.0, 500.0)]);
+    let policy = ValuationPolicy::PROTOCOL;
+
+    let now = account.maintenance(policy).unwrap();
+    assert_eq!(account.maintenance_at(policy, 1_700_000_000).unwrap(), now);
+    // A year on the $1000 deposit earns 5% and the $500 borrow costs 10%
+    let expected = I80F48::from_num(1_050) * I80F48::from_num(0.8) - I80F48::from_num(550) * I80F48::from_num(1.2);
+    let projected = account.maintenance_at(policy, 1_700_000_000 + 31_536_000).unwrap();
+    assert!((projected - expected).abs() < I80F48::from_num(1e-6), "{} != {}", projected, expected);
+  }
+
+  #[test]
+  fn liabilities_are_valued_at_the_liability_share_value() {
+    let mut bank = bank();
+    bank.liability_share_value = I80F48::from_num(1.5).into();
+    // 100 asset shares and 100 liability shares
+    let bank_account = bank_account(BANK, bank, 1.0, 100.0, 150.0);
+
+    assert_eq!(bank_account.asset_value().unwrap(), I80F48::from_num(100));
+    assert_eq!(bank_account.liability_value().unwrap(), I80F48::from_num(150));
+  }
+
+  #[test]
+  fn deposit_only_balance_has_an_asset_value() {
+    let bank_account = bank_account(BANK, bank(), 10.0, 100.0, 0.0);
+
+    assert_eq!(bank_account.asset_value().unwrap(), I80F48::from_num(1_000));
+    assert_eq!(bank_account.liability_value().unwrap(), I80F48::ZERO);
+  }
+
+  #[test]
+  fn borrow_only_balance_has_no_asset_value() {
+    let bank_account = bank_account(BANK, bank(), 10.0, 0.0, 100.0);
+
+    assert_eq!(bank_account.asset_value().unwrap(), I80F48::ZERO);
+    assert_eq!(bank_account.liability_value().unwrap(), I80F48::from_num(1_000));
+  }
+
+  #[test]
+  fn switchboard_std_dev_biases_assets_down_and_liabilities_up() {
+    let mut feed = switchboard_on_demand::PullFeedAccountData::zeroed();
+    feed.result.value = 10 * 10i128.pow(switchboard_on_demand::PRECISION);
+    feed.result.std_dev = 10i128.pow(switchboard_on_demand::PRECISION) / 10;
+    let price_feed = OraclePriceFeedAdapter::SwitchboardPull(SwitchboardPullPriceFeed {
+      feed: Box::new(LitePullFeedAccountData::from(&feed)),
+      age: SwitchboardAge { write_secs: 0, result_slots: 0 },
+    });
+    let mut bank_account = bank_account(BANK, bank(), 10.0, 100.0, 100.0);
+    bank_account.price_feed = price_feed;
+
+    // $0.1 of std dev widens the $10 price by 1.96 * $0.1 on either side
+    let spread = I80F48::from_num(100) * I80F48::from_num(0.1) * I80F48::from_num(1.96);
+    let asset_value = bank_account.asset_value().unwrap();
+    let liability_value = bank_account.liability_value().unwrap();
+    assert!(asset_value < I80F48::from_num(1_000));
+    assert!(liability_value > I80F48::from_num(1_000));
+    assert!((liability_value - asset_value - spread - spread).abs() < I80F48::from_num(1e-9));
+  }
+
+  /// Collateral tagged `collateral_tag` against a borrow from a bank whose emode entry gives tag 5
+  /// a maintenance weight of `entry_weight`. Returns the collateral's maintenance asset weight.
+  fn emode_collateral_weight(collateral_tag: u16, entry_weight: f64) -> I80F48 {
+    let mut collateral = bank();
+    collateral.emode.emode_tag = collateral_tag;
+    let mut debt = bank();
+    let mut entry = EmodeEntry::zeroed();
+    entry.collateral_bank_emode_tag = 5;
+    entry.asset_weight_init = I80F48::from_num(entry_weight).into();
+    entry.asset_weight_maint = I80F48::from_num(entry_weight).into();
+    debt.emode.emode_config = EmodeConfig::from_entries(&[entry]);
+    let account = user_account(vec![
+      bank_account(BANK, collateral, 1.0, 100.0, 0.0),
+      bank_account(Pubkey::new_from_array([2; 32]), debt, 1.0, 0.0, 50.0),
+    ]);
+
+    let (_, asset_weight, _) = account.maintenance_weights().next().unwrap();
+    asset_weight
+  }
+
+  #[test]
+  fn emode_entry_raises_the_collateral_weight() {
+    assert_eq!(emode_collateral_weight(5, 0.9), I80F48::from_num(0.9));
+  }
+
+  #[test]
+  fn collateral_without_an_emode_entry_keeps_the_bank_weight() {
+    assert_eq!(emode_collateral_weight(6, 0.9), I80F48::from_num(0.8));
+  }
+
+  #[test]
+  fn higher_bank_weight_wins_over_the_emode_entry() {
+    assert_eq!(emode_collateral_weight(5, 0.7), I80F48::from_num(0.8));
+  }
+
+  /// `collateral` tokens weighing 0.75 against 300 tokens borrowed weighing 1.25, all at $1
+  fn weighted_300_debt(collateral: f64) -> MarginfiUserAccount {
+    let mut collateral_bank = bank();
+    collateral_bank.config.asset_weight_maint = I80F48::from_num(0.75).into();
+    let mut debt_bank = bank();
+    debt_bank.config.liability_weight_maint = I80F48::from_num(1.25).into();
+    user_account(vec![
+      bank_account(BANK, collateral_bank, 1.0, collateral, 0.0),
+      bank_account(Pubkey::new_from_array([2; 32]), debt_bank, 1.0, 0.0, 300.0),
+    ])
+  }
+
+  #[test]
+  fn health_factor_of_one_is_not_liquidatable() {
+    // $375 of weighted collateral against $375 of weighted debt
+    let account = weighted_300_debt(500.0);
+
+    assert_eq!(account.health_factor(ValuationPolicy::PROTOCOL).unwrap(), I80F48::ONE);
+    assert!(!account.is_liquidatable(ValuationPolicy::PROTOCOL).unwrap());
+  }
+
+  #[test]
+  fn health_factor_just_below_one_is_liquidatable() {
+    let account = weighted_300_debt(499.99);
+
+    assert!(account.health_factor(ValuationPolicy::PROTOCOL).unwrap() < I80F48::ONE);
+    assert!(account.is_liquidatable(ValuationPolicy::PROTOCOL).unwrap());
+  }
+
+  #[test]
+  fn health_factor_just_above_one_is_not_liquidatable() {
+    let account = weighted_300_debt(500.01);
+
+    assert!(account.health_factor(ValuationPolicy::PROTOCOL).unwrap() > I80F48::ONE);
+    assert!(!account.is_liquidatable(ValuationPolicy::PROTOCOL).unwrap());
+  }
+
+  #[test]
+  fn health_factor_without_liabilities_is_max() {
+    let account = user_account(vec![bank_account(BANK, bank(), 1.0, 100.0, 0.0)]);
+
+    assert_eq!(account.health_factor(ValuationPolicy::PROTOCOL).unwrap(), I80F48::MAX);
+  }
+
+  #[tokio::test]
+  async fn banks_are_fetched_in_one_multi_get() {
+    let banks: Vec<(Pubkey, Bank)> = (1..=3u8)
+      .map(|i| {
+        let mut bank = bank();
+        bank.config.fixed_price = I80F48::ONE.into();
+        (Pubkey::new_from_array([i; 32]), bank)
+      })
+      .collect();
+    let account = user_account(banks.iter().map(|(pk, bank)| bank_account(*pk, *bank, 1.0, 10.0, 0.0)).collect());
+    let account_pk = Pubkey::new_from_array([9; 32]);
+    let bank_data: Vec<_> = banks
+      .iter()
+      .map(|(pk, bank)| ui_account(pk, &rpc_account(MARGINFI_PROGRAM_ID, zero_copy(bank))))
+      .collect();
+    // Unmocked requests fail, so a second multi-get would fail the load
+    let mocks: MocksMap = [
+      (RpcRequest::GetAccountInfo, response(ui_account(&account_pk, &rpc_account(MARGINFI_PROGRAM_ID, zero_copy(account.account()))))),
+      (RpcRequest::GetMultipleAccounts, response(serde_json::json!(bank_data))),
+      (RpcRequest::GetAccountInfo, clock_response(&Clock::default())),
+    ]
+    .into_iter()
+    .collect();
+    let rpc_client = RpcClient::new_mock_with_mocks_map("fails", mocks);
+
+    let loaded = MarginfiUserAccount::from_pubkey(&rpc_client, &account_pk).await.unwrap();
+    assert_eq!(loaded.bank_accounts().len(), 3);
+    assert_eq!(loaded.asset_value().unwrap(), I80F48::from_num(30));
+  }
+
+  #[tokio::test]
+  async fn bank_on_both_sides_is_fetched_once() {
+    let mut fixed = bank();
+    fixed.config.fixed_price = I80F48::ONE.into();
+    let (both, borrowed) = (Pubkey::new_from_array([1; 32]), Pubkey::new_from_array([2; 32]));
+    let account = user_account(vec![
+      bank_account(both, fixed, 1.0, 100.0, 10.0),
+      bank_account(borrowed, fixed, 1.0, 0.0, 20.0),
+    ]);
+    let account_pk = Pubkey::new_from_array([9; 32]);
+    let bank_data: Vec<_> = [both, borrowed]
+      .iter()
+      .map(|pk| ui_account(pk, &rpc_account(MARGINFI_PROGRAM_ID, zero_copy(&fixed))))
+      .collect();
+    // Unmocked requests fail, so fetching a bank again for the liability side would fail the load
+    let mocks: MocksMap = [
+      (RpcRequest::GetAccountInfo, response(ui_account(&account_pk, &rpc_account(MARGINFI_PROGRAM_ID, zero_copy(account.account()))))),
+      (RpcRequest::GetMultipleAccounts, response(serde_json::json!(bank_data))),
+      (RpcRequest::GetAccountInfo, clock_response(&Clock::default())),
+    ]
+    .into_iter()
+    .collect();
+    let rpc_client = RpcClient::new_mock_with_mocks_map("fails", mocks);
+
+    let loaded = MarginfiUserAccount::from_pubkey(&rpc_client, &account_pk).await.unwrap();
+    let snapshot = AccountSnapshot::new(&account_pk, &loaded, ValuationPolicy::PROTOCOL).unwrap();
+    let banks: Vec<_> = snapshot.positions.iter().map(|position| position.bank).collect();
+    assert_eq!(banks, vec![both, both, borrowed]);
+    assert_eq!(snapshot.asset_value, I80F48::from_num(100));
+    assert_eq!(snapshot.liability_value, I80F48::from_num(30));
+  }
+
+  #[test]
+  fn ema_and_real_time_prices_value_the_account_differently() {
+    // $10 spot against an $8 EMA, both exact
+    let mut collateral = bank_account(BANK, bank(), 10.0, 100.0, 0.0);
+    collateral.price_feed = OraclePriceFeedAdapter::PythPushOracle(
+      PythPushOraclePriceFeed::from_components(1_000, 0, 800, 0, -2),
+    );
+    let spot = SideValuation { price_type: OraclePriceType::RealTime, bias: None, strict_confidence: true };
+    let ema = SideValuation { price_type: OraclePriceType::TimeWeighted, ..spot };
+    assert_eq!(collateral.asset_value_with(spot).unwrap(), I80F48::from_num(1_000));
+    assert_eq!(collateral.asset_value_with(ema).unwrap(), I80F48::from_num(800));
+
+    let account = user_account(vec![collateral, bank_account(Pubkey::new_from_array([2; 32]), bank(), 1.0, 0.0, 300.0)]);
+    // 1000 or 800 weighted 0.8, less 300 weighted 1.2
+    assert_eq!(account.maintenance(ValuationPolicy::PROTOCOL).unwrap(), I80F48::from_num(440));
+    assert_eq!(account.maintenance(ValuationPolicy::CONSERVATIVE).unwrap(), I80F48::from_num(280));
+  }
+
+  #[test]
+  fn initial_health_never_exceeds_maintenance() {
+    let debt = Pubkey::new_from_array([2; 32]);
+    let accounts = [
+      user_account(vec![bank_account(BANK, bank(), 10.0, 100.0, 0.0)]),
+      user_account(vec![bank_account(BANK, bank(), 10.0, 100.0, 0.0), bank_account(debt, bank(), 1.0, 0.0, 300.0)]),
+      user_account(vec![bank_account(BANK, bank(), 10.0, 100.0, 0.0), bank_account(debt, bank(), 1.0, 0.0, 900.0)]),
+      user_account(vec![bank_account(BANK, bank(), 10.0, 100.0, 50.0)]),
+    ];
+
+    for account in &accounts {
+      for policy in [ValuationPolicy::PROTOCOL, ValuationPolicy::CONSERVATIVE, ValuationPolicy::AGGRESSIVE] {
+        let initial = account.initial_health(policy).unwrap();
+        let maintenance = account.maintenance(policy).unwrap();
+        assert!(initial <= maintenance, "{} > {}", initial, maintenance);
+      }
+    }
+  }
+
+  #[test]
+  fn values_near_the_i80f48_range_error_instead_of_panicking() {
+    let near_max = |price: f64, deposited: I80F48, borrowed: I80F48| {
+      let mut bank_account = bank_account(BANK, bank(), price, 0.0, 0.0);
+      bank_account.bank.mint_decimals = 0;
+      bank_account.balance.asset_shares = deposited.into();
+      bank_account.balance.liability_shares = borrowed.into();
+      user_account(vec![bank_account])
+    };
+    let accounts = [
+      // the deposit times its price overflows
+      near_max(1e6, I80F48::MAX / 2, I80F48::ZERO),
+      // the debt fits but its 1.2 maintenance weight overflows
+      near_max(1.0, I80F48::ZERO, I80F48::MAX / 10 * 9),
+    ];
+
+    for account in &accounts {
+      assert!(account.maintenance(ValuationPolicy::PROTOCOL).is_err());
+    }
+  }
+
+  /// An account depositing into a bank tagged `collateral_tag` and borrowing from one tagged
+  /// `debt_tag`
+  fn tagged_account(collateral_tag: u8, debt_tag: u8) -> MarginfiUserAccount {
+    let tagged = |asset_tag| {
+      let mut bank = bank();
+      bank.config.asset_tag = asset_tag;
+      bank
+    };
+    user_account(vec![
+      bank_account(BANK, tagged(collateral_tag), 10.0, 100.0, 0.0),
+      bank_account(Pubkey::new_from_array([2; 32]), tagged(debt_tag), 1.0, 0.0, 300.0),
+    ])
+  }
+
+  #[test]
+  fn staked_collateral_may_back_sol_debt() {
+    assert_eq!(tagged_account(ASSET_TAG_STAKED, ASSET_TAG_SOL).asset_tag_conflict(), None);
+  }
+
+  #[test]
+  fn staked_collateral_may_not_back_default_debt() {
+    assert_eq!(
+      tagged_account(ASSET_TAG_STAKED, ASSET_TAG_DEFAULT).asset_tag_conflict(),
+      Some((BANK, Pubkey::new_from_array([2; 32]))),
+    );
+  }
+
+  #[test]
+  fn wide_confidence_fails_strictly_but_is_priced_leniently() {
+    // $10 quoted within $2, far past the default 10% max confidence
+    let mut bank_account = bank_account(BANK, bank(), 10.0, 100.0, 0.0);
+    bank_account.price_feed = OraclePriceFeedAdapter::PythPushOracle(
+      PythPushOraclePriceFeed::from_components(1_000, 200, 1_000, 200, -2),
+    );
+
+    let err = bank_account.asset_value_with(ValuationPolicy::PROTOCOL.assets).unwrap_err();
+    match err.downcast_ref::<anchor_lang::error::Error>() {
+      Some(anchor_lang::error::Error::AnchorError(err)) => {
+        assert_eq!(MarginfiError::from(err.error_code_number), MarginfiError::OracleMaxConfidenceExceeded);
+      }
+      _ => panic!("expected an anchor error, got {:#}", err),
+    }
+    let lenient = bank_account.asset_value_with(ValuationPolicy::PROTOCOL.lenient().assets).unwrap();
+    assert!(lenient > I80F48::ZERO && lenient < I80F48::from_num(1_000));
+  }
+
+  #[test]
+  fn confidence_multiplier_scales_the_max_confidence() {
+    // $10 quoted within $0.2: an interval of 0.424 against the default max of $1
+    let narrow = PythPushOraclePriceFeed::from_components(1_000, 20, 1_000, 20, -2);
+    // $10 quoted within $2: an interval of 4.24
+    let wide = PythPushOraclePriceFeed::from_components(1_000, 200, 1_000, 200, -2);
+    let priced = |feed: &PythPushOraclePriceFeed, multiplier: f64| {
+      let mut bank_account = bank_account(BANK, bank(), 10.0, 100.0, 0.0);
+      bank_account.price_feed = OraclePriceFeedAdapter::PythPushOracle(feed.clone());
+      user_account(vec![bank_account])
+        .with_oracle_confidence_multiplier(I80F48::from_num(multiplier))
+        .bank_accounts()[0]
+        .asset_value_with(ValuationPolicy::PROTOCOL.assets)
+        .is_ok()
+    };
+
+    assert!(priced(&narrow, 1.0));
+    assert!(!priced(&narrow, 0.4));
+    assert!(!priced(&wide, 1.0));
+    assert!(priced(&wide, 5.0));
+  }
+
+  #[test]
+  fn sol_quoted_price_is_converted_to_usd_through_its_reference() {
+    let sol_bank = Pubkey::new_from_array([3; 32]);
+    let sol = UsdReference {
+      bank: sol_bank,
+      price_feed: OraclePriceFeedAdapter::Fixed(FixedPriceFeed { price: I80F48::from_num(150) }),
+      oracle_max_confidence: 0,
+    };
+    // 100 tokens worth 0.05 SOL each
+    let account = user_account(vec![bank_account(BANK, bank(), 0.05, 100.0, 0.0)])
+      .attach_usd_references(&[(BANK, sol_bank)], &[sol]);
+    let bank_account = &account.bank_accounts()[0];
+
+    let close = |actual: I80F48, expected: f64| (actual - I80F48::from_num(expected)).abs() < I80F48::from_num(1e-6);
+    let price = bank_account.usd_price_with(ValuationPolicy::PROTOCOL.assets).unwrap();
+    assert!(close(price, 7.5), "{}", price);
+    let value = bank_account.asset_value().unwrap();
+    assert!(close(value, 750.0), "{}", value);
+  }
+
+  #[test]
+  fn rejected_confidence_surfaces_its_numbers() {
+    // $10 quoted within $2: an interval of 2 * 2.12 against the default max of 10% of the price
+    let mut bank_account = bank_account(BANK, bank(), 10.0, 100.0, 0.0);
+    bank_account.price_feed = OraclePriceFeedAdapter::PythPushOracle(
+      PythPushOraclePriceFeed::from_components(1_000, 200, 1_000, 200, -2),
+    );
+
+    let err = bank_account.asset_value_with(ValuationPolicy::PROTOCOL.assets).unwrap_err();
+    let breach = err.downcast_ref::<ConfidenceBreach>().expect("the breach is attached to the error");
+    let close = |actual: I80F48, expected: f64| (actual - I80F48::from_num(expected)).abs() < I80F48::from_num(1e-6);
+    assert_eq!(breach.price, I80F48::from_num(10));
+    assert!(close(breach.conf_interval, 4.24), "{}", breach.conf_interval);
+    assert!(close(breach.max_conf, 1.0), "{}", breach.max_conf);
+    assert!(err.to_string().contains("exceeds the max of"), "{}", err);
+  }
+}
@@ -0,0 +1,74 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anchor_lang::prelude::Pubkey;
+use anyhow::Context;
+
+use super::tracked::{HealthTier, TrackedAccounts};
+
+/// A tracked account as persisted across restarts
+#[derive(serde::Serialize, serde::Deserialize, Debug, Clone, Copy)]
+pub struct WatchListEntry {
+  pub pubkey: Pubkey,
+  /// Tier at the last evaluation before saving
+  pub tier: HealthTier,
+}
+
+/// Writes the tracked accounts to `path` as JSON. The list is written next to it first and
+/// renamed over it, so a crash mid-write leaves the previous list intact.
+pub fn save_watch_list(path: &Path, tracked: &TrackedAccounts) -> anyhow::Result<()> {
+  let entries: Vec<WatchListEntry> = tracked
+    .by_urgency()
+    .iter()
+    .map(|account| WatchListEntry { pubkey: account.pubkey, tier: account.tier() })
+    .collect();
+  let json = serde_json::to_vec(&entries).context("failed to serialize watch list")?;
+
+  let mut tmp_path = path.as_os_str().to_owned();
+  tmp_path.push(".tmp");
+  fs::write(&tmp_path, json)
+    .with_context(|| format!("failed to write watch list {}", path.display()))?;
+  fs::rename(&tmp_path, path)
+    .with_context(|| format!("failed to replace watch list {}", path.display()))?;
+
+  anyhow::Ok(())
+}
+
+/// The watch list saved at `path`, most at-risk first. A missing or corrupt file yields an empty
+/// list, losing it only costs rediscovering the accounts.
+pub fn load_watch_list(path: &Path) -> Vec<WatchListEntry> {
+  let data = match fs::read(path) {
+    Ok(data) => data,
+    Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Vec::new(),
+    Err(err) => {
+      tracing::warn!(path = %path.display(), error = %err, "failed to read watch list, starting empty");
+      return Vec::new();
+    }
+  };
+  match serde_json::from_slice::<Vec<WatchListEntry>>(&data) {
+    Ok(mut entries) => {
+      entries.sort_by_key(|entry| entry.tier);
+      entries
+    }
+    Err(err) => {
+      tracing::warn!(path = %path.display(), error = %err, "watch list is corrupt, starting empty");
+      Vec::new()
+    }
+  }
+}
+
+/// Saves the watch list every `interval` in the background
+pub fn spawn_watch_list_saver(tracked: Arc<TrackedAccounts>, path: PathBuf, interval: Duration) {
+  tokio::spawn(async move {
+    let mut interval = tokio::time::interval(interval);
+    interval.tick().await;
+    loop {
+      interval.tick().await;
+      if let Err(err) = save_watch_list(&path, &tracked) {
+        tracing::warn!(error = %format!("{:#}", err), "failed to save watch list");
+      }
+    }
+  });
+}
@@ -0,0 +1,49 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Tracks the last sign of life (a notification or a successful RPC read) so a supervisor can
+/// restart a bot that stalled without erroring
+pub struct Watchdog {
+  started_at: Instant,
+  /// Milliseconds since `started_at`
+  last_activity_ms: AtomicU64,
+}
+
+impl Default for Watchdog {
+  fn default() -> Self {
+    Self {
+      started_at: Instant::now(),
+      last_activity_ms: AtomicU64::new(0),
+    }
+  }
+}
+
+impl Watchdog {
+  pub fn touch(&self) {
+    let elapsed = self.started_at.elapsed().as_millis() as u64;
+    self.last_activity_ms.fetch_max(elapsed, Ordering::Relaxed);
+  }
+
+  pub fn idle(&self) -> Duration {
+    let last_activity = Duration::from_millis(self.last_activity_ms.load(Ordering::Relaxed));
+    self.started_at.elapsed().saturating_sub(last_activity)
+  }
+
+  /// Exits the process with a non-zero code once idle for longer than `timeout`
+  pub fn spawn(self: &Arc<Self>, timeout: Duration) {
+    let watchdog = Arc::clone(self);
+    let check_interval = (timeout / 10).max(Duration::from_secs(1));
+    tokio::spawn(async move {
+      let mut interval = tokio::time::interval(check_interval);
+      loop {
+        interval.tick().await;
+        let idle = watchdog.idle();
+        if idle > timeout {
+          tracing::error!(idle = ?idle, timeout = ?timeout, "watchdog saw no activity, exiting");
+          std::process::exit(1);
+        }
+      }
+    });
+  }
+}
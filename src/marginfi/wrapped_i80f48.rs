@@ -33,10 +33,164 @@ impl From<WrappedI80F48> for I80F48 {
     }
 }
 
+/// Lossy, for metrics and display
+impl From<WrappedI80F48> for f64 {
+    fn from(w: WrappedI80F48) -> Self {
+        I80F48::from(w).to_num::<f64>()
+    }
+}
+
+/// Serialized as a decimal string, which round-trips every value exactly
+impl serde::Serialize for WrappedI80F48 {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> std::result::Result<S::Ok, S::Error> {
+        serializer.collect_str(&I80F48::from(*self))
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for WrappedI80F48 {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> std::result::Result<Self, D::Error> {
+        let value = String::deserialize(deserializer)?;
+        value
+            .parse::<I80F48>()
+            .map(Self::from)
+            .map_err(|e| serde::de::Error::custom(format!("invalid fixed point number \"{}\": {}", value, e)))
+    }
+}
+
 impl PartialEq for WrappedI80F48 {
     fn eq(&self, other: &Self) -> bool {
         self.value == other.value
     }
 }
 
-impl Eq for WrappedI80F48 {}
\ No newline at end of file
+impl Eq for WrappedI80F48 {}
+impl PartialOrd for WrappedI80F48 {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Both sides share the I80F48 scale, so comparing the raw bits orders the values. Converting
+/// to `I80F48` is the same 16 byte little endian load, `tests::compare_with_into` times
+/// both.
+impl Ord for WrappedI80F48 {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.bits().cmp(&other.bits())
+    }
+}
+
+impl WrappedI80F48 {
+    #[inline]
+    fn bits(&self) -> i128 {
+        i128::from_le_bytes(self.value)
+    }
+
+    /// A native amount in whole tokens of a mint with `decimals`. The decimal point of the
+    /// native amount's decimal string is shifted instead of dividing by `10^decimals`, which
+    /// would round the result to the nearest binary fraction.
+    pub fn to_ui_string(&self, decimals: u8) -> String {
+        let native = I80F48::from(*self).to_string();
+        let (sign, native) = match native.strip_prefix('-') {
+            Some(magnitude) => ("-", magnitude),
+            None => ("", native.as_str()),
+        };
+        let (int_digits, frac_digits) = native.split_once('.').unwrap_or((native, ""));
+
+        let decimals = decimals as usize;
+        let int_digits = format!("{:0>width$}", int_digits, width = decimals + 1);
+        let (int_part, shifted) = int_digits.split_at(int_digits.len() - decimals);
+        let frac_part = format!("{}{}", shifted, frac_digits);
+        let frac_part = frac_part.trim_end_matches('0');
+
+        if frac_part.is_empty() {
+            format!("{}{}", sign, int_part)
+        } else {
+            format!("{}{}.{}", sign, int_part, frac_part)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use pretty_assertions::assert_eq;
+
+    use super::*;
+
+    fn wrapped(value: &str) -> WrappedI80F48 {
+        value.parse::<I80F48>().unwrap().into()
+    }
+
+    #[test]
+    fn ui_string_shifts_the_decimal_point() {
+        assert_eq!(wrapped("123456789").to_ui_string(6), "123.456789");
+        assert_eq!(wrapped("1500000").to_ui_string(6), "1.5");
+        assert_eq!(wrapped("10").to_ui_string(0), "10");
+        assert_eq!(wrapped("0").to_ui_string(9), "0");
+    }
+
+    #[test]
+    fn ui_string_of_sub_unit_and_negative_amounts() {
+        assert_eq!(wrapped("5").to_ui_string(6), "0.000005");
+        assert_eq!(wrapped("1").to_ui_string(9), "0.000000001");
+        assert_eq!(wrapped("-1500000").to_ui_string(6), "-1.5");
+        assert_eq!(wrapped("-2.5").to_ui_string(2), "-0.025");
+    }
+
+    #[test]
+    fn serde_round_trips_negative_and_sub_cent_values() {
+        for value in ["-1.5", "0.001", "-0.0049", "0.000001", "123456789.123456789"] {
+            let value = wrapped(value);
+            let json = serde_json::to_string(&value).unwrap();
+            assert_eq!(json, format!("\"{}\"", I80F48::from(value)));
+            assert_eq!(serde_json::from_str::<WrappedI80F48>(&json).unwrap(), value);
+        }
+        for value in [I80F48::MIN, I80F48::MAX, I80F48::DELTA, -I80F48::DELTA] {
+            let json = serde_json::to_string(&WrappedI80F48::from(value)).unwrap();
+            assert_eq!(I80F48::from(serde_json::from_str::<WrappedI80F48>(&json).unwrap()), value);
+        }
+    }
+
+    #[test]
+    fn deserialize_rejects_non_numbers() {
+        assert!(serde_json::from_str::<WrappedI80F48>("\"1.2.3\"").is_err());
+        assert!(serde_json::from_str::<WrappedI80F48>("1.5").is_err());
+    }
+
+    /// Times `Balance::is_empty`'s share comparison over the balances of 10k full accounts, on the
+    /// raw bits and through `.into()`. Run with `cargo test --release -- --ignored compare_with_into`.
+    #[test]
+    #[ignore]
+    fn compare_with_into() {
+        use std::hint::black_box;
+        use std::time::Instant;
+
+        let mut seed = 0x2545_f491_4f6c_dd1d_u64;
+        let shares: Vec<WrappedI80F48> = (0..160_000)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 7;
+                seed ^= seed << 17;
+                // Mostly dust and empty balances, like the unused slots of real accounts
+                I80F48::from_bits((seed % 1_000_000_000) as i128 - 1_000).into()
+            })
+            .collect();
+        let threshold = I80F48::from_num(0.00001);
+
+        let start = Instant::now();
+        let into = black_box(&shares).iter().filter(|shares| I80F48::from(**shares) < threshold).count();
+        let into_elapsed = start.elapsed();
+        let start = Instant::now();
+        let wrapped_threshold = WrappedI80F48::from(threshold);
+        let bits = black_box(&shares).iter().filter(|shares| **shares < wrapped_threshold).count();
+        let bits_elapsed = start.elapsed();
+
+        assert_eq!(bits, into);
+        eprintln!("into: {:?}, bits: {:?}", into_elapsed, bits_elapsed);
+    }
+
+    #[test]
+    fn converts_to_f64() {
+        assert_eq!(f64::from(wrapped("-0.25")), -0.25);
+        assert_eq!(f64::from(wrapped("1234.5")), 1234.5);
+    }
+}
@@ -0,0 +1,68 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Exponential backoff between retries. Each delay doubles the previous one up to `max`, and is
+/// jittered down to between half and all of it so that restarted instances don't retry in lockstep.
+pub struct Backoff {
+  initial: Duration,
+  max: Duration,
+  current: Duration,
+}
+
+impl Backoff {
+  pub fn new(initial: Duration, max: Duration) -> Self {
+    let initial = initial.min(max);
+    Self {
+      initial,
+      max,
+      current: initial,
+    }
+  }
+
+  /// The delay before the next retry
+  pub fn next_delay(&mut self) -> Duration {
+    let delay = self.current;
+    self.current = self.current.saturating_mul(2).min(self.max);
+
+    let half = delay / 2;
+    let jitter_nanos = half.as_nanos() as u64;
+    if jitter_nanos == 0 {
+      return delay;
+    }
+    let seed = SystemTime::now()
+      .duration_since(UNIX_EPOCH)
+      .map(|now| now.subsec_nanos() as u64)
+      .unwrap_or(0);
+    half + Duration::from_nanos(seed % (jitter_nanos + 1))
+  }
+
+  /// Starts over from the initial delay, after a retry succeeded
+  pub fn reset(&mut self) {
+    self.current = self.initial;
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn delays_double_up_to_the_max() {
+    let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(2));
+
+    // Each delay is jittered down to between half and all of 500ms, 1s, 2s, 2s
+    for ceiling in [500, 1_000, 2_000, 2_000] {
+      let delay = backoff.next_delay();
+      assert!(delay >= Duration::from_millis(ceiling / 2) && delay <= Duration::from_millis(ceiling), "{:?}", delay);
+    }
+  }
+
+  #[test]
+  fn reset_starts_over_from_the_initial_delay() {
+    let mut backoff = Backoff::new(Duration::from_millis(500), Duration::from_secs(30));
+    backoff.next_delay();
+    backoff.next_delay();
+    backoff.reset();
+
+    assert!(backoff.next_delay() <= Duration::from_millis(500));
+  }
+}
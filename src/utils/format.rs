@@ -0,0 +1,59 @@
+use fixed::types::I80F48;
+
+/// Renders a usd value for logs, rounded half away from zero to cents with thousands separators,
+/// e.g. `-1,234,567.89$`. Structured output keeps the full precision value instead.
+pub fn format_usd(value: I80F48) -> String {
+  let cents = value
+    .unsigned_abs()
+    .saturating_mul_int(100)
+    .saturating_round()
+    .to_num::<u128>();
+  let sign = if value.is_negative() && cents > 0 { "-" } else { "" };
+
+  let digits = (cents / 100).to_string();
+  let mut whole = String::with_capacity(digits.len() + digits.len() / 3);
+  for (i, digit) in digits.chars().enumerate() {
+    if i > 0 && (digits.len() - i) % 3 == 0 {
+      whole.push(',');
+    }
+    whole.push(digit);
+  }
+
+  format!("{}{}.{:02}$", sign, whole, cents % 100)
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  #[test]
+  fn half_cents_round_away_from_zero() {
+    assert_eq!(format_usd(I80F48::from_num(0.125)), "0.13$");
+    assert_eq!(format_usd(I80F48::from_num(-0.125)), "-0.13$");
+    assert_eq!(format_usd(I80F48::from_num(2.375)), "2.38$");
+    assert_eq!(format_usd(I80F48::from_num(-2.375)), "-2.38$");
+  }
+
+  #[test]
+  fn rounding_up_carries_into_the_dollars() {
+    assert_eq!(format_usd(I80F48::from_num(999.995)), "1,000.00$");
+    assert_eq!(format_usd(I80F48::from_num(-999.995)), "-1,000.00$");
+  }
+
+  #[test]
+  fn thousands_are_separated() {
+    assert_eq!(format_usd(I80F48::from_num(999)), "999.00$");
+    assert_eq!(format_usd(I80F48::from_num(1_000)), "1,000.00$");
+    assert_eq!(format_usd(I80F48::from_num(-1_234_567.5)), "-1,234,567.50$");
+    assert_eq!(format_usd(I80F48::from_num(12_345_678_901u64)), "12,345,678,901.00$");
+  }
+
+  #[test]
+  fn zero_has_no_sign() {
+    assert_eq!(format_usd(I80F48::ZERO), "0.00$");
+    assert_eq!(format_usd(I80F48::from_num(-0.0)), "0.00$");
+    assert_eq!(format_usd(I80F48::from_num(-0.001)), "0.00$");
+  }
+}
@@ -0,0 +1,13 @@
+use tracing::Level;
+
+/// Installs a subscriber writing events at or above `max_level` to stderr, one line each with
+/// the fields of the spans they happen in, e.g.
+/// `WARN handle_account{account=...}: skipped reason="nothing borrowed"`
+pub fn init_logging(max_level: Level) -> anyhow::Result<()> {
+  tracing_subscriber::fmt()
+    .with_max_level(max_level)
+    .with_writer(std::io::stderr)
+    .with_target(false)
+    .try_init()
+    .map_err(|e| anyhow::anyhow!("failed to install the log subscriber: {}", e))
+}
@@ -1,3 +1,15 @@
+mod backoff;
+mod format;
+mod logging;
 mod parse_account;
+mod rate_limited_log;
+mod retry;
+mod rpc;
 
-pub use parse_account::*;
\ No newline at end of file
+pub use backoff::*;
+pub use format::*;
+pub use logging::*;
+pub use parse_account::*;
+pub use rate_limited_log::*;
+pub use retry::*;
+pub use rpc::*;
@@ -1,10 +1,70 @@
+use anchor_lang::{error::ErrorCode, Discriminator};
 use bytemuck::Pod;
 
-pub fn parse_account<T: Pod>(
+/// Reinterprets an account's data as `T`, after checking it starts with `T`'s discriminator
+pub fn parse_account<T: Pod + Discriminator>(
   data: &[u8],
 ) -> Result<T, Box<dyn std::error::Error + Send + Sync>> {
-  let marginfi_account = bytemuck::try_from_bytes::<T>(&data[8..])
+  let Some((discriminator, account_data)) = data.split_at_checked(8) else {
+    return Err(format!("account data too short: {} < 8", data.len()).into());
+  };
+  if discriminator != T::DISCRIMINATOR {
+    return Err(anchor_lang::error::Error::from(ErrorCode::AccountDidNotDeserialize).into());
+  }
+  let marginfi_account = bytemuck::try_from_bytes::<T>(account_data)
       .map_err(|e| format!("account data parse failed: {:?}", e))?;
   
   Ok(*marginfi_account)
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  /// An account type the size of its only field
+  #[repr(C)]
+  #[derive(Clone, Copy, Debug, PartialEq, bytemuck::Pod, bytemuck::Zeroable)]
+  struct Counter {
+    count: u64,
+  }
+
+  impl Discriminator for Counter {
+    const DISCRIMINATOR: &'static [u8] = &[1, 2, 3, 4, 5, 6, 7, 8];
+  }
+
+  #[test]
+  fn data_shorter_than_a_discriminator_errors() {
+    for len in [0, 7] {
+      let err = parse_account::<Counter>(&vec![0; len]).unwrap_err();
+      assert_eq!(err.to_string(), format!("account data too short: {} < 8", len));
+    }
+  }
+
+  #[test]
+  fn a_bare_discriminator_errors() {
+    let err = parse_account::<Counter>(Counter::DISCRIMINATOR).unwrap_err();
+    assert!(err.to_string().starts_with("account data parse failed"), "{}", err);
+  }
+
+  #[test]
+  fn matching_discriminator_parses_the_account() {
+    let data = [Counter::DISCRIMINATOR, &42u64.to_le_bytes()].concat();
+
+    assert_eq!(parse_account::<Counter>(&data).unwrap(), Counter { count: 42 });
+  }
+
+  #[test]
+  fn mismatching_discriminator_errors() {
+    let data = [&[8, 7, 6, 5, 4, 3, 2, 1][..], &42u64.to_le_bytes()].concat();
+
+    let err = parse_account::<Counter>(&data).unwrap_err();
+    match err.downcast_ref::<anchor_lang::error::Error>() {
+      Some(anchor_lang::error::Error::AnchorError(err)) => {
+        assert_eq!(err.error_code_number, ErrorCode::AccountDidNotDeserialize as u32);
+      }
+      _ => panic!("expected AccountDidNotDeserialize, got {}", err),
+    }
+  }
+}
@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::hash::Hash;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+struct Window {
+  started_at: Instant,
+  suppressed: u64,
+}
+
+/// Collapses repeated identical warnings. The first occurrence of a key within `window` is
+/// logged, repeats are only counted, and the count is summarized with the next occurrence after
+/// the window elapses.
+pub struct RateLimitedLog<K> {
+  window: Duration,
+  windows: Mutex<HashMap<K, Window>>,
+}
+
+impl<K: Eq + Hash> RateLimitedLog<K> {
+  pub fn new(window: Duration) -> Self {
+    Self {
+      window,
+      windows: Mutex::new(HashMap::new()),
+    }
+  }
+
+  pub fn log(&self, key: K, message: &str) {
+    let now = Instant::now();
+    let mut windows = self.windows.lock().unwrap();
+
+    match windows.get_mut(&key) {
+      Some(window) if now.duration_since(window.started_at) < self.window => {
+        window.suppressed += 1;
+      }
+      Some(window) => {
+        if window.suppressed > 0 {
+          tracing::warn!(
+            suppressed = window.suppressed,
+            window_secs = now.duration_since(window.started_at).as_secs(),
+            "{}",
+            message
+          );
+        } else {
+          tracing::warn!("{}", message);
+        }
+        *window = Window { started_at: now, suppressed: 0 };
+      }
+      None => {
+        tracing::warn!("{}", message);
+        windows.insert(key, Window { started_at: now, suppressed: 0 });
+      }
+    }
+  }
+}
@@ -0,0 +1,98 @@
+use std::future::Future;
+use std::time::Duration;
+
+use crate::marginfi::BotError;
+
+use super::Backoff;
+
+/// How often and how patiently `with_retry` retries
+#[derive(Clone, Copy, Debug)]
+pub struct RetryPolicy {
+  /// Attempts in total, 1 never retries
+  pub max_attempts: u32,
+  /// Delay before the first retry, doubled on each further one
+  pub initial_backoff: Duration,
+}
+
+/// Runs `f` until it succeeds, fails with an error that isn't transient (see
+/// `BotError::is_transient`), or `policy.max_attempts` are used up. Decoding and oracle failures
+/// are returned right away, retrying them would fail the same way.
+pub async fn with_retry<T, F, Fut>(policy: RetryPolicy, mut f: F) -> anyhow::Result<T>
+where
+  F: FnMut() -> Fut,
+  Fut: Future<Output = anyhow::Result<T>>,
+{
+  let mut backoff = Backoff::new(policy.initial_backoff, Duration::MAX);
+  let mut attempt = 1;
+  loop {
+    match f().await {
+      Ok(value) => return anyhow::Ok(value),
+      Err(err) if attempt < policy.max_attempts && BotError::of(&err).is_some_and(BotError::is_transient) => {
+        tokio::time::sleep(backoff.next_delay()).await;
+        attempt += 1;
+      }
+      Err(err) => return Err(err),
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::cell::Cell;
+
+  use pretty_assertions::assert_eq;
+
+  use super::*;
+
+  const POLICY: RetryPolicy = RetryPolicy { max_attempts: 3, initial_backoff: Duration::from_millis(1) };
+
+  /// A client whose first `failures` calls fail with `error`, counting every call
+  struct FlakyClient {
+    failures: u32,
+    calls: Cell<u32>,
+    error: fn() -> BotError,
+  }
+
+  impl FlakyClient {
+    fn new(failures: u32, error: fn() -> BotError) -> Self {
+      Self { failures, calls: Cell::new(0), error }
+    }
+
+    async fn call(&self) -> anyhow::Result<u32> {
+      self.calls.set(self.calls.get() + 1);
+      if self.calls.get() <= self.failures {
+        return Err((self.error)().into());
+      }
+      anyhow::Ok(self.calls.get())
+    }
+  }
+
+  fn timeout() -> BotError {
+    BotError::Rpc(anyhow::anyhow!("timed out"))
+  }
+
+  #[tokio::test]
+  async fn transient_failures_are_retried_until_success() {
+    let client = FlakyClient::new(2, timeout);
+
+    assert_eq!(with_retry(POLICY, || client.call()).await.unwrap(), 3);
+    assert_eq!(client.calls.get(), 3);
+  }
+
+  #[tokio::test]
+  async fn transient_failures_give_up_after_max_attempts() {
+    let client = FlakyClient::new(3, timeout);
+
+    let err = with_retry(POLICY, || client.call()).await.unwrap_err();
+    assert!(matches!(BotError::of(&err), Some(BotError::Rpc(_))));
+    assert_eq!(client.calls.get(), 3);
+  }
+
+  #[tokio::test]
+  async fn parse_failures_are_not_retried() {
+    let client = FlakyClient::new(1, || BotError::Parse(anyhow::anyhow!("invalid account data")));
+
+    assert!(with_retry(POLICY, || client.call()).await.is_err());
+    assert_eq!(client.calls.get(), 1);
+  }
+}
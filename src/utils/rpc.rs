@@ -0,0 +1,24 @@
+use anchor_lang::prelude::Pubkey;
+use solana_account::Account;
+use solana_rpc_client::nonblocking::rpc_client::RpcClient;
+
+use crate::marginfi::BotError;
+
+/// A slot number
+pub type Slot = u64;
+
+fn rpc_error(err: impl std::error::Error + Send + Sync + 'static, context: String) -> anyhow::Error {
+  BotError::Rpc(anyhow::Error::new(err).context(context)).into()
+}
+
+/// `RpcClient::get_account`, with failures classified as `BotError::Rpc`
+pub async fn fetch_account(client: &RpcClient, pubkey: &Pubkey) -> anyhow::Result<Account> {
+  client.get_account(pubkey).await
+    .map_err(|e| rpc_error(e, format!("failed to fetch account {}", pubkey)))
+}
+
+/// `RpcClient::get_multiple_accounts`, with failures classified as `BotError::Rpc`
+pub async fn fetch_multiple_accounts(client: &RpcClient, pubkeys: &[Pubkey]) -> anyhow::Result<Vec<Option<Account>>> {
+  client.get_multiple_accounts(pubkeys).await
+    .map_err(|e| rpc_error(e, "failed to fetch multiple accounts".to_string()))
+}